@@ -1,8 +1,8 @@
 // error handling
 use crate::TofndResult;
 use anyhow::anyhow;
-use tracing::debug;
 use tofn::{collections::TypedUsize, gg20::keygen::malicious::Behaviour as KeygenBehaviour};
+use tracing::debug;
 
 use clap::App;
 
@@ -31,28 +31,23 @@ pub fn get_behaviour_matches(app: App) -> TofndResult<Behaviours> {
             .value_of("behaviour")
             .ok_or_else(|| anyhow!("behaviour value"))?;
         let victim_str = matches
-        .value_of("victim")
-        .ok_or_else(|| anyhow!("victim value"))?;
-    
-    let victimp: Result<Vec<usize>, _> = victim_str
-        .split(',')
-        .map(|s| s.parse::<usize>())
-        .collect();
-    
-    victim = victimp.unwrap();
-    let faulty_str = matches
-    .value_of("faulty")
-    .ok_or_else(|| anyhow!("faulty value"))?;
+            .value_of("victim")
+            .ok_or_else(|| anyhow!("victim value"))?;
 
-let faultyp: Result<Vec<usize>, _> = faulty_str
-    .split(',')
-    .map(|s| s.parse::<usize>())
-    .collect();
+        let victimp: Result<Vec<usize>, _> =
+            victim_str.split(',').map(|s| s.parse::<usize>()).collect();
 
-faulty = faultyp.unwrap();
+        victim = victimp.unwrap();
+        let faulty_str = matches
+            .value_of("faulty")
+            .ok_or_else(|| anyhow!("faulty value"))?;
 
+        let faultyp: Result<Vec<usize>, _> =
+            faulty_str.split(',').map(|s| s.parse::<usize>()).collect();
+
+        faulty = faultyp.unwrap();
     }
-    debug!("{:?} {:?} ",victim,faulty);
+    debug!("{:?} {:?} ", victim, faulty);
     // TODO: parse keygen malicious types as well
     //  let keygen = KeygenBehaviour::R1BadCommit;
     let keygen = match_string_to_behaviour(behaviour, victim, faulty);
@@ -66,9 +61,9 @@ fn match_string_to_behaviour(
 ) -> KeygenBehaviour {
     use KeygenBehaviour::*;
     // let victim = TypedUsize::from_usize(victim);
-    let victim= _victim.iter().map(|&x| TypedUsize::from_usize(x)).collect();
-    let faulty= _faulty.iter().map(|&x| TypedUsize::from_usize(x)).collect();
-    
+    let victim = _victim.iter().map(|&x| TypedUsize::from_usize(x)).collect();
+    let faulty = _faulty.iter().map(|&x| TypedUsize::from_usize(x)).collect();
+
     // let faulty = TypedUsize::from_usize(faulty);
     // TODO: some of the behaviours do not demand a victim. In the future, more
     // will be added that potentially need different set of arguments.
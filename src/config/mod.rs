@@ -1,9 +1,11 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 
 use clap::{crate_version, App, Arg};
 
 // error handling
-use crate::{encrypted_sled::PasswordMethod, mnemonic::Cmd, TofndResult};
+use crate::{
+    encrypted_sled::PasswordMethod, kv_manager::StorageBackend, mnemonic::Cmd, TofndResult,
+};
 use anyhow::anyhow;
 
 // TODO: move these into constants.rs
@@ -12,7 +14,18 @@ const TOFND_HOME_ENV_VAR: &str = "TOFND_HOME";
 const DEFAULT_MNEMONIC_CMD: &str = "existing";
 const DEFAULT_IP: &str = "127.0.0.1";
 const DEFAULT_PORT: u16 = 50051;
-const AVAILABLE_MNEMONIC_CMDS: &[&str] = &["existing", "create", "import", "export", "rotate"];
+const DEFAULT_PEER_PORT: u16 = 50052;
+const DEFAULT_ROUND_TIMEOUT_SECS: u64 = 60;
+const AVAILABLE_MNEMONIC_CMDS: &[&str] = &[
+    "existing",
+    "create",
+    "import",
+    "export",
+    "rotate",
+    "change-password",
+];
+const DEFAULT_STORAGE: &str = "sled";
+const AVAILABLE_STORAGES: &[&str] = &["sled", "memory", "log"];
 
 #[cfg(feature = "malicious")]
 mod malicious;
@@ -31,10 +44,15 @@ fn default_tofnd_dir() -> TofndResult<PathBuf> {
 pub struct Config {
     pub ip: String,
     pub port: u16,
+    pub peer_ip: String,
+    pub peer_port: u16,
     pub safe_keygen: bool,
     pub mnemonic_cmd: Cmd,
     pub tofnd_path: PathBuf,
     pub password_method: PasswordMethod,
+    pub storage: StorageBackend,
+    pub round_timeout: Duration,
+    pub peers: HashMap<String, String>, // peer addresses by party uid, for the direct transport
     #[cfg(feature = "malicious")]
     pub behaviours: Behaviours,
 }
@@ -43,6 +61,8 @@ pub fn parse_args() -> TofndResult<Config> {
     // need to use let to avoid dropping temporary value
     let ip = &DEFAULT_IP.to_string();
     let port = &DEFAULT_PORT.to_string();
+    let peer_port = &DEFAULT_PEER_PORT.to_string();
+    let round_timeout = &DEFAULT_ROUND_TIMEOUT_SECS.to_string();
     let default_dir = default_tofnd_dir()?;
     let default_dir = default_dir
         .to_str()
@@ -65,6 +85,20 @@ pub fn parse_args() -> TofndResult<Config> {
                 .required(false)
                 .default_value(port),
         )
+        .arg(
+            Arg::new("peer-ip")
+                .help("Address of the Peer gRPC service of the direct transport. It is served separately from the GG20 service, so that peers can't reach the GG20 service.")
+                .long("peer-address")
+                .required(false)
+                .default_value(ip),
+        )
+        .arg(
+            Arg::new("peer-port")
+                .help("Port of the Peer gRPC service of the direct transport.")
+                .long("peer-port")
+                .required(false)
+                .default_value(peer_port),
+        )
         .arg(
             // TODO: change to something like `--unsafe-primes`
             Arg::new("unsafe")
@@ -94,6 +128,29 @@ pub fn parse_args() -> TofndResult<Config> {
                 .default_value(DEFAULT_MNEMONIC_CMD)
                 .possible_values(AVAILABLE_MNEMONIC_CMDS),
         )
+        .arg(
+            Arg::new("storage")
+                .help("Storage backend of the kv store. `sled` and `log` store encrypted data on disk; `memory` keeps data in memory only and is intended for tests.")
+                .long("storage")
+                .required(false)
+                .default_value(DEFAULT_STORAGE)
+                .possible_values(AVAILABLE_STORAGES),
+        )
+        .arg(
+            Arg::new("round-timeout")
+                .help("Default deadline of each protocol round in seconds. Used if a protocol's init message does not specify one.")
+                .long("round-timeout")
+                .required(false)
+                .default_value(round_timeout),
+        )
+        .arg(
+            Arg::new("peer")
+                .help("Peer address of a party as <uid>=<address>, e.g. alice=127.0.0.1:50052. Can be repeated. If set, protocol traffic is sent directly to the peers instead of being relayed by the client.")
+                .long("peer")
+                .required(false)
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
         .arg(
             Arg::new("directory")
                 .long("directory")
@@ -129,6 +186,19 @@ pub fn parse_args() -> TofndResult<Config> {
         .value_of("port")
         .ok_or_else(|| anyhow!("port value"))?
         .parse::<u16>()?;
+    let peer_ip = matches
+        .value_of("peer-ip")
+        .ok_or_else(|| anyhow!("peer-ip value"))?
+        .to_string();
+    let peer_port = matches
+        .value_of("peer-port")
+        .ok_or_else(|| anyhow!("peer-port value"))?
+        .parse::<u16>()?;
+    if (ip.as_str(), port) == (peer_ip.as_str(), peer_port) {
+        return Err(anyhow!(
+            "the peer service can't listen on the address of the gRPC service"
+        ));
+    }
     let safe_keygen = !matches.is_present("unsafe");
     let mnemonic_cmd = matches
         .value_of("mnemonic")
@@ -139,6 +209,27 @@ pub fn parse_args() -> TofndResult<Config> {
         .value_of("directory")
         .ok_or_else(|| anyhow!("directory value"))?
         .into();
+    let storage = match matches
+        .value_of("storage")
+        .ok_or_else(|| anyhow!("storage value"))?
+    {
+        "sled" => StorageBackend::Sled,
+        "memory" => StorageBackend::Memory,
+        "log" => StorageBackend::Log,
+        storage => return Err(anyhow!("unknown storage {}", storage)),
+    };
+    let round_timeout = Duration::from_secs(
+        matches
+            .value_of("round-timeout")
+            .ok_or_else(|| anyhow!("round-timeout value"))?
+            .parse::<u64>()?,
+    );
+    let peers = matches
+        .values_of("peer")
+        .into_iter()
+        .flatten()
+        .map(parse_peer)
+        .collect::<TofndResult<HashMap<_, _>>>()?;
     let password_method = match matches.is_present("no-password") {
         true => PasswordMethod::NoPassword,
         false => PasswordMethod::Prompt,
@@ -147,11 +238,26 @@ pub fn parse_args() -> TofndResult<Config> {
     Ok(Config {
         ip,
         port,
+        peer_ip,
+        peer_port,
         safe_keygen,
         mnemonic_cmd,
         tofnd_path,
         password_method,
+        storage,
+        round_timeout,
+        peers,
         #[cfg(feature = "malicious")]
         behaviours,
     })
 }
+
+// parse a peer of the form <uid>=<address>
+fn parse_peer(peer: &str) -> TofndResult<(String, String)> {
+    match peer.split_once('=') {
+        Some((uid, address)) if !uid.is_empty() && !address.is_empty() => {
+            Ok((uid.to_owned(), address.to_owned()))
+        }
+        _ => Err(anyhow!("invalid peer {:?}; expected <uid>=<address>", peer)),
+    }
+}
@@ -0,0 +1,121 @@
+//! Encryption of values with [XChaCha20Poly1305].
+//! The cipher key is derived from a [Password] and a [PasswordSalt] with [scrypt].
+//! A new random nonce is created every time a value is encrypted, forming an [EncryptedRecord]:<encrypted value, nonce>.
+//!
+//! For envelope encryption, values are encrypted under a random [DataKey] instead, and the [DataKey] is stored
+//! wrapped, i.e. encrypted under the key that is derived from the password.
+
+use chacha20poly1305::aead::{AeadInPlace, NewAead};
+use chacha20poly1305::{self, XChaCha20Poly1305};
+use rand::RngCore;
+
+use zeroize::Zeroize;
+
+use std::convert::TryInto;
+
+use super::password::{Password, PasswordSalt};
+use super::record::EncryptedRecord;
+use super::result::{EncryptedDbError::*, EncryptedDbResult};
+
+/// A random key that encrypts the values of a kv store.
+/// Changing the password only needs to re-wrap the [DataKey], instead of re-encrypting every value.
+#[derive(Zeroize)]
+#[zeroize(drop)]
+pub(super) struct DataKey([u8; 32]);
+
+impl DataKey {
+    /// Create a new random [DataKey] using [rand::thread_rng]
+    pub(super) fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+}
+
+/// Encrypts values into serialized [EncryptedRecord]s and decrypts them back.
+pub struct RecordCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl RecordCipher {
+    /// Create a new [RecordCipher] whose key is derived from `password` and `salt`.
+    pub fn new(password: Password, salt: PasswordSalt) -> EncryptedDbResult<Self> {
+        // zeroize key since we are no longer using it after creating cipher
+        let mut key = Self::chacha20poly1305_kdf(password, salt)?;
+        let cipher = XChaCha20Poly1305::new(&key);
+        key.zeroize();
+
+        Ok(Self { cipher })
+    }
+
+    /// Create a new [RecordCipher] whose key is `data_key`.
+    pub(super) fn from_data_key(data_key: &DataKey) -> Self {
+        Self {
+            cipher: XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&data_key.0)),
+        }
+    }
+
+    /// Encrypt `data_key`, and return the bytes of the [EncryptedRecord]
+    pub(super) fn wrap_key(&self, data_key: &DataKey) -> EncryptedDbResult<Vec<u8>> {
+        self.encrypt(&data_key.0)
+    }
+
+    /// Decrypt the [DataKey] of the [EncryptedRecord] with bytes `record_bytes`
+    pub(super) fn unwrap_key(&self, record_bytes: &[u8]) -> EncryptedDbResult<DataKey> {
+        let mut bytes = self.decrypt(record_bytes)?;
+        let data_key = bytes.as_slice().try_into().map(DataKey);
+        bytes.zeroize();
+        data_key.map_err(|_| MalformedDataKey)
+    }
+
+    fn chacha20poly1305_kdf(
+        password: Password,
+        salt: PasswordSalt,
+    ) -> EncryptedDbResult<chacha20poly1305::Key> {
+        let mut output = chacha20poly1305::Key::default();
+
+        // default params: log_n = 15, r = 8, p = 1
+        scrypt::scrypt(
+            password.as_ref(),
+            salt.as_ref(),
+            &scrypt::Params::default(),
+            output.as_mut_slice(),
+        )?;
+
+        Ok(output)
+    }
+
+    /// get a new random nonce to use for value encryption using [rand::thread_rng]
+    fn generate_nonce() -> chacha20poly1305::XNonce {
+        let mut bytes = chacha20poly1305::XNonce::default();
+        rand::thread_rng().fill_bytes(bytes.as_mut_slice());
+        bytes
+    }
+
+    /// Encrypt `value` with a newly derived random nonce, and return the bytes of the [EncryptedRecord]
+    pub fn encrypt(&self, value: &[u8]) -> EncryptedDbResult<Vec<u8>> {
+        let nonce = Self::generate_nonce();
+
+        let mut value = value.to_vec();
+
+        // encrypt value
+        self.cipher
+            .encrypt_in_place(&nonce, b"", &mut value)
+            .map_err(|e| Encryption(e.to_string()))?;
+
+        EncryptedRecord::new(value, nonce).to_bytes()
+    }
+
+    /// Decrypt the value of the [EncryptedRecord] with bytes `record_bytes`
+    pub fn decrypt(&self, record_bytes: &[u8]) -> EncryptedDbResult<Vec<u8>> {
+        let record = EncryptedRecord::from_bytes(record_bytes)?;
+        let (mut value, nonce) = record.into();
+
+        // decrypt value
+        self.cipher
+            .decrypt_in_place(&nonce, b"", &mut value)
+            .map_err(|e| Decryption(e.to_string()))?;
+
+        Ok(value)
+    }
+}
@@ -2,4 +2,7 @@
 pub(super) const PASSWORD_VERIFICATION_KEY: &str = "verification_key";
 pub(super) const PASSWORD_VERIFICATION_VALUE: &str = "verification_value";
 pub(super) const PASSWORD_SALT_KEY: &[u8] = b"password_salt_key";
+pub(super) const DATA_KEY_KEY: &[u8] = b"data_key_key";
 pub(super) const UNSAFE_PASSWORD: &str = "tofnd_unsafe_password";
+pub(super) const REWRITE_EXTENSION: &str = "rewrite";
+pub(super) const REPLACED_EXTENSION: &str = "replaced";
@@ -1,27 +1,37 @@
-//! Wrap [sled] with [chacha20poly1305] encryption. An [XChaCha20Entropy] is
-//! used as [XChaCha20Poly1305] cipher key to create an [EncryptedDb].
+//! Wrap [sled] with [chacha20poly1305] encryption. A [RecordCipher] whose key is a random [DataKey]
+//! is used to encrypt the values of an [EncryptedDb]. The [DataKey] is stored wrapped under a key
+//! that is derived from the password, so that [EncryptedDb::change_password] only needs to re-wrap it.
+//! Kv stores that were created before envelope encryption have their values encrypted directly
+//! under the password key; they are re-encrypted under a new [DataKey] on the first password change.
 //! A new random [XChaCha20Nonce] is created every time a new value needs to be
 //! inserted, forming a [EncryptedRecord]:<encrypted value, nonce>. The nonce is later
 //! used to decrypt and retrieve the originally inserted value.
+//!
+//! [EncryptedDb::purge] rewrites the kv store into a new directory, which then replaces the old one,
+//! so that the purged record does not remain in sled's log segments. A rewrite that was interrupted is
+//! completed, or discarded, the next time the kv store is opened.
 
-use std::convert::TryInto;
+use std::{
+    convert::TryInto,
+    fs, io,
+    path::{Path, PathBuf},
+};
 
-use chacha20poly1305::aead::{AeadInPlace, NewAead};
-use chacha20poly1305::{self, XChaCha20Poly1305};
 use rand::RngCore;
 
 use sled::IVec;
-use zeroize::Zeroize;
 
+use super::cipher::{DataKey, RecordCipher};
 use super::constants::*;
 use super::password::{Password, PasswordSalt};
-use super::record::EncryptedRecord;
 use super::result::{EncryptedDbError::*, EncryptedDbResult};
 
 /// A [sled] kv store with [XChaCha20Poly1305] value encryption.
 pub struct EncryptedDb {
     kv: sled::Db,
-    cipher: XChaCha20Poly1305,
+    path: PathBuf,
+    cipher: RecordCipher,
+    data_key: Option<DataKey>, // `None` for kv stores without envelope encryption
 }
 
 impl EncryptedDb {
@@ -33,7 +43,9 @@ impl EncryptedDb {
     where
         P: AsRef<std::path::Path>,
     {
-        let kv = sled::open(db_name).map_err(CorruptedKv)?;
+        let path = db_name.as_ref().to_owned();
+        finish_rewrite(&path).map_err(Rewrite)?;
+        let kv = sled::open(&path).map_err(CorruptedKv)?;
 
         let password_salt: PasswordSalt = if kv.was_recovered() {
             // existing kv: get the existing password salt
@@ -48,12 +60,36 @@ impl EncryptedDb {
             password_salt.into()
         };
 
-        // zeroize key since we are no longer using it after creating cipher
-        let mut key = Self::chacha20poly1305_kdf(password, password_salt)?;
-        let cipher = XChaCha20Poly1305::new(&key);
-        key.zeroize();
+        let password_cipher = RecordCipher::new(password, password_salt)?;
 
-        let encrypted_db = EncryptedDb { kv, cipher };
+        let data_key = if kv.was_recovered() {
+            // existing kv: unwrap the existing data key, if any
+            match kv.get(DATA_KEY_KEY)? {
+                Some(wrapped_key) => Some(
+                    password_cipher
+                        .unwrap_key(&wrapped_key)
+                        .map_err(|_| WrongPassword)?,
+                ),
+                None => None,
+            }
+        } else {
+            // new kv: choose a new data key and store it wrapped
+            let data_key = DataKey::generate();
+            kv.insert(DATA_KEY_KEY, password_cipher.wrap_key(&data_key)?)?;
+            Some(data_key)
+        };
+
+        let cipher = match &data_key {
+            Some(data_key) => RecordCipher::from_data_key(data_key),
+            None => password_cipher,
+        };
+
+        let encrypted_db = EncryptedDb {
+            kv,
+            path,
+            cipher,
+            data_key,
+        };
 
         // verify that [password] is correct
         if encrypted_db.kv.was_recovered() {
@@ -69,69 +105,67 @@ impl EncryptedDb {
         Ok(encrypted_db)
     }
 
-    fn chacha20poly1305_kdf(
-        password: Password,
-        salt: PasswordSalt,
-    ) -> EncryptedDbResult<chacha20poly1305::Key> {
-        let mut output = chacha20poly1305::Key::default();
+    /// Change the password of the kv store to `password`, using a new password salt.
+    /// The caller must have opened the kv store with the old password, which verifies it.
+    /// If the kv store has a [DataKey], it is re-wrapped under the new password key.
+    /// Otherwise, a new [DataKey] is created and all values are re-encrypted under it.
+    /// All changes are applied in a single atomic batch and flushed to disk, so that a crash
+    /// leaves the kv store either with the old or with the new password.
+    pub fn change_password(&mut self, password: Password) -> EncryptedDbResult<()> {
+        let mut password_salt = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut password_salt);
+        let password_cipher = RecordCipher::new(password, password_salt.into())?;
 
-        // default params: log_n = 15, r = 8, p = 1
-        scrypt::scrypt(
-            password.as_ref(),
-            salt.as_ref(),
-            &scrypt::Params::default(),
-            output.as_mut_slice(),
-        )?;
+        let mut batch = sled::Batch::default();
+        batch.insert(PASSWORD_SALT_KEY, &password_salt[..]);
 
-        Ok(output)
-    }
+        let new_data_key = match &self.data_key {
+            // re-wrap the existing data key
+            Some(data_key) => {
+                batch.insert(DATA_KEY_KEY, password_cipher.wrap_key(data_key)?);
+                None
+            }
+            // kv store without a data key: re-encrypt all values under a new one, including the verification value
+            None => {
+                let data_key = DataKey::generate();
+                let cipher = RecordCipher::from_data_key(&data_key);
+                for res in self.kv.iter() {
+                    let (key, record_bytes) = res?;
+                    let key: &[u8] = key.as_ref();
+                    if key == PASSWORD_SALT_KEY {
+                        continue;
+                    }
+                    let value = self.cipher.decrypt(&record_bytes)?;
+                    batch.insert(key, cipher.encrypt(&value)?);
+                }
+                batch.insert(DATA_KEY_KEY, password_cipher.wrap_key(&data_key)?);
+                Some((data_key, cipher))
+            }
+        };
+
+        self.kv.apply_batch(batch)?;
+        self.kv.flush()?;
 
-    /// get a new random nonce to use for value encryption using [rand::thread_rng]
-    fn generate_nonce() -> chacha20poly1305::XNonce {
-        let mut bytes = chacha20poly1305::XNonce::default();
-        rand::thread_rng().fill_bytes(bytes.as_mut_slice());
-        bytes
+        // use the new data key only after the batch is applied
+        if let Some((data_key, cipher)) = new_data_key {
+            self.cipher = cipher;
+            self.data_key = Some(data_key);
+        }
+        Ok(())
     }
 
     /// create a new [EncryptedRecord] containing an encrypted value and a newly derived random nonce
-    fn encrypt<V>(&self, value: V) -> EncryptedDbResult<EncryptedRecord>
+    fn encrypt<V>(&self, value: V) -> EncryptedDbResult<Vec<u8>>
     where
         V: Into<IVec>,
     {
-        let nonce = Self::generate_nonce();
-
-        let mut value = value.into().to_vec();
-
-        // encrypt value
-        self.cipher
-            .encrypt_in_place(&nonce, b"", &mut value)
-            .map_err(|e| Encryption(e.to_string()))?;
-
-        // return record
-        Ok(EncryptedRecord::new(value, nonce))
-    }
-
-    /// derive a decrypted value from a [EncryptedRecord] containing an encrypted value and a random nonce
-    fn decrypt_record_value(&self, record: EncryptedRecord) -> EncryptedDbResult<IVec> {
-        let (mut value, nonce) = record.into();
-
-        // decrypt value
-        self.cipher
-            .decrypt_in_place(&nonce, b"", &mut value)
-            .map_err(|e| Decryption(e.to_string()))?;
-
-        // return decrypted value
-        Ok(value.into())
+        self.cipher.encrypt(&value.into())
     }
 
     /// derive a decrypted value from [EncryptedRecord] bytes
     fn decrypt(&self, record_bytes: Option<IVec>) -> EncryptedDbResult<Option<IVec>> {
         let res = match record_bytes {
-            Some(record_bytes) => {
-                let record = EncryptedRecord::from_bytes(&record_bytes)?;
-                let decrypted_value_bytes = self.decrypt_record_value(record)?;
-                Some(decrypted_value_bytes)
-            }
+            Some(record_bytes) => Some(self.cipher.decrypt(&record_bytes)?.into()),
             None => None,
         };
         Ok(res)
@@ -143,8 +177,8 @@ impl EncryptedDb {
         K: AsRef<[u8]>,
         V: Into<IVec>,
     {
-        let record = self.encrypt(value)?;
-        let prev_record_bytes_opt = self.kv.insert(&key, record.to_bytes()?)?;
+        let record_bytes = self.encrypt(value)?;
+        let prev_record_bytes_opt = self.kv.insert(&key, record_bytes)?;
         self.decrypt(prev_record_bytes_opt)
     }
 
@@ -174,13 +208,139 @@ impl EncryptedDb {
         self.decrypt(prev_val)
     }
 
+    /// Atomically insert the encrypted `inserts` and delete the `removes`.
+    /// Either all changes are applied or none of them is.
+    pub fn apply_batch<K, V>(&self, inserts: Vec<(K, V)>, removes: Vec<K>) -> EncryptedDbResult<()>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        let mut batch = sled::Batch::default();
+        for (key, value) in inserts {
+            batch.insert(key.as_ref(), self.encrypt(value)?);
+        }
+        for key in removes {
+            batch.remove(key.as_ref());
+        }
+        Ok(self.kv.apply_batch(batch)?)
+    }
+
+    /// Delete a value and rewrite the kv store, so that its encrypted record can't be recovered from disk.
+    /// The record is overwritten before it is removed, and all other records are copied to a new kv store, which replaces the old one.
+    pub fn purge<K>(&mut self, key: K) -> EncryptedDbResult<()>
+    where
+        K: AsRef<[u8]>,
+    {
+        if let Some(record_bytes) = self.kv.get(&key)? {
+            self.kv.insert(&key, vec![0; record_bytes.len()])?;
+            self.kv.flush()?;
+            self.kv.remove(&key)?;
+            self.kv.flush()?;
+        }
+        self.rewrite()
+    }
+
+    /// Copy all records to a new kv store next to the kv store, and atomically replace the kv store with it.
+    fn rewrite(&mut self) -> EncryptedDbResult<()> {
+        let (new_path, _) = rewrite_paths(&self.path);
+
+        // discard any leftover of an interrupted rewrite
+        if new_path.exists() {
+            fs::remove_dir_all(&new_path).map_err(Rewrite)?;
+        }
+        let new_kv = sled::open(&new_path).map_err(CorruptedKv)?;
+        for res in self.kv.iter() {
+            let (key, record_bytes) = res?;
+            new_kv.insert(key, record_bytes)?;
+        }
+        new_kv.flush()?;
+        drop(new_kv);
+
+        // sled locks its directory, so the kv store is closed while the directories are swapped
+        let placeholder = sled::Config::new().temporary(true).open()?;
+        drop(std::mem::replace(&mut self.kv, placeholder));
+        let swapped = swap_rewrite(&self.path);
+        self.kv = sled::open(&self.path).map_err(CorruptedKv)?;
+        swapped.map_err(Rewrite)
+    }
+
+    /// Iterate over all keys that start with `prefix`, decrypting their values.
+    /// The password salt, data key and verification keys are not returned.
+    pub fn scan_prefix<P>(
+        &self,
+        prefix: P,
+    ) -> impl DoubleEndedIterator<Item = EncryptedDbResult<(IVec, IVec)>> + '_
+    where
+        P: AsRef<[u8]>,
+    {
+        self.kv
+            .scan_prefix(prefix)
+            .filter(|res| match res {
+                Ok((key, _)) => {
+                    let key: &[u8] = key.as_ref();
+                    key != PASSWORD_SALT_KEY
+                        && key != DATA_KEY_KEY
+                        && key != PASSWORD_VERIFICATION_KEY.as_bytes()
+                }
+                Err(_) => true,
+            })
+            .map(move |res| {
+                let (key, record_bytes) = res?;
+                Ok((key, self.cipher.decrypt(&record_bytes)?.into()))
+            })
+    }
+
     /// Returns true if the database was recovered from a previous process.
     pub fn was_recovered(&self) -> bool {
         self.kv.was_recovered()
     }
 
-    #[cfg(test)]
+    /// Synchronously flushes all dirty IO buffers to disk.
     pub fn flush(&self) -> EncryptedDbResult<usize> {
         Ok(self.kv.flush()?)
     }
 }
+
+/// paths of the new kv store of a rewrite of the kv store at `path`, and of the kv store that it replaces
+fn rewrite_paths(path: &Path) -> (PathBuf, PathBuf) {
+    (
+        path.with_extension(REWRITE_EXTENSION),
+        path.with_extension(REPLACED_EXTENSION),
+    )
+}
+
+/// Replace the kv store at `path` with its rewrite. If the rewrite can't be moved into place, the kv store is restored.
+fn swap_rewrite(path: &Path) -> io::Result<()> {
+    let (new_path, old_path) = rewrite_paths(path);
+    fs::rename(path, &old_path)?;
+    if let Err(err) = fs::rename(&new_path, path) {
+        fs::rename(&old_path, path)?;
+        return Err(err);
+    }
+    if let Some(parent) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::File::open(parent)?.sync_all()?;
+    }
+    fs::remove_dir_all(&old_path)
+}
+
+/// Complete a rewrite of the kv store at `path` that was interrupted while the directories were swapped,
+/// and remove the leftovers of interrupted rewrites.
+fn finish_rewrite(path: &Path) -> io::Result<()> {
+    let (new_path, old_path) = rewrite_paths(path);
+    if !path.exists() && old_path.exists() {
+        // the kv store is only moved away once its rewrite is complete
+        let complete = if new_path.exists() {
+            &new_path
+        } else {
+            &old_path
+        };
+        fs::rename(complete, path)?;
+    }
+    if old_path.exists() {
+        fs::remove_dir_all(&old_path)?;
+    }
+    if new_path.exists() {
+        fs::remove_dir_all(&new_path)?;
+    }
+    Ok(())
+}
@@ -2,15 +2,17 @@
 //! Specifically, use [chacha20poly1305::XChaCha20] because the nonces are generated randomly.
 //! To create an new [Db], an [Entropy] needs to be provided.
 
+mod cipher;
 mod constants;
 mod kv;
 mod password;
 mod record;
 mod result;
 
+pub use cipher::RecordCipher;
 // match the API of sled
 pub use kv::EncryptedDb as Db;
-pub use password::{Password, PasswordMethod, PasswordSalt};
+pub use password::{read_new_password, Password, PasswordMethod, PasswordSalt};
 pub use result::EncryptedDbError as Error;
 pub use result::EncryptedDbResult as Result;
 
@@ -1,7 +1,10 @@
 //! Handles the generation of an [Entropy] from user's password using [scrypt] pbkdf.
 use std::convert::{TryFrom, TryInto};
 
-use super::{constants::UNSAFE_PASSWORD, result::EncryptedDbResult};
+use super::{
+    constants::UNSAFE_PASSWORD,
+    result::{EncryptedDbError::PasswordMismatch, EncryptedDbResult},
+};
 
 use sled::IVec;
 use zeroize::Zeroize;
@@ -18,6 +21,13 @@ impl AsRef<[u8]> for Password {
     }
 }
 
+/// Don't leak passwords in logs
+impl std::fmt::Debug for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Password(<redacted>)")
+    }
+}
+
 pub struct PasswordSalt([u8; 32]);
 
 impl AsRef<[u8]> for PasswordSalt {
@@ -61,6 +71,18 @@ impl PasswordMethod {
     }
 }
 
+/// Prompt for a new password twice, and return it if both entries match
+pub fn read_new_password() -> EncryptedDbResult<Password> {
+    println!("Please type your new tofnd password:");
+    let password = Password(read_password()?);
+    println!("Please type your new tofnd password again:");
+    let confirmation = Password(read_password()?);
+    if password.as_ref() != confirmation.as_ref() {
+        return Err(PasswordMismatch);
+    }
+    Ok(password)
+}
+
 #[cfg(test)]
 impl From<&str> for Password {
     fn from(value: &str) -> Self {
@@ -2,7 +2,6 @@
 
 use chacha20poly1305::XNonce;
 use serde::{Deserialize, Serialize};
-use sled::IVec;
 
 use tofn::sdk::api::{deserialize, serialize};
 
@@ -32,7 +31,7 @@ impl EncryptedRecord {
     }
 
     /// Convert bytes to a [EncryptedRecord] using serde.
-    pub(super) fn from_bytes(bytes: &IVec) -> EncryptedDbResult<EncryptedRecord> {
+    pub(super) fn from_bytes(bytes: &[u8]) -> EncryptedDbResult<EncryptedRecord> {
         deserialize(bytes).ok_or(Deserialization)
     }
 }
@@ -26,5 +26,11 @@ pub enum EncryptedDbError {
     MissingPasswordSalt,
     #[error("Malformed password salt: {0}")]
     MalformedPasswordSalt(#[from] std::array::TryFromSliceError),
+    #[error("Malformed data key")]
+    MalformedDataKey,
+    #[error("Passwords do not match")]
+    PasswordMismatch,
+    #[error("Kv store rewrite error: {0}")]
+    Rewrite(std::io::Error),
 }
 pub type EncryptedDbResult<Success> = Result<Success, EncryptedDbError>;
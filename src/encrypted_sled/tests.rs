@@ -1,4 +1,5 @@
 use super::{kv::EncryptedDb, Password};
+use std::{fs, path::Path};
 use testdir::testdir;
 
 #[test]
@@ -43,6 +44,37 @@ fn test_encrypted_sled() {
     assert_eq!(res, None);
 }
 
+#[test]
+fn test_scan_prefix() {
+    let db_path = testdir!("encrypted_db");
+    let db = EncryptedDb::open(&db_path, get_test_password()).unwrap();
+
+    db.insert("key1", "value1").unwrap();
+    db.insert("key2", "value2").unwrap();
+    db.insert("other", "value3").unwrap();
+
+    // scan <key> -> returns decrypted values of key1 and key2 in order
+    let res: Vec<_> = db.scan_prefix("key").map(|res| res.unwrap()).collect();
+    assert_eq!(
+        res,
+        vec![
+            (sled::IVec::from("key1"), sled::IVec::from("value1")),
+            (sled::IVec::from("key2"), sled::IVec::from("value2")),
+        ]
+    );
+
+    // scan all -> password salt and verification keys are skipped
+    let keys: Vec<_> = db.scan_prefix("").map(|res| res.unwrap().0).collect();
+    assert_eq!(
+        keys,
+        vec![
+            sled::IVec::from("key1"),
+            sled::IVec::from("key2"),
+            sled::IVec::from("other"),
+        ]
+    );
+}
+
 #[test]
 fn test_use_existing_salt() {
     let db_path = testdir!("encrypted_db");
@@ -85,6 +117,129 @@ fn test_large_input() {
     assert_eq!(res, Some(sled::IVec::from(large_value)));
 }
 
+/// whether any file in the directory `path` contains `bytes`
+fn on_disk(path: &Path, bytes: &[u8]) -> bool {
+    fs::read_dir(path).unwrap().any(|entry| {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            return on_disk(&path, bytes);
+        }
+        fs::read(&path)
+            .unwrap()
+            .windows(bytes.len())
+            .any(|window| window == bytes)
+    })
+}
+
+#[test]
+fn test_purge() {
+    let db_path = testdir!("purge").join("kv");
+    let db = EncryptedDb::open(&db_path, get_test_password()).unwrap();
+    db.insert("key", "value").unwrap();
+    db.insert("other", "other value").unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    // the encrypted record of the key is on disk
+    let kv = sled::open(&db_path).unwrap();
+    let record = kv.get("key").unwrap().unwrap();
+    drop(kv);
+    assert!(on_disk(&db_path, &record));
+
+    // purge erases it
+    let mut db = EncryptedDb::open(&db_path, get_test_password()).unwrap();
+    db.purge("key").unwrap();
+    assert_eq!(db.get("key").unwrap(), None);
+    assert_eq!(
+        db.get("other").unwrap(),
+        Some(sled::IVec::from("other value"))
+    );
+    drop(db);
+    assert!(!on_disk(&db_path, &record));
+
+    // a rewrite that was interrupted while the directories were swapped is completed on open
+    fs::rename(&db_path, db_path.with_extension("rewrite")).unwrap();
+    fs::create_dir(db_path.with_extension("replaced")).unwrap();
+    let db = EncryptedDb::open(&db_path, get_test_password()).unwrap();
+    assert_eq!(
+        db.get("other").unwrap(),
+        Some(sled::IVec::from("other value"))
+    );
+    assert!(!db_path.with_extension("replaced").exists());
+    assert!(!db_path.with_extension("rewrite").exists());
+    drop(db);
+
+    // a rewrite that was interrupted before the swap is discarded
+    fs::create_dir(db_path.with_extension("rewrite")).unwrap();
+    let db = EncryptedDb::open(&db_path, get_test_password()).unwrap();
+    assert_eq!(
+        db.get("other").unwrap(),
+        Some(sled::IVec::from("other value"))
+    );
+    assert!(!db_path.with_extension("rewrite").exists());
+}
+
+#[test]
+fn test_change_password() {
+    let db_path = testdir!("change_password");
+
+    let mut db = EncryptedDb::open(&db_path, Password::from("old password")).unwrap();
+    db.insert("key", "value").unwrap();
+    db.change_password(Password::from("new password")).unwrap();
+
+    // the values can still be decrypted after the data key is re-wrapped
+    assert_eq!(db.get("key").unwrap(), Some(sled::IVec::from("value")));
+    drop(db);
+
+    // the old password no longer opens the kv store
+    assert!(matches!(
+        EncryptedDb::open(&db_path, Password::from("old password")),
+        Err(super::result::EncryptedDbError::WrongPassword)
+    ));
+
+    let db = EncryptedDb::open(&db_path, Password::from("new password")).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(sled::IVec::from("value")));
+}
+
+#[test]
+fn test_change_password_without_data_key() {
+    use super::{cipher::RecordCipher, constants::*};
+
+    // create a kv store whose values are encrypted directly under the password key
+    let db_path = testdir!("change_password_without_data_key");
+    let salt = [1u8; 32];
+    let cipher = RecordCipher::new(Password::from("old password"), salt.into()).unwrap();
+    let kv = sled::open(&db_path).unwrap();
+    kv.insert(PASSWORD_SALT_KEY, &salt[..]).unwrap();
+    kv.insert(
+        PASSWORD_VERIFICATION_KEY,
+        cipher
+            .encrypt(PASSWORD_VERIFICATION_VALUE.as_bytes())
+            .unwrap(),
+    )
+    .unwrap();
+    kv.insert("key", cipher.encrypt(b"value").unwrap()).unwrap();
+    drop(kv);
+
+    let mut db = EncryptedDb::open(&db_path, Password::from("old password")).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(sled::IVec::from("value")));
+
+    // all values are re-encrypted under a new data key
+    db.change_password(Password::from("new password")).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(sled::IVec::from("value")));
+    drop(db);
+
+    assert!(matches!(
+        EncryptedDb::open(&db_path, Password::from("old password")),
+        Err(super::result::EncryptedDbError::WrongPassword)
+    ));
+
+    let db = EncryptedDb::open(&db_path, Password::from("new password")).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(sled::IVec::from("value")));
+    let keys: Vec<_> = db.scan_prefix("").map(|res| res.unwrap().0).collect();
+    assert_eq!(keys, vec![sled::IVec::from("key")]);
+}
+
 pub fn get_test_password() -> Password {
     crate::encrypted_sled::PasswordMethod::NoPassword
         .execute()
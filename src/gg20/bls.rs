@@ -0,0 +1,267 @@
+//! BLS12-381 helpers for the gRPCs that operate on stored DKG shares.
+//! The group public key and the per-share public keys live in G1, so partial signatures
+//! and identity key shares live in G2.
+
+use bls12_381::{
+    hash_to_curve::{ExpandMsgXmd, HashToCurve},
+    pairing, G1Affine, G1Projective, G2Affine, G2Projective, Scalar,
+};
+use group::{ff::Field, Group, GroupEncoding};
+use sha2::{Digest, Sha512};
+
+use std::convert::TryInto;
+
+/// Domain separation tag of partial signatures.
+/// Uses the min-pk ciphersuite of https://datatracker.ietf.org/doc/html/draft-irtf-cfrg-bls-signature-05#section-4.2.1
+pub(super) const SIGNATURE_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_NUL_";
+
+/// Domain separation tag of identities for threshold identity-based encryption.
+/// Clients must hash identities with the same tag when encrypting.
+pub(super) const IDENTITY_DST: &[u8] = b"TOFND_IBE_BLS12381G2_XMD:SHA-256_SSWU_RO_";
+
+/// Domain separation tag of the Fiat-Shamir challenge of [DleqProof]
+const DLEQ_DST: &[u8] = b"TOFND_DLEQ_BLS12381_SHA-512";
+
+/// hash an arbitrary message to G2 under the domain separation tag `dst`
+pub(super) fn hash_to_g2(msg: &[u8], dst: &[u8]) -> G2Projective {
+    <G2Projective as HashToCurve<ExpandMsgXmd<sha2::Sha256>>>::hash_to_curve(msg, dst)
+}
+
+/// compute the partial signature `x_i * H(msg)` of a share with secret `x_i`
+pub(super) fn partial_sign(x_i: &Scalar, msg: &[u8]) -> G2Affine {
+    (hash_to_g2(msg, SIGNATURE_DST) * x_i).into()
+}
+
+/// compute the identity key share `x_i * H(identity)` of a share with secret `x_i`
+pub(super) fn extract_identity_key(x_i: &Scalar, identity: &[u8]) -> G2Affine {
+    (hash_to_g2(identity, IDENTITY_DST) * x_i).into()
+}
+
+/// evaluation point of the share with index `share_index`; shares are evaluated at `index + 1`
+/// because the secret lives at 0
+pub(super) fn share_point(share_index: usize) -> Scalar {
+    Scalar::from(share_index as u64 + 1)
+}
+
+/// compute the public key `x_i * g1` of share `share_index` from the Feldman commitments
+/// `[a_0 * g1, ..., a_t * g1]` of the secret polynomial
+pub(super) fn share_public_key(commitments: &[G1Projective], share_index: usize) -> G1Projective {
+    // Horner's rule on the commitments
+    let x = share_point(share_index);
+    commitments
+        .iter()
+        .rev()
+        .fold(G1Projective::identity(), |acc, commitment| {
+            acc * x + commitment
+        })
+}
+
+/// check `e(pk_i, h) == e(g1, partial)`, i.e. that `partial` is `x_i * h` for the secret `x_i` of `pk_i`
+pub(super) fn verify_partial(pub_key: &G1Affine, h: &G2Affine, partial: &G2Affine) -> bool {
+    pairing(pub_key, h) == pairing(&G1Affine::generator(), partial)
+}
+
+/// Lagrange-interpolate the G2 points `(share_index, x_i * h)` at 0 to get `x * h`.
+/// Returns `None` if the share indices are not distinct.
+pub(super) fn interpolate(partials: &[(usize, G2Projective)]) -> Option<G2Projective> {
+    let share_indices: Vec<usize> = partials.iter().map(|(index, _)| *index).collect();
+    let mut res = G2Projective::identity();
+    for (i, (_, partial_i)) in partials.iter().enumerate() {
+        res += partial_i * lagrange_coefficient(&share_indices, i)?;
+    }
+    Some(res)
+}
+
+/// Lagrange coefficient `l_i(0)` of the `i`th of the shares `share_indices`.
+/// Returns `None` if the share indices are not distinct.
+pub(super) fn lagrange_coefficient(share_indices: &[usize], i: usize) -> Option<Scalar> {
+    let x_i = share_point(share_indices[i]);
+    // l_i(0) = prod_{j != i} x_j / (x_j - x_i)
+    let mut num = Scalar::one();
+    let mut den = Scalar::one();
+    for (j, index_j) in share_indices.iter().enumerate() {
+        if i == j {
+            continue;
+        }
+        let x_j = share_point(*index_j);
+        num *= x_j;
+        den *= x_j - x_i;
+    }
+    let den_inv: Option<Scalar> = den.invert().into();
+    Some(num * den_inv?)
+}
+
+/// Non-interactive Chaum-Pedersen proof that `log_g(a) == log_h(b)`.
+/// `g` and `h` can belong to different groups as long as they share the scalar field,
+/// i.e. the proof can show that a G1 public key and a G2 key share use the same secret.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct DleqProof {
+    c: Scalar,
+    z: Scalar,
+}
+
+impl DleqProof {
+    /// prove knowledge of `x` such that `a = x * g` and `b = x * h`
+    pub(super) fn prove<A, B>(x: &Scalar, g: &A, h: &B) -> Self
+    where
+        A: Group<Scalar = Scalar> + GroupEncoding,
+        B: Group<Scalar = Scalar> + GroupEncoding,
+    {
+        let (a, b) = (*g * x, *h * x);
+        let r = Scalar::random(rand::thread_rng());
+        let c = Self::challenge(g, &a, h, &b, &(*g * r), &(*h * r));
+        Self { c, z: r + c * x }
+    }
+
+    /// verify that `log_g(a) == log_h(b)`
+    pub(super) fn verify<A, B>(&self, g: &A, a: &A, h: &B, b: &B) -> bool
+    where
+        A: Group<Scalar = Scalar> + GroupEncoding,
+        B: Group<Scalar = Scalar> + GroupEncoding,
+    {
+        // z * g - c * a = r * g
+        let r_g = *g * self.z - *a * self.c;
+        let r_h = *h * self.z - *b * self.c;
+        self.c == Self::challenge(g, a, h, b, &r_g, &r_h)
+    }
+
+    /// Fiat-Shamir challenge over the statement and the prover's commitments
+    fn challenge<A, B>(g: &A, a: &A, h: &B, b: &B, r_g: &A, r_h: &B) -> Scalar
+    where
+        A: GroupEncoding,
+        B: GroupEncoding,
+    {
+        let mut hasher = Sha512::new();
+        hasher.update(DLEQ_DST);
+        for point in [g.to_bytes(), a.to_bytes(), r_g.to_bytes()].iter() {
+            hasher.update(point.as_ref());
+        }
+        for point in [h.to_bytes(), b.to_bytes(), r_h.to_bytes()].iter() {
+            hasher.update(point.as_ref());
+        }
+        let digest: [u8; 64] = hasher.finalize().into();
+        Scalar::from_bytes_wide(&digest)
+    }
+
+    /// encode proof as `c || z`
+    pub(super) fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.c.to_bytes());
+        bytes[32..].copy_from_slice(&self.z.to_bytes());
+        bytes
+    }
+
+    /// decode proof from `c || z`; returns `None` on malformed bytes
+    pub(super) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 64 {
+            return None;
+        }
+        let c = Option::from(Scalar::from_bytes(bytes[..32].try_into().ok()?))?;
+        let z = Option::from(Scalar::from_bytes(bytes[32..].try_into().ok()?))?;
+        Some(Self { c, z })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_sign() {
+        let x_i = Scalar::from(42);
+        let pub_key = G1Affine::from(G1Affine::generator() * x_i);
+        let msg = b"message";
+
+        let sig = partial_sign(&x_i, msg);
+
+        // e(pk_i, H(m)) == e(g1, sig_i)
+        let hash = G2Affine::from(hash_to_g2(msg, SIGNATURE_DST));
+        assert_eq!(
+            pairing(&pub_key, &hash),
+            pairing(&G1Affine::generator(), &sig)
+        );
+
+        // a different message must not verify
+        let other_hash = G2Affine::from(hash_to_g2(b"other message", SIGNATURE_DST));
+        assert_ne!(
+            pairing(&pub_key, &other_hash),
+            pairing(&G1Affine::generator(), &sig)
+        );
+    }
+
+    #[test]
+    fn test_interpolate() {
+        // f(x) = 7 + 3x + 5x^2, threshold 2
+        let coeffs = [Scalar::from(7), Scalar::from(3), Scalar::from(5)];
+        let f = |index: usize| {
+            let x = share_point(index);
+            coeffs
+                .iter()
+                .rev()
+                .fold(Scalar::zero(), |acc, a| acc * x + a)
+        };
+        let commitments: Vec<_> = coeffs
+            .iter()
+            .map(|a| G1Projective::generator() * a)
+            .collect();
+        let h = hash_to_g2(b"message", SIGNATURE_DST);
+
+        // share public keys match the Feldman commitments
+        for index in 0..5 {
+            assert_eq!(
+                share_public_key(&commitments, index),
+                G1Projective::generator() * f(index)
+            );
+            let pub_key = G1Affine::from(share_public_key(&commitments, index));
+            let partial = G2Affine::from(h * f(index));
+            assert!(verify_partial(&pub_key, &h.into(), &partial));
+            assert!(!verify_partial(
+                &pub_key,
+                &h.into(),
+                &G2Affine::from(h * f(index + 1))
+            ));
+        }
+
+        // any 3 shares recover the secret
+        let expected = h * coeffs[0];
+        for indices in [[0, 1, 2], [1, 3, 4], [4, 0, 2]].iter() {
+            let partials: Vec<_> = indices.iter().map(|&i| (i, h * f(i))).collect();
+            assert_eq!(interpolate(&partials).unwrap(), expected);
+        }
+
+        // 2 shares are not enough
+        let partials: Vec<_> = [0, 1].iter().map(|&i| (i, h * f(i))).collect();
+        assert_ne!(interpolate(&partials).unwrap(), expected);
+
+        // duplicate indices are rejected
+        let partials: Vec<_> = [0, 0, 1].iter().map(|&i| (i, h * f(i))).collect();
+        assert!(interpolate(&partials).is_none());
+    }
+
+    #[test]
+    fn test_dleq_proof() {
+        let x_i = Scalar::from(42);
+        let g = G1Projective::generator();
+        let pub_key = g * x_i;
+        let h = hash_to_g2(b"identity", IDENTITY_DST);
+        let key_share = G2Projective::from(extract_identity_key(&x_i, b"identity"));
+
+        let proof = DleqProof::prove(&x_i, &g, &h);
+        assert!(proof.verify(&g, &pub_key, &h, &key_share));
+
+        // proof survives encoding
+        let decoded = DleqProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify(&g, &pub_key, &h, &key_share));
+
+        // key share of a different secret must not verify
+        let bad_key_share = h * Scalar::from(43);
+        assert!(!proof.verify(&g, &pub_key, &h, &bad_key_share));
+
+        // key share of a different identity must not verify
+        let other_h = hash_to_g2(b"other identity", IDENTITY_DST);
+        assert!(!proof.verify(&g, &pub_key, &other_h, &(other_h * x_i)));
+
+        assert!(DleqProof::from_bytes(&[0u8; 63]).is_none());
+    }
+}
@@ -1,6 +1,8 @@
 //! This module handles the routing of incoming traffic.
 //! Receives and validates messages until the connection is closed by the client.
 //! The incoming messages come from the gRPC stream and are forwarded to shares' internal channels.
+//! Broadcasts and echoes are forwarded to all shares, and p2ps only to the share they are addressed to; see [ShareSenders].
+//! Sessions that generate several keys get the traffic of all keys over the same stream; traffic is forwarded to the shares of the key it is tagged with.
 
 // tonic cruft
 use super::proto;
@@ -19,12 +21,67 @@ enum RoutingStatus {
     Skip,
 }
 
-/// Receives incoming from a gRPC stream and broadcasts them to internal channels;
+/// Internal channels of the protocol threads of a session
+#[derive(Default)]
+pub(super) struct ShareSenders {
+    senders: Vec<mpsc::UnboundedSender<Option<proto::TrafficIn>>>,
+    first_share_id: Option<usize>, // share id of the first thread if each thread runs a share; `None` if the threads get all traffic
+}
+
+impl ShareSenders {
+    /// channels of the threads of shares `first_share_id..first_share_id + senders.len()`
+    pub(super) fn shares(
+        first_share_id: usize,
+        senders: Vec<mpsc::UnboundedSender<Option<proto::TrafficIn>>>,
+    ) -> Self {
+        Self {
+            senders,
+            first_share_id: Some(first_share_id),
+        }
+    }
+
+    /// channel of a session that is run by a single thread for the whole party
+    pub(super) fn party(sender: mpsc::UnboundedSender<Option<proto::TrafficIn>>) -> Self {
+        Self {
+            senders: vec![sender],
+            first_share_id: None,
+        }
+    }
+
+    /// Forward traffic to the threads it is addressed to.
+    /// P2ps for shares that are not ours are dropped.
+    pub(super) fn send(&self, traffic: proto::TrafficIn) {
+        let first_share_id = match self.first_share_id {
+            Some(first_share_id) if !traffic.is_broadcast => first_share_id,
+            _ => {
+                for sender in &self.senders {
+                    let _ = sender.send(Some(traffic.clone()));
+                }
+                return;
+            }
+        };
+
+        let sender = (traffic.to_share_id as usize)
+            .checked_sub(first_share_id)
+            .and_then(|subindex| self.senders.get(subindex));
+        match sender {
+            Some(sender) => {
+                let _ = sender.send(Some(traffic));
+            }
+            None => warn!(
+                "ignore incoming p2p from {}: share {} is not ours",
+                traffic.from_party_uid, traffic.to_share_id
+            ),
+        }
+    }
+}
+
+/// Receives incoming from a gRPC stream and routes them to internal channels; `out_internal_channels` holds the channels of each key of the session.
 /// Loops until client closes the socket, or a message containing [proto::message_in::Data::Abort] is received  
 /// Empty and unknown messages are ignored
 pub(super) async fn broadcast_messages(
     in_grpc_stream: &mut tonic::Streaming<proto::MessageIn>,
-    mut out_internal_channels: Vec<mpsc::UnboundedSender<Option<proto::TrafficIn>>>,
+    out_internal_channels: Vec<ShareSenders>,
     span: Span,
 ) {
     // loop until `stop` is received
@@ -39,9 +96,13 @@ pub(super) async fn broadcast_messages(
             RoutingStatus::Skip => continue,
         };
 
-        // send the message to all channels
-        for out_channel in &mut out_internal_channels {
-            let _ = out_channel.send(Some(traffic.clone()));
+        // send the message to the channels it is addressed to
+        match out_internal_channels.get(traffic.key_index as usize) {
+            Some(share_senders) => share_senders.send(traffic),
+            None => warn!(
+                "ignore incoming msg from {}: key {} is not part of the session",
+                traffic.from_party_uid, traffic.key_index
+            ),
         }
     }
 }
@@ -51,7 +112,8 @@ pub(super) async fn broadcast_messages(
 /// [proto::message_in::Data::Traffic]    -> return [RoutingResult::Continue]
 /// [proto::message_in::Data::Abort]      -> return [RoutingResult::Stop]
 /// [proto::message_in::Data::KeygenInit] -> return [RoutingResult::Skip]
-/// [proto::message_in::Data::SignInit]   -> return [RoutingResult::Skip]
+/// [proto::message_in::Data::RefreshInit] -> return [RoutingResult::Skip]
+/// [proto::message_in::Data::ReshareInit] -> return [RoutingResult::Skip]
 fn open_message(msg: Option<Result<proto::MessageIn, Status>>, span: Span) -> RoutingStatus {
     // start routing span
     let route_span = span!(parent: &span, Level::INFO, "routing");
@@ -102,11 +164,12 @@ fn open_message(msg: Option<Result<proto::MessageIn, Status>>, span: Span) -> Ro
             warn!("received abort message");
             return RoutingStatus::Stop;
         }
-        proto::message_in::Data::KeygenInit(_) => todo!(),
-        // proto::message_in::Data::KeygenInit(_) | proto::message_in::Data::SignInit(_) => {
-        //     warn!("ignore incoming msg: expect `data` to be TrafficIn type");
-        //     return RoutingStatus::Skip;
-        // }
+        proto::message_in::Data::KeygenInit(_)
+        | proto::message_in::Data::RefreshInit(_)
+        | proto::message_in::Data::ReshareInit(_) => {
+            warn!("ignore incoming msg: expect `data` to be TrafficIn type");
+            return RoutingStatus::Skip;
+        }
     };
 
     // return traffic
@@ -148,10 +211,6 @@ mod tests {
                 )),
                 RoutingStatus::Skip,
             ),
-            TestCase::new(
-                new_msg_in(proto::message_in::Data::SignInit(proto::SignInit::default())),
-                RoutingStatus::Skip,
-            ),
             TestCase::new(
                 new_msg_in(proto::message_in::Data::Traffic(proto::TrafficIn::default())),
                 RoutingStatus::Continue {
@@ -174,4 +233,40 @@ mod tests {
         let result = open_message(None, span);
         assert_eq!(result, RoutingStatus::Stop);
     }
+
+    #[test]
+    fn test_share_senders() {
+        let (senders, mut receivers): (Vec<_>, Vec<_>) =
+            (0..2).map(|_| mpsc::unbounded_channel()).unzip();
+        // our shares are 3 and 4
+        let share_senders = ShareSenders::shares(3, senders);
+        let traffic = |is_broadcast: bool, to_share_id: u32| proto::TrafficIn {
+            is_broadcast,
+            to_share_id,
+            ..Default::default()
+        };
+
+        // bcasts go to all shares, p2ps only to their recipient
+        share_senders.send(traffic(true, 0));
+        share_senders.send(traffic(false, 4));
+        share_senders.send(traffic(false, 2));
+        share_senders.send(traffic(false, 5));
+        drop(share_senders);
+
+        let received = |receiver: &mut mpsc::UnboundedReceiver<Option<proto::TrafficIn>>| {
+            let mut received = vec![];
+            while let Ok(Some(traffic)) = receiver.try_recv() {
+                received.push((traffic.is_broadcast, traffic.to_share_id));
+            }
+            received
+        };
+        assert_eq!(received(&mut receivers[0]), vec![(true, 0)]);
+        assert_eq!(received(&mut receivers[1]), vec![(true, 0), (false, 4)]);
+
+        // a party-wide thread gets all traffic
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let party_sender = ShareSenders::party(sender);
+        party_sender.send(traffic(false, 7));
+        assert_eq!(received(&mut receiver), vec![(false, 7)]);
+    }
 }
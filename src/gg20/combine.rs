@@ -0,0 +1,258 @@
+//! This module handles the combine gRPC.
+//! Request includes a `key_uid`, the kind of the partial outputs and the signed message or identity.
+//! Each partial output is verified against the public key of its share, which is derived from the
+//! VSS commitments stored in [PartyInfo]. Invalid partials are dropped and reported along with their owners.
+//! The remaining valid partials are Lagrange-interpolated into the group signature or the identity private key.
+
+use super::{bls, delete_key::check_key_uid, proto, service::Gg20Service, types::PartyInfo};
+use bls12_381::{G1Affine, G2Affine, G2Projective};
+
+// logging
+use tracing::{info, warn};
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::collections::HashSet;
+use std::convert::{TryFrom, TryInto};
+
+use proto::combine_request::Kind;
+
+impl Gg20Service {
+    pub(super) async fn handle_combine(
+        &self,
+        request: proto::CombineRequest,
+    ) -> TofndResult<proto::CombineResult> {
+        let dst = match Kind::from_i32(request.kind) {
+            Some(Kind::Signature) => bls::SIGNATURE_DST,
+            Some(Kind::IdentityKey) => bls::IDENTITY_DST,
+            _ => return Err(anyhow!("unknown combine kind {}", request.kind)),
+        };
+
+        // get public info of key from kv store
+        check_key_uid(&request.key_uid)?;
+        let party_info: PartyInfo = self
+            .kv_manager
+            .kv()
+            .get(&request.key_uid)
+            .await
+            .map_err(|err| anyhow!("could not find key {}: {}", request.key_uid, err))?
+            .try_into()?;
+
+        let threshold = party_info.common.threshold();
+        let h = G2Affine::from(bls::hash_to_g2(&request.msg, dst));
+
+        // verify partials and keep a valid partial per share
+        let mut valid_partials = Vec::<(usize, G2Projective)>::new();
+        let mut seen_shares = HashSet::new();
+        let mut invalid_partials = Vec::new();
+        for partial in request.partials {
+            let share_index = usize::try_from(partial.share_index)?;
+
+            // duplicates of an already verified share add nothing
+            if seen_shares.contains(&share_index) {
+                continue;
+            }
+
+            match Self::verify_partial_output(&party_info, share_index, &h, &partial.partial) {
+                Some(point) => {
+                    seen_shares.insert(share_index);
+                    valid_partials.push((share_index, point.into()));
+                }
+                None => {
+                    let party_uid = party_info.share_owner(share_index).unwrap_or_default();
+                    warn!(
+                        "Dropping invalid partial of share {} [party {}] for key {}",
+                        share_index, party_uid, request.key_uid
+                    );
+                    invalid_partials.push(proto::InvalidPartial {
+                        share_index: partial.share_index,
+                        party_uid: party_uid.to_string(),
+                    });
+                }
+            }
+        }
+
+        if valid_partials.len() <= threshold {
+            return Err(anyhow!(
+                "got {} valid partials for key {}, need at least {}",
+                valid_partials.len(),
+                request.key_uid,
+                threshold + 1
+            ));
+        }
+
+        info!(
+            "Combining {} valid partials of key {} ({} invalid)",
+            valid_partials.len(),
+            request.key_uid,
+            invalid_partials.len()
+        );
+
+        // any `threshold + 1` valid partials determine the output
+        valid_partials.truncate(threshold + 1);
+        let output = bls::interpolate(&valid_partials)
+            .ok_or_else(|| anyhow!("could not interpolate partials"))?;
+
+        Ok(proto::CombineResult {
+            output: G2Affine::from(output).to_compressed().to_vec(),
+            invalid_partials,
+        })
+    }
+
+    /// decode a partial output of share `share_index` and check it against the share's public key.
+    /// Returns `None` if the share does not exist or the partial is malformed or invalid.
+    fn verify_partial_output(
+        party_info: &PartyInfo,
+        share_index: usize,
+        h: &G2Affine,
+        partial: &[u8],
+    ) -> Option<G2Affine> {
+        party_info.share_owner(share_index)?;
+
+        let partial: Option<G2Affine> = G2Affine::from_compressed(partial.try_into().ok()?).into();
+        let partial = partial?;
+
+        let pub_key = G1Affine::from(bls::share_public_key(
+            party_info.common.vss_commitments(),
+            share_index,
+        ));
+
+        if bls::verify_partial(&pub_key, h, &partial) {
+            Some(partial)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::{
+        test_utils::{store_key, test_service},
+        vss,
+    };
+    use bls12_381::Scalar;
+    use testdir::testdir;
+    use tracing_test::traced_test;
+
+    /// partial signatures of `msg` by the shares `share_indices` of the key dealt by `poly`
+    fn partial_signatures(
+        poly: &vss::Polynomial,
+        share_indices: &[usize],
+        msg: &[u8],
+    ) -> Vec<proto::PartialOutput> {
+        share_indices
+            .iter()
+            .map(|&index| proto::PartialOutput {
+                share_index: index as u32,
+                partial: bls::partial_sign(&poly.eval(index), msg)
+                    .to_compressed()
+                    .to_vec(),
+            })
+            .collect()
+    }
+
+    fn request(
+        kind: Kind,
+        msg: &[u8],
+        partials: Vec<proto::PartialOutput>,
+    ) -> proto::CombineRequest {
+        proto::CombineRequest {
+            key_uid: "key".to_owned(),
+            kind: kind as i32,
+            msg: msg.to_vec(),
+            partials,
+        }
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_combine_signature() {
+        let service = test_service(testdir!()).await;
+        let secret = Scalar::from(7);
+        let poly = store_key(&service, "key", secret).await;
+        let msg = b"message";
+        let signature = G2Affine::from(bls::hash_to_g2(msg, bls::SIGNATURE_DST) * secret)
+            .to_compressed()
+            .to_vec();
+
+        // bob's partial of share 2 signs another message; the partials of shares 1 and 3 still combine
+        let mut partials = partial_signatures(&poly, &[1, 2, 3], msg);
+        partials[1].partial = bls::partial_sign(&poly.eval(2), b"other message")
+            .to_compressed()
+            .to_vec();
+        let result = service
+            .handle_combine(request(Kind::Signature, msg, partials.clone()))
+            .await
+            .unwrap();
+        assert_eq!(result.output, signature);
+        assert_eq!(
+            result.invalid_partials,
+            vec![proto::InvalidPartial {
+                share_index: 2,
+                party_uid: "bob".to_owned(),
+            }]
+        );
+
+        // malformed partials and partials of unknown shares are dropped too
+        partials[0].partial = vec![1; 3];
+        partials.push(proto::PartialOutput {
+            share_index: 4,
+            partial: partials[2].partial.clone(),
+        });
+        // a single valid partial remains, which does not meet the threshold
+        assert!(service
+            .handle_combine(request(Kind::Signature, msg, partials))
+            .await
+            .is_err());
+
+        // duplicates of a valid partial don't count twice
+        let partials = partial_signatures(&poly, &[0, 0], msg);
+        assert!(service
+            .handle_combine(request(Kind::Signature, msg, partials))
+            .await
+            .is_err());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_combine_identity_key() {
+        let service = test_service(testdir!()).await;
+        let secret = Scalar::from(7);
+        let poly = store_key(&service, "key", secret).await;
+        let identity = b"identity";
+
+        let partials: Vec<_> = [0, 3]
+            .iter()
+            .map(|&index| proto::PartialOutput {
+                share_index: index as u32,
+                partial: bls::extract_identity_key(&poly.eval(index), identity)
+                    .to_compressed()
+                    .to_vec(),
+            })
+            .collect();
+        let result = service
+            .handle_combine(request(Kind::IdentityKey, identity, partials.clone()))
+            .await
+            .unwrap();
+        let identity_key = bls::hash_to_g2(identity, bls::IDENTITY_DST) * secret;
+        assert_eq!(
+            result.output,
+            G2Affine::from(identity_key).to_compressed().to_vec()
+        );
+        assert!(result.invalid_partials.is_empty());
+
+        // identity key shares are not signatures
+        assert!(service
+            .handle_combine(request(Kind::Signature, identity, partials.clone()))
+            .await
+            .is_err());
+        assert!(service
+            .handle_combine(request(Kind::Unspecified, identity, partials))
+            .await
+            .is_err());
+    }
+}
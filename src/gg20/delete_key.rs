@@ -0,0 +1,192 @@
+//! This module handles the delete_key and restore_key gRPCs.
+//! [proto::delete_key_request::Mode::Archive] moves the key to an archived namespace, where it is hidden
+//! from key presence, signing and listing, until it is restored with restore_key.
+//! [proto::delete_key_request::Mode::Purge] removes the key and any archived copy of it, including the archive of an unconfirmed refresh,
+//! and rewrites the kv store, so that the encrypted shares are erased from disk.
+//! Keys that are reserved by an ongoing keygen can't be deleted.
+//! Uids of archived keys and of mnemonic records are rejected by every gRPC that takes a key uid; see [check_key_uid].
+
+use super::{proto, refresh, service::Gg20Service};
+use crate::mnemonic::is_mnemonic_key;
+
+// logging
+use tracing::info;
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use proto::delete_key_request::Mode;
+
+/// prefix of archived keys in the kv store
+pub(super) const ARCHIVED_KEY_PREFIX: &str = "archived:";
+
+/// get the key under which `key_uid` is archived
+fn archived_key(key_uid: &str) -> String {
+    format!("{}{}", ARCHIVED_KEY_PREFIX, key_uid)
+}
+
+/// Check that `key_uid` can name a key. Archived keys and mnemonic records share the kv store with keys,
+/// but can't be used, deleted or overwritten through their kv store keys.
+pub(super) fn check_key_uid(key_uid: &str) -> TofndResult<()> {
+    if is_mnemonic_key(key_uid) {
+        return Err(anyhow!("key uid {} is reserved for mnemonics", key_uid));
+    }
+    if key_uid.starts_with(ARCHIVED_KEY_PREFIX) {
+        return Err(anyhow!("key uid {} is reserved for archived keys", key_uid));
+    }
+    Ok(())
+}
+
+impl Gg20Service {
+    pub(super) async fn handle_delete_key(
+        &self,
+        request: proto::DeleteKeyRequest,
+    ) -> TofndResult<()> {
+        check_key_uid(&request.key_uid)?;
+        let kv = self.kv_manager.kv();
+        match Mode::from_i32(request.mode) {
+            Some(Mode::Archive) => {
+                kv.rename(&request.key_uid, &archived_key(&request.key_uid))
+                    .await
+                    .map_err(|err| anyhow!("could not archive key {}: {}", request.key_uid, err))?;
+                info!("Archived key {}", request.key_uid);
+            }
+            Some(Mode::Purge) => {
+                // purge both the live and the archived record; purging a missing key is a no-op
+                for key in [
+                    request.key_uid.clone(),
+                    archived_key(&request.key_uid),
+                    refresh::archive_key(&request.key_uid),
+                ]
+                .iter()
+                {
+                    kv.purge(key)
+                        .await
+                        .map_err(|err| anyhow!("could not purge key {}: {}", key, err))?;
+                }
+                info!("Purged key {}", request.key_uid);
+            }
+            _ => return Err(anyhow!("unknown delete mode {}", request.mode)),
+        }
+        Ok(())
+    }
+
+    pub(super) async fn handle_restore_key(
+        &self,
+        request: proto::RestoreKeyRequest,
+    ) -> TofndResult<()> {
+        check_key_uid(&request.key_uid)?;
+        self.kv_manager
+            .kv()
+            .rename(&archived_key(&request.key_uid), &request.key_uid)
+            .await
+            .map_err(|err| anyhow!("could not restore key {}: {}", request.key_uid, err))?;
+        info!("Restored key {}", request.key_uid);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::test_utils::{party_info, put_key, store_key, test_service};
+    use bls12_381::Scalar;
+    use testdir::testdir;
+    use tracing_test::traced_test;
+
+    fn delete(key_uid: &str, mode: Mode) -> proto::DeleteKeyRequest {
+        proto::DeleteKeyRequest {
+            key_uid: key_uid.to_owned(),
+            mode: mode as i32,
+        }
+    }
+
+    fn restore(key_uid: &str) -> proto::RestoreKeyRequest {
+        proto::RestoreKeyRequest {
+            key_uid: key_uid.to_owned(),
+        }
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_archive_and_restore() {
+        let service = test_service(testdir!()).await;
+        let kv = service.kv_manager.kv();
+        store_key(&service, "key", Scalar::from(7)).await;
+
+        service
+            .handle_delete_key(delete("key", Mode::Archive))
+            .await
+            .unwrap();
+        assert!(!kv.exists("key").await.unwrap());
+        assert!(kv.exists("archived:key").await.unwrap());
+
+        // archived keys can't be used or deleted through their archived uid
+        let sign = proto::SignRequest {
+            key_uid: "archived:key".to_owned(),
+            msg_to_sign: b"message".to_vec(),
+        };
+        assert!(service.handle_sign(sign).await.is_err());
+        assert!(service
+            .handle_delete_key(delete("archived:key", Mode::Purge))
+            .await
+            .is_err());
+
+        service.handle_restore_key(restore("key")).await.unwrap();
+        assert!(kv.exists("key").await.unwrap());
+        assert!(!kv.exists("archived:key").await.unwrap());
+
+        // nothing left to restore
+        assert!(service.handle_restore_key(restore("key")).await.is_err());
+        assert!(service
+            .handle_delete_key(delete("key", Mode::Unspecified))
+            .await
+            .is_err());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_purge() {
+        let service = test_service(testdir!()).await;
+        let kv = service.kv_manager.kv();
+
+        // an archived copy, the live key, and the archive of an unconfirmed refresh of it
+        store_key(&service, "key", Scalar::from(7)).await;
+        service
+            .handle_delete_key(delete("key", Mode::Archive))
+            .await
+            .unwrap();
+        let poly = store_key(&service, "key", Scalar::from(7)).await;
+        let seed_fingerprint = service.kv_manager.seed_fingerprint().await.unwrap();
+        put_key(
+            &service,
+            &refresh::archive_key("key"),
+            party_info(&poly, seed_fingerprint),
+        )
+        .await;
+        store_key(&service, "other", Scalar::from(7)).await;
+
+        service
+            .handle_delete_key(delete("key", Mode::Purge))
+            .await
+            .unwrap();
+        for key in ["key", "archived:key", "archived:refresh:key"].iter() {
+            assert!(!kv.exists(key).await.unwrap());
+        }
+        assert!(kv.exists("other").await.unwrap());
+
+        // purging a missing key is a no-op
+        service
+            .handle_delete_key(delete("key", Mode::Purge))
+            .await
+            .unwrap();
+
+        // mnemonics can't be purged as keys
+        assert!(service
+            .handle_delete_key(delete("mnemonic", Mode::Purge))
+            .await
+            .is_err());
+        assert!(kv.exists("mnemonic").await.unwrap());
+    }
+}
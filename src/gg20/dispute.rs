@@ -0,0 +1,367 @@
+//! This module handles the verify_dispute gRPC.
+//! Request includes the VSS commitments that the accused broadcast during a keygen, the encryption keys and the identity
+//! key of the parties and a [proto::Complaint] as reported by keygen. No stored state is needed, so any tofnd can settle a dispute.
+//!
+//! A complaint reveals the Diffie-Hellman key `kij` of the accuser and the accused, along with a DLEQ proof that
+//! `log_g1(accuser_key) == log_{accused_key}(kij)`, and the share that the accuser decrypted with `kij`.
+//! It also carries the p2p in which the accused dealt the share, signed by the accused for the session and round, and
+//! the key that opens it. The accuser is at fault if the proof does not verify, if the accused did not sign the p2p,
+//! if the p2p does not decrypt to the disputed share under `kij`, or if the share is consistent with the commitments.
+//! Otherwise, the accused dealt an invalid share.
+
+use super::{
+    bls::{self, DleqProof},
+    identity::{self, ShareIds},
+    proto,
+    protocol::{P2pShare, Share, ShareInfoDispute},
+    service::Gg20Service,
+};
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use group::GroupEncoding;
+use proto::verify_dispute_response::Verdict;
+use sha2::{Digest, Sha512};
+
+// logging
+use tracing::info;
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::convert::{TryFrom, TryInto};
+
+/// domain of the masks of dealt shares
+const SHARE_MASK_DOMAIN: &[u8] = b"tofnd share mask";
+
+impl Gg20Service {
+    pub(super) async fn handle_verify_dispute(
+        &self,
+        request: proto::VerifyDisputeRequest,
+    ) -> TofndResult<Verdict> {
+        let complaint = request
+            .complaint
+            .ok_or_else(|| anyhow!("missing complaint"))?;
+        let dispute = decode_dispute(&complaint)?;
+
+        let commitments = request
+            .commitments
+            .iter()
+            .map(|commitment| {
+                decode_point(commitment)
+                    .map(G1Projective::from)
+                    .ok_or_else(|| anyhow!("invalid commitment"))
+            })
+            .collect::<TofndResult<Vec<_>>>()?;
+        if commitments.is_empty() {
+            return Err(anyhow!("missing commitments"));
+        }
+
+        let accuser_key = decode_point(&request.accuser_encryption_key)
+            .ok_or_else(|| anyhow!("invalid encryption key of accuser"))?;
+        let accused_key = decode_point(&request.accused_encryption_key)
+            .ok_or_else(|| anyhow!("invalid encryption key of accused"))?;
+        let accused_identity_key = identity::decode_public_key(&request.accused_identity_key)
+            .ok_or_else(|| anyhow!("invalid identity key of accused"))?;
+
+        let verdict = verify_dispute(
+            &complaint,
+            &dispute,
+            &commitments,
+            &accuser_key,
+            &accused_key,
+            &accused_identity_key,
+        );
+        info!(
+            "Dispute of {} against {} for share {}: {:?}",
+            complaint.accuser_uid, complaint.accused_uid, complaint.share_index, verdict
+        );
+        Ok(verdict)
+    }
+}
+
+/// Decide which party of a dispute is at fault.
+/// `accuser_key` and `accused_key` are the encryption keys that the parties broadcast during keygen,
+/// `accused_identity_key` is the identity key that the client advertised for the accused.
+fn verify_dispute(
+    complaint: &proto::Complaint,
+    dispute: &ShareInfoDispute,
+    commitments: &[G1Projective],
+    accuser_key: &G1Affine,
+    accused_key: &G1Affine,
+    accused_identity_key: &G1Affine,
+) -> Verdict {
+    // the accuser must prove that `kij` is the Diffie-Hellman key of both parties
+    let (c, z) = &dispute.proof;
+    let proof = match DleqProof::from_bytes(&[&c[..], &z[..]].concat()) {
+        Some(proof) => proof,
+        None => return Verdict::AccuserAtFault,
+    };
+    if !proof.verify(
+        &G1Projective::generator(),
+        &G1Projective::from(accuser_key),
+        &G1Projective::from(accused_key),
+        &dispute.kij,
+    ) {
+        return Verdict::AccuserAtFault;
+    }
+
+    // the accused must have dealt exactly the disputed share
+    let dealt = dealt_share(complaint, accused_identity_key, &dispute.kij);
+    if dealt.as_ref() != Some(&dispute.share) {
+        return Verdict::AccuserAtFault;
+    }
+
+    // the share must match the commitments of its dealer
+    let expected = bls::share_public_key(commitments, dispute.share.index);
+    if G1Projective::generator() * dispute.share.scalar == expected {
+        Verdict::AccuserAtFault
+    } else {
+        Verdict::AccusedAtFault
+    }
+}
+
+/// The mask of the share with index `share_index` that is encrypted under `kij`; the share is dealt as `share + mask`
+pub(super) fn share_mask(kij: &G1Projective, share_index: usize) -> Scalar {
+    let mut hasher = Sha512::new();
+    hasher.update(SHARE_MASK_DOMAIN);
+    hasher.update(kij.to_bytes().as_ref());
+    hasher.update((share_index as u64).to_be_bytes());
+    let digest: [u8; 64] = hasher.finalize().into();
+    Scalar::from_bytes_wide(&digest)
+}
+
+/// The share that the accused dealt to the accuser, decrypted with `kij`.
+/// Returns `None` if the complaint carries no p2p that the accused signed for the accuser and the disputed share,
+/// or if the p2p does not deal a share.
+fn dealt_share(
+    complaint: &proto::Complaint,
+    accused_identity_key: &G1Affine,
+    kij: &G1Projective,
+) -> Option<Share> {
+    let dealing = complaint.encrypted_share.as_ref()?;
+    if dealing.to_share_id != complaint.share_index {
+        return None;
+    }
+
+    let share_ids = ShareIds::new(
+        usize::try_from(dealing.from_share_id).ok()?,
+        usize::try_from(dealing.to_share_id).ok()?,
+    );
+    if !identity::verify_p2p(
+        accused_identity_key,
+        &dealing.session_id,
+        &complaint.accused_uid,
+        &dealing.round_num,
+        &complaint.accuser_uid,
+        share_ids,
+        &dealing.payload,
+        &dealing.signature,
+    ) {
+        return None;
+    }
+
+    let payload = identity::open_p2p(&dealing.p2p_key, &dealing.round_num, &dealing.payload)?;
+    let encrypted = P2pShare::parse(&payload)?.encrypted_u_i_share;
+    Some(Share {
+        scalar: encrypted.scalar - share_mask(kij, encrypted.index),
+        index: encrypted.index,
+    })
+}
+
+/// decode a compressed G1 point; returns `None` on malformed bytes
+fn decode_point(bytes: &[u8]) -> Option<G1Affine> {
+    Option::from(G1Affine::from_compressed(bytes.try_into().ok()?))
+}
+
+/// decode the [ShareInfoDispute] of a complaint
+fn decode_dispute(complaint: &proto::Complaint) -> TofndResult<ShareInfoDispute> {
+    let scalar: Option<Scalar> = complaint
+        .share
+        .as_slice()
+        .try_into()
+        .ok()
+        .and_then(|share| Scalar::from_bytes(share).into());
+    let scalar = scalar.ok_or_else(|| anyhow!("invalid share"))?;
+
+    let kij = decode_point(&complaint.kij).ok_or_else(|| anyhow!("invalid kij"))?;
+
+    if complaint.proof.len() != 64 {
+        return Err(anyhow!("invalid proof length {}", complaint.proof.len()));
+    }
+    let (c, z) = complaint.proof.split_at(32);
+
+    Ok(ShareInfoDispute {
+        share: Share {
+            scalar,
+            index: usize::try_from(complaint.share_index)?,
+        },
+        kij: kij.into(),
+        proof: (c.try_into()?, z.try_into()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::identity::{IdentityKey, TrafficAuth, TrafficKind};
+
+    /// the traffic authenticators of alice, the accuser, and bob, the accused
+    fn party_auths() -> Vec<TrafficAuth> {
+        let uids = vec!["alice".to_owned(), "bob".to_owned()];
+        let keys: Vec<_> = (0..uids.len())
+            .map(|i| IdentityKey::from_key_material(&[i as u8 + 1; 64]))
+            .collect();
+        let public_keys: Vec<_> = keys
+            .iter()
+            .map(|key| key.public_key().to_compressed().to_vec())
+            .collect();
+        keys.into_iter()
+            .enumerate()
+            .map(|(i, key)| TrafficAuth::new("session", &uids, i, key, &public_keys).unwrap())
+            .collect()
+    }
+
+    /// the p2p of round 1 in which bob deals `share` to share 1 of alice, encrypted under `kij`
+    fn dealing(bob: &TrafficAuth, share: Scalar, kij: &G1Projective) -> proto::EncryptedShare {
+        let payload = serde_json::to_vec(&P2pShare {
+            encrypted_u_i_share: Share {
+                scalar: share + share_mask(kij, 1),
+                index: 1,
+            },
+        })
+        .unwrap();
+        let share_ids = ShareIds::new(0, 1);
+        let sealed = bob.seal(0, "1", &payload).unwrap();
+        let signature = bob.sign("1", TrafficKind::P2p, "alice", share_ids, &sealed);
+        proto::EncryptedShare::new(
+            "session",
+            "1",
+            share_ids,
+            &sealed,
+            &signature,
+            bob.p2p_key_out(0).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_verify_dispute() {
+        // dealer's polynomial f(x) = 7 + 3x, threshold 1
+        let coeffs = [Scalar::from(7), Scalar::from(3)];
+        let commitments: Vec<_> = coeffs
+            .iter()
+            .map(|a| G1Projective::generator() * a)
+            .collect();
+        let f = |index: usize| coeffs[0] + coeffs[1] * bls::share_point(index);
+
+        let (accuser_secret, accused_secret) = (Scalar::from(11), Scalar::from(13));
+        let accuser_key = G1Affine::from(G1Projective::generator() * accuser_secret);
+        let accused_key = G1Affine::from(G1Projective::generator() * accused_secret);
+        let kij = G1Projective::from(accused_key) * accuser_secret;
+
+        let auths = party_auths();
+        let accused_identity_key = *IdentityKey::from_key_material(&[2; 64]).public_key();
+
+        let complaint = |share: Scalar, secret: Scalar, dealing: proto::EncryptedShare| {
+            let proof = DleqProof::prove(
+                &secret,
+                &G1Projective::generator(),
+                &G1Projective::from(accused_key),
+            )
+            .to_bytes();
+            proto::Complaint {
+                accuser_uid: "alice".to_string(),
+                accused_uid: "bob".to_string(),
+                round: 2,
+                share_index: 1,
+                share: share.to_bytes().to_vec(),
+                kij: G1Affine::from(kij).to_compressed().to_vec(),
+                proof: proof.to_vec(),
+                encrypted_share: Some(dealing),
+            }
+        };
+        let verdict = |complaint: &proto::Complaint| {
+            let dispute = decode_dispute(complaint).unwrap();
+            verify_dispute(
+                complaint,
+                &dispute,
+                &commitments,
+                &accuser_key,
+                &accused_key,
+                &accused_identity_key,
+            )
+        };
+
+        let bad_share = f(1) + Scalar::one();
+        let bad_dealing = dealing(&auths[1], bad_share, &kij);
+        let good_dealing = dealing(&auths[1], f(1), &kij);
+
+        // the accused dealt a bad share
+        assert_eq!(
+            verdict(&complaint(bad_share, accuser_secret, bad_dealing.clone())),
+            Verdict::AccusedAtFault
+        );
+
+        // the accuser complained about a good share
+        assert_eq!(
+            verdict(&complaint(f(1), accuser_secret, good_dealing.clone())),
+            Verdict::AccuserAtFault
+        );
+
+        // the accuser swapped in a fake share for the good share it was dealt
+        assert_eq!(
+            verdict(&complaint(bad_share, accuser_secret, good_dealing.clone())),
+            Verdict::AccuserAtFault
+        );
+
+        // the accuser can't prove `kij`
+        assert_eq!(
+            verdict(&complaint(bad_share, Scalar::from(17), bad_dealing.clone())),
+            Verdict::AccuserAtFault
+        );
+
+        // the accuser forged the dealing of a bad share
+        let forged = dealing(&auths[0], bad_share, &kij);
+        assert_eq!(
+            verdict(&complaint(bad_share, accuser_secret, forged)),
+            Verdict::AccuserAtFault
+        );
+
+        // the dealing of another session, round or share, or with a wrong key
+        let mut tampered = bad_dealing.clone();
+        tampered.session_id = "other session".to_string();
+        assert_eq!(
+            verdict(&complaint(bad_share, accuser_secret, tampered)),
+            Verdict::AccuserAtFault
+        );
+        let mut tampered = bad_dealing.clone();
+        tampered.round_num = "2".to_string();
+        assert_eq!(
+            verdict(&complaint(bad_share, accuser_secret, tampered)),
+            Verdict::AccuserAtFault
+        );
+        let mut tampered = bad_dealing.clone();
+        tampered.to_share_id = 2;
+        assert_eq!(
+            verdict(&complaint(bad_share, accuser_secret, tampered)),
+            Verdict::AccuserAtFault
+        );
+        let mut tampered = bad_dealing.clone();
+        tampered.p2p_key = vec![0; 32];
+        assert_eq!(
+            verdict(&complaint(bad_share, accuser_secret, tampered)),
+            Verdict::AccuserAtFault
+        );
+
+        // complaints without a dealing can't convict the accused
+        let mut undealt = complaint(bad_share, accuser_secret, bad_dealing);
+        undealt.encrypted_share = None;
+        assert_eq!(verdict(&undealt), Verdict::AccuserAtFault);
+
+        // malformed complaints are rejected
+        let complaint = proto::Complaint {
+            kij: vec![0; 48],
+            ..Default::default()
+        };
+        assert!(decode_dispute(&complaint).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Echo of broadcasts, to detect parties that send different broadcasts to different parties.
+//!
+//! The relayer of a session is not trusted to deliver the same broadcasts to all parties.
+//! If echoes are enabled, each party broadcasts an [Echo] of the broadcasts it received after each broadcast round,
+//! and the round is executed only if the echoes of all parties agree with its own view.
+//! An [Echo] contains the digest of each broadcast along with the signature of its sender.
+//! Hence, an echoed broadcast that is not ours proves that its sender equivocated, unless its signature
+//! is invalid, in which case the party that echoed it lied.
+
+use super::identity::{payload_digest, TrafficAuth};
+use serde::{Deserialize, Serialize};
+use tofn::sdk::api::Fault;
+
+// error handling
+use crate::TofndResult;
+
+/// a broadcast as seen by the party that received it
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct SignedDigest {
+    share_id: usize, // the share of the sender that sent the broadcast
+    digest: [u8; 32],
+    signature: Vec<u8>,
+}
+
+/// The broadcasts that a party received in a round
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct Echo {
+    bcasts: Vec<Vec<SignedDigest>>, // sorted broadcasts of each party; alligned with party uids
+}
+
+impl Echo {
+    pub(super) fn new(party_count: usize) -> Self {
+        Self {
+            bcasts: vec![vec![]; party_count],
+        }
+    }
+
+    /// record a broadcast of share `share_id` of party `from`
+    pub(super) fn record(
+        &mut self,
+        from: usize,
+        share_id: usize,
+        payload: &[u8],
+        signature: &[u8],
+    ) {
+        let bcast = SignedDigest {
+            share_id,
+            digest: payload_digest(payload),
+            signature: signature.to_vec(),
+        };
+        let bcasts = &mut self.bcasts[from];
+        if let Err(pos) = bcasts.binary_search(&bcast) {
+            bcasts.insert(pos, bcast);
+        }
+    }
+
+    pub(super) fn to_bytes(&self) -> TofndResult<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub(super) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+
+    /// Compare the echo of party `echoer` for round `round_num` with ours.
+    /// Returns the parties that are to blame for a mismatch: the parties that signed broadcasts other than the ones we received,
+    /// or `echoer` if it echoed a malformed echo or a broadcast that was not signed by its sender.
+    pub(super) fn check(
+        &self,
+        echoer: usize,
+        echo: &Echo,
+        round_num: &str,
+        traffic_auth: &TrafficAuth,
+    ) -> Vec<(usize, Fault)> {
+        if echo.bcasts.len() != self.bcasts.len() {
+            return vec![(echoer, Fault::CorruptedMessage)];
+        }
+
+        let mut equivocators = vec![];
+        for (from, (ours, theirs)) in self.bcasts.iter().zip(&echo.bcasts).enumerate() {
+            // a broadcast we did not receive at all is missing, not equivocated
+            if ours.is_empty() {
+                continue;
+            }
+            for bcast in theirs.iter().filter(|bcast| !ours.contains(bcast)) {
+                if !traffic_auth.verify_bcast(
+                    from,
+                    bcast.share_id,
+                    round_num,
+                    &bcast.digest,
+                    &bcast.signature,
+                ) {
+                    return vec![(echoer, Fault::CorruptedMessage)];
+                }
+                equivocators.push((from, Fault::ProtocolFault));
+            }
+        }
+
+        equivocators.dedup_by_key(|(from, _)| *from);
+        equivocators
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::identity::{IdentityKey, ShareIds, TrafficKind};
+
+    fn party_auths(count: usize) -> Vec<TrafficAuth> {
+        let uids: Vec<String> = (0..count).map(|i| format!("party_{}", i)).collect();
+        let keys: Vec<_> = (0..count)
+            .map(|i| IdentityKey::from_key_material(&[i as u8 + 1; 64]))
+            .collect();
+        let public_keys: Vec<_> = keys
+            .iter()
+            .map(|key| key.public_key().to_compressed().to_vec())
+            .collect();
+        keys.into_iter()
+            .enumerate()
+            .map(|(i, key)| TrafficAuth::new("session", &uids, i, key, &public_keys).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_echo() {
+        let auths = party_auths(3);
+        let sign = |from: usize, payload: &[u8]| {
+            auths[from].sign("1", TrafficKind::Bcast, "", ShareIds::default(), payload)
+        };
+
+        // parties 1 and 2 got the same bcast from party 0
+        let mut echo_1 = Echo::new(3);
+        let mut echo_2 = Echo::new(3);
+        for echo in [&mut echo_1, &mut echo_2] {
+            echo.record(0, 0, b"bcast", &sign(0, b"bcast"));
+            echo.record(1, 0, b"bcast 1", &sign(1, b"bcast 1"));
+        }
+        assert!(echo_1.check(2, &echo_2, "1", &auths[1]).is_empty());
+
+        // echoes survive encoding
+        let decoded = Echo::from_bytes(&echo_2.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, echo_2);
+        assert!(Echo::from_bytes(b"garbage").is_none());
+
+        // party 0 sent a different bcast to party 2
+        let mut echo_2 = Echo::new(3);
+        echo_2.record(0, 0, b"other bcast", &sign(0, b"other bcast"));
+        echo_2.record(1, 0, b"bcast 1", &sign(1, b"bcast 1"));
+        assert_eq!(
+            echo_1.check(2, &echo_2, "1", &auths[1]),
+            vec![(0, Fault::ProtocolFault)]
+        );
+
+        // party 2 made up a bcast of party 0
+        let mut echo_2 = Echo::new(3);
+        echo_2.record(0, 0, b"other bcast", &sign(2, b"other bcast"));
+        assert_eq!(
+            echo_1.check(2, &echo_2, "1", &auths[1]),
+            vec![(2, Fault::CorruptedMessage)]
+        );
+
+        // echo of the wrong number of parties
+        assert_eq!(
+            echo_1.check(2, &Echo::new(2), "1", &auths[1]),
+            vec![(2, Fault::CorruptedMessage)]
+        );
+    }
+}
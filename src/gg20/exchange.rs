@@ -0,0 +1,389 @@
+//! Rounds that tofnd runs by itself, without tofn, such as the rounds of [crate::gg20::refresh].
+//!
+//! Traffic is authenticated and p2ps are encrypted with [TrafficAuth], like the traffic of tofn's protocols,
+//! and is transported either by the client or directly between daemons; see [crate::gg20::peer].
+//! Each party sends at most one bcast and one p2p to each other party per round, and not all parties send in every round.
+//! Our own contributions never leave tofnd, so traffic that claims to come from us is ignored.
+
+use super::{
+    broadcast::{broadcast_messages, ShareSenders},
+    identity::{ShareIds, TrafficAuth, TrafficKind},
+    peer::PeerRouter,
+    proto,
+    protocol::{msg_round, DeliveredMessages, Delivery, FaultRecord, Inbox, ProtocolReport},
+    service::Gg20Service,
+    ProtocolCommunication,
+};
+use proto::message_out::criminal_list::criminal::FaultKind;
+use std::{cmp::Ordering, time::Duration};
+use tofn::{
+    collections::{FillVecMap, TypedUsize},
+    gg20::keygen::KeygenPartyId,
+    sdk::api::Fault,
+};
+
+// tonic cruft
+use tokio::sync::mpsc;
+use tokio::time::{timeout_at, Instant};
+use tonic::Status;
+
+// logging
+use tracing::{debug, error, warn, Span};
+
+// error handling
+use crate::TofndResult;
+
+pub(super) type Faulters = FillVecMap<KeygenPartyId, Fault>;
+
+/// The messages that the other parties sent in a round; aligned with party uids.
+/// Our own entries and the entries of parties that faulted are `None`.
+pub(super) struct RoundMsgs {
+    pub(super) bcasts: Vec<Option<Vec<u8>>>,
+    pub(super) p2ps: Vec<Option<Vec<u8>>>,
+}
+
+/// Sends and receives the messages of a session
+pub(super) struct Exchange {
+    chans:
+        ProtocolCommunication<Option<proto::TrafficIn>, Result<proto::MessageOut, tonic::Status>>,
+    party_uids: Vec<String>,
+    my_index: usize,
+    traffic_auth: TrafficAuth,
+    round_timeout: Duration,
+    inbox: Inbox,
+    pub(super) report: ProtocolReport,
+}
+
+impl Gg20Service {
+    /// Open the [Exchange] of session `session_id`; traffic is transported like the traffic of keygen.
+    /// In direct mode, outgoing traffic is routed to the peers at `party_addresses` instead of the client,
+    /// and incoming traffic comes from the peers; the client stream is only watched for an abort.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn open_exchange(
+        &self,
+        mut stream_in: tonic::Streaming<proto::MessageIn>,
+        stream_out_sender: mpsc::UnboundedSender<Result<proto::MessageOut, Status>>,
+        session_id: &str,
+        party_uids: &[String],
+        my_index: usize,
+        party_addresses: &[String],
+        traffic_auth: TrafficAuth,
+        last_round: usize,
+        round_timeout: Option<Duration>,
+        span: Span,
+    ) -> TofndResult<Exchange> {
+        let peer_addresses = self.peer_addresses(party_uids, my_index, party_addresses)?;
+        let protocol_sender = match &peer_addresses {
+            Some(addresses) => {
+                let router = PeerRouter::new(
+                    self.peer_sessions.clone(),
+                    session_id,
+                    party_uids,
+                    my_index,
+                    addresses,
+                    span.clone(),
+                )?;
+                let (protocol_sender, protocol_receiver) = mpsc::unbounded_channel();
+                tokio::spawn(router.run(protocol_receiver, stream_out_sender));
+                protocol_sender
+            }
+            None => stream_out_sender,
+        };
+
+        let (traffic_sender, traffic_receiver) = mpsc::unbounded_channel();
+        let mut traffic_senders = ShareSenders::party(traffic_sender);
+        let session = peer_addresses.map(|_| {
+            self.peer_sessions
+                .register(session_id, std::mem::take(&mut traffic_senders))
+        });
+        tokio::spawn(async move {
+            broadcast_messages(&mut stream_in, vec![traffic_senders], span).await;
+            drop(session);
+        });
+
+        Ok(Exchange::new(
+            ProtocolCommunication::new(traffic_receiver, protocol_sender),
+            party_uids,
+            my_index,
+            traffic_auth,
+            last_round,
+            round_timeout.unwrap_or(self.cfg.round_timeout),
+        ))
+    }
+}
+
+impl Exchange {
+    pub(super) fn new(
+        chans: ProtocolCommunication<
+            Option<proto::TrafficIn>,
+            Result<proto::MessageOut, tonic::Status>,
+        >,
+        party_uids: &[String],
+        my_index: usize,
+        traffic_auth: TrafficAuth,
+        last_round: usize,
+        round_timeout: Duration,
+    ) -> Self {
+        Self {
+            chans,
+            party_uids: party_uids.to_vec(),
+            my_index,
+            traffic_auth,
+            round_timeout,
+            inbox: Inbox::new(last_round),
+            report: ProtocolReport::default(),
+        }
+    }
+
+    pub(super) fn party_uids(&self) -> &[String] {
+        &self.party_uids
+    }
+
+    pub(super) fn my_index(&self) -> usize {
+        self.my_index
+    }
+
+    /// send a signed bcast of round `round` to all other parties
+    pub(super) fn send_bcast(&self, round: usize, payload: &[u8]) -> TofndResult<()> {
+        let round_num = round.to_string();
+        let signature = self.traffic_auth.sign(
+            &round_num,
+            TrafficKind::Bcast,
+            "",
+            ShareIds::default(),
+            payload,
+        );
+        self.chans.sender.send(Ok(proto::MessageOut::new_bcast(
+            payload,
+            &round_num,
+            ShareIds::default(),
+            signature,
+        )))?;
+        Ok(())
+    }
+
+    /// send a signed p2p of round `round` to party `to`, encrypted for its recipient
+    pub(super) fn send_p2p(&self, round: usize, to: usize, payload: &[u8]) -> TofndResult<()> {
+        let round_num = round.to_string();
+        let to_party_uid = &self.party_uids[to];
+        let payload = self.traffic_auth.seal(to, &round_num, payload)?;
+        let signature = self.traffic_auth.sign(
+            &round_num,
+            TrafficKind::P2p,
+            to_party_uid,
+            ShareIds::default(),
+            &payload,
+        );
+        self.chans.sender.send(Ok(proto::MessageOut::new_p2p(
+            to_party_uid,
+            &payload,
+            &round_num,
+            ShareIds::default(),
+            signature,
+        )))?;
+        Ok(())
+    }
+
+    /// Receive the bcasts and/or p2ps of round `round` from the parties `senders`, until they all arrived or the round's deadline passes.
+    /// Messages of future rounds are buffered; messages of past rounds and duplicates are dropped.
+    /// Returns the messages along with the parties that equivocated, sent messages that were not signed by their identity key
+    /// or p2ps that can't be decrypted, and the parties whose messages did not arrive.
+    pub(super) async fn receive(
+        &mut self,
+        round: usize,
+        senders: &[usize],
+        expect_bcasts: bool,
+        expect_p2ps: bool,
+    ) -> TofndResult<(RoundMsgs, Faulters)> {
+        let party_count = self.party_uids.len();
+        let mut msgs = RoundMsgs {
+            bcasts: vec![None; party_count],
+            p2ps: vec![None; party_count],
+        };
+        let mut faulters = Faulters::with_size(party_count);
+        let mut delivered_msgs = DeliveredMessages::new();
+
+        let deadline = Instant::now() + self.round_timeout;
+        let mut buffered_msgs = self.inbox.take(round).into_iter();
+
+        // a party is done once it delivered all messages of the round or faulted
+        let my_index = self.my_index;
+        let done = |from: usize, msgs: &RoundMsgs, faulters: &Faulters| {
+            from == my_index
+                || !senders.contains(&from)
+                || is_faulter(faulters, from)
+                || ((!expect_bcasts || msgs.bcasts[from].is_some())
+                    && (!expect_p2ps || msgs.p2ps[from].is_some()))
+        };
+
+        let mut timed_out = false;
+        while !(0..party_count).all(|from| done(from, &msgs, &faulters)) {
+            let traffic = match buffered_msgs.next() {
+                Some(traffic) => traffic,
+                None => match timeout_at(deadline, self.chans.receiver.recv()).await {
+                    Ok(Some(Some(traffic))) => traffic,
+                    Ok(Some(None)) => {
+                        warn!("ignore incoming msg: missing `data` field");
+                        continue;
+                    }
+                    Ok(None) => {
+                        error!("{}: internal channel closed prematurely", round);
+                        break;
+                    }
+                    Err(_) => {
+                        warn!(
+                            "round {} timed out after {:?}; aborting",
+                            round, self.round_timeout
+                        );
+                        timed_out = true;
+                        break;
+                    }
+                },
+            };
+
+            // check the round of the message
+            let msg_round = match msg_round(&traffic) {
+                Some(msg_round) if !traffic.is_echo => msg_round,
+                _ => {
+                    warn!(
+                        "ignore incoming msg from {}: unexpected round {:?}",
+                        traffic.from_party_uid, traffic.round_num
+                    );
+                    continue;
+                }
+            };
+            match msg_round.cmp(&round) {
+                Ordering::Less => {
+                    warn!(
+                        "ignore incoming msg from {}: round {} is over, current round is {}",
+                        traffic.from_party_uid, msg_round, round
+                    );
+                    continue;
+                }
+                Ordering::Greater => {
+                    debug!(
+                        "buffer incoming msg from {} for round {}, current round is {}",
+                        traffic.from_party_uid, msg_round, round
+                    );
+                    self.inbox.buffer(msg_round, traffic, &self.party_uids);
+                    continue;
+                }
+                Ordering::Equal => {}
+            }
+
+            // get sender's party index; we don't need our own messages
+            let from = match self
+                .party_uids
+                .iter()
+                .position(|uid| uid == &traffic.from_party_uid)
+            {
+                Some(from) if from != self.my_index && senders.contains(&from) => from,
+                Some(_) => continue,
+                None => {
+                    warn!(
+                        "ignore incoming msg: unknown sender {}",
+                        traffic.from_party_uid
+                    );
+                    continue;
+                }
+            };
+            if is_faulter(&faulters, from) {
+                continue;
+            }
+
+            // messages must be signed by the identity key of their sender
+            if !self.traffic_auth.verify(from, &traffic) {
+                warn!(
+                    "invalid signature of incoming msg from {} in round {}",
+                    traffic.from_party_uid, round
+                );
+                self.report.set_fault(
+                    &mut faulters,
+                    from,
+                    FaultRecord::new(round, FaultKind::BadSignature, "invalid signature"),
+                )?;
+                continue;
+            }
+
+            // each party delivers one message of each kind per round
+            match delivered_msgs.check(from, traffic.is_broadcast, &traffic.payload, 1) {
+                Delivery::New => {}
+                Delivery::Duplicate => continue,
+                Delivery::Equivocation => {
+                    let kind = if traffic.is_broadcast { "bcast" } else { "p2p" };
+                    warn!(
+                        "party {} sent conflicting {} msgs in round {}",
+                        traffic.from_party_uid, kind, round
+                    );
+                    self.report.set_fault(
+                        &mut faulters,
+                        from,
+                        FaultRecord::new(
+                            round,
+                            FaultKind::Equivocation,
+                            format!("sent conflicting {} msgs", kind),
+                        ),
+                    )?;
+                    msgs.bcasts[from] = None;
+                    msgs.p2ps[from] = None;
+                    continue;
+                }
+            }
+
+            if traffic.is_broadcast {
+                msgs.bcasts[from] = Some(traffic.payload);
+                continue;
+            }
+
+            // p2ps are encrypted for us by their sender
+            match self
+                .traffic_auth
+                .open(from, &traffic.round_num, &traffic.payload)
+            {
+                Some(payload) => msgs.p2ps[from] = Some(payload),
+                None => {
+                    warn!(
+                        "unable to decrypt incoming p2p msg from {} in round {}",
+                        traffic.from_party_uid, round
+                    );
+                    self.report.set_fault(
+                        &mut faulters,
+                        from,
+                        FaultRecord::new(round, FaultKind::Corrupted, "undecryptable p2p msg"),
+                    )?;
+                }
+            }
+        }
+
+        // parties that did not deliver all messages of the round are faulters
+        for from in 0..party_count {
+            if done(from, &msgs, &faulters) {
+                continue;
+            }
+            let record = match timed_out {
+                true => FaultRecord::new(
+                    round,
+                    FaultKind::Timeout,
+                    format!("no message before the deadline of {:?}", self.round_timeout),
+                ),
+                false => FaultRecord::new(round, FaultKind::Missing, "missing message"),
+            };
+            self.report.set_fault(&mut faulters, from, record)?;
+        }
+
+        // messages of faulters are not used
+        for (from, _) in faulters.iter_some() {
+            msgs.bcasts[from.as_usize()] = None;
+            msgs.p2ps[from.as_usize()] = None;
+        }
+
+        Ok((msgs, faulters))
+    }
+}
+
+/// whether party `from` faulted this round
+fn is_faulter(faulters: &Faulters, from: usize) -> bool {
+    !faulters
+        .is_none(TypedUsize::from_usize(from))
+        .unwrap_or(true)
+}
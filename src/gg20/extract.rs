@@ -0,0 +1,102 @@
+//! This module handles the extract gRPC.
+//! Request includes a `key_uid` and an arbitrary identity. For threshold identity-based encryption,
+//! each party publishes the identity key share `x_i * H(identity)` of all of its shares stored under `key_uid`.
+//! Every key share comes with a DLEQ proof that it was computed with the same secret as the share's
+//! public key `x_i * g1`, so the client can reject invalid key shares before combining `threshold + 1` of them.
+
+use super::{bls, delete_key::check_key_uid, proto, service::Gg20Service, types::PartyInfo};
+use bls12_381::G1Projective;
+
+// logging
+use tracing::info;
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::convert::{TryFrom, TryInto};
+
+impl Gg20Service {
+    pub(super) async fn handle_extract(
+        &self,
+        request: proto::ExtractRequest,
+    ) -> TofndResult<Vec<proto::IdentityKeyShare>> {
+        // get party's shares from kv store
+        check_key_uid(&request.key_uid)?;
+        let party_info: PartyInfo = self
+            .kv_manager
+            .kv()
+            .get(&request.key_uid)
+            .await
+            .map_err(|err| anyhow!("could not find key {}: {}", request.key_uid, err))?
+            .try_into()?;
+
+        info!(
+            "Extracting identity key shares from {} shares of key {}",
+            party_info.shares.len(),
+            request.key_uid
+        );
+
+        let identity_point = bls::hash_to_g2(&request.identity, bls::IDENTITY_DST);
+
+        // compute an identity key share and its proof for each of our shares
+        party_info
+            .shares
+            .iter()
+            .map(|share| {
+                let key_share = bls::extract_identity_key(share.x_i(), &request.identity);
+                let proof =
+                    bls::DleqProof::prove(share.x_i(), &G1Projective::generator(), &identity_point);
+                Ok(proto::IdentityKeyShare {
+                    share_index: u32::try_from(share.index())?,
+                    key_share: key_share.to_compressed().to_vec(),
+                    proof: proof.to_bytes().to_vec(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::test_utils::{store_key, test_service};
+    use bls12_381::{G2Affine, G2Projective, Scalar};
+    use testdir::testdir;
+    use tracing_test::traced_test;
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_extract() {
+        let service = test_service(testdir!()).await;
+        let poly = store_key(&service, "key", Scalar::from(7)).await;
+        let identity = b"identity".to_vec();
+        let request = |key_uid: &str| proto::ExtractRequest {
+            key_uid: key_uid.to_owned(),
+            identity: identity.clone(),
+        };
+
+        let key_shares = service.handle_extract(request("key")).await.unwrap();
+        assert_eq!(key_shares.len(), 2);
+
+        let g = G1Projective::generator();
+        let h = bls::hash_to_g2(&identity, bls::IDENTITY_DST);
+        let commitments = poly.commitments();
+        for key_share in key_shares {
+            let index = key_share.share_index as usize;
+            let point = G2Projective::from(
+                G2Affine::from_compressed(key_share.key_share[..].try_into().unwrap()).unwrap(),
+            );
+            assert_eq!(point, h * poly.eval(index));
+
+            // the proof binds the key share to the public key of its share only
+            let proof = bls::DleqProof::from_bytes(&key_share.proof).unwrap();
+            let pub_key = bls::share_public_key(&commitments, index);
+            assert!(proof.verify(&g, &pub_key, &h, &point));
+            let other_pub_key = bls::share_public_key(&commitments, index + 2);
+            assert!(!proof.verify(&g, &other_pub_key, &h, &point));
+        }
+
+        assert!(service.handle_extract(request("unknown")).await.is_err());
+    }
+}
@@ -0,0 +1,664 @@
+//! Long-term identity keys of tofnd, used to authenticate protocol traffic.
+//! The identity key is a BLS12-381 key that is derived from the mnemonic seed, so it survives recovery.
+//! Its public key in G1 is published with the get_identity gRPC, and the client distributes the
+//! identity keys of all parties in [proto::KeygenInit]. Every outgoing message is signed with
+//! the identity key, and incoming messages are verified against the identity key of their claimed sender.
+//!
+//! P2p payloads are additionally encrypted with [XChaCha20Poly1305], so that only the recipient's tofnd can read them.
+//! The cipher key of each (sender, recipient) pair is derived from the static Diffie-Hellman secret of their identity keys,
+//! the session id and the uids of the pair. Encrypted payloads are <ciphertext, nonce>, with a new random nonce per message.
+
+use super::{bls, proto, service::Gg20Service};
+use bls12_381::{G1Affine, G2Affine, Scalar};
+use chacha20poly1305::aead::{AeadInPlace, NewAead};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::convert::TryInto;
+
+/// domain of the identity key material that is derived from the mnemonic seed
+const IDENTITY_KEY_DOMAIN: &[u8] = b"tofnd identity key";
+
+/// Domain separation tag of traffic signatures
+const TRAFFIC_DST: &[u8] = b"TOFND_TRAFFIC_BLS12381G2_XMD:SHA-256_SSWU_RO_";
+
+/// domain of the p2p cipher keys that are derived from Diffie-Hellman secrets
+const P2P_KEY_DOMAIN: &[u8] = b"tofnd p2p key";
+
+/// size of the nonce that is appended to encrypted p2p payloads
+const NONCE_SIZE: usize = 24;
+
+/// BLS identity key of a party
+#[derive(Clone)]
+pub(super) struct IdentityKey {
+    secret: Scalar,
+    public: G1Affine,
+}
+
+impl IdentityKey {
+    /// create an identity key from 64 bytes of key material
+    pub(super) fn from_key_material(bytes: &[u8; 64]) -> Self {
+        let secret = Scalar::from_bytes_wide(bytes);
+        let public = G1Affine::from(G1Affine::generator() * secret);
+        Self { secret, public }
+    }
+
+    pub(super) fn public_key(&self) -> &G1Affine {
+        &self.public
+    }
+
+    /// sign `msg` under [TRAFFIC_DST]
+    pub(super) fn sign(&self, msg: &[u8]) -> G2Affine {
+        (bls::hash_to_g2(msg, TRAFFIC_DST) * self.secret).into()
+    }
+
+    /// Diffie-Hellman secret with the owner of `public_key`
+    fn shared_secret(&self, public_key: &G1Affine) -> [u8; 48] {
+        G1Affine::from(public_key * self.secret).to_compressed()
+    }
+}
+
+/// encode `fields` unambiguously by prefixing each field with its length
+fn encode_fields(fields: &[&[u8]]) -> Vec<u8> {
+    let mut msg = Vec::new();
+    for field in fields {
+        msg.extend_from_slice(&(field.len() as u64).to_be_bytes());
+        msg.extend_from_slice(field);
+    }
+    msg
+}
+
+/// derive the key of the p2p cipher of messages from `from_party_uid` to `to_party_uid`
+fn p2p_key(
+    shared_secret: &[u8],
+    session_id: &str,
+    from_party_uid: &str,
+    to_party_uid: &str,
+) -> [u8; 32] {
+    Sha256::digest(&encode_fields(&[
+        P2P_KEY_DOMAIN,
+        shared_secret,
+        session_id.as_bytes(),
+        from_party_uid.as_bytes(),
+        to_party_uid.as_bytes(),
+    ]))
+    .into()
+}
+
+fn p2p_cipher(key: &[u8; 32]) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(Key::from_slice(key))
+}
+
+/// decrypt a p2p payload of round `round_num` with `cipher`
+fn open_with(cipher: &XChaCha20Poly1305, round_num: &str, payload: &[u8]) -> Option<Vec<u8>> {
+    if payload.len() < NONCE_SIZE {
+        return None;
+    }
+
+    let (ciphertext, nonce) = payload.split_at(payload.len() - NONCE_SIZE);
+    let mut plaintext = ciphertext.to_vec();
+    cipher
+        .decrypt_in_place(
+            XNonce::from_slice(nonce),
+            round_num.as_bytes(),
+            &mut plaintext,
+        )
+        .ok()?;
+    Some(plaintext)
+}
+
+/// Decrypt a p2p payload of round `round_num` with a revealed p2p key, see [TrafficAuth::p2p_key_in].
+pub(super) fn open_p2p(key: &[u8], round_num: &str, payload: &[u8]) -> Option<Vec<u8>> {
+    open_with(&p2p_cipher(key.try_into().ok()?), round_num, payload)
+}
+
+/// Encode the signed data of a message. Payloads are signed by their digest, so that echoes can carry signatures of broadcasts.
+fn traffic_msg(
+    session_id: &str,
+    from_party_uid: &str,
+    round_num: &str,
+    kind: TrafficKind,
+    to_party_uid: &str,
+    share_ids: ShareIds,
+    digest: &[u8; 32],
+) -> Vec<u8> {
+    encode_fields(&[
+        session_id.as_bytes(),
+        from_party_uid.as_bytes(),
+        &(share_ids.from as u64).to_be_bytes(),
+        round_num.as_bytes(),
+        &[kind.tag()],
+        to_party_uid.as_bytes(),
+        &(share_ids.to as u64).to_be_bytes(),
+        digest,
+    ])
+}
+
+/// Verify that the owner of `public_key` signed a p2p to `to_party_uid` in session `session_id`, without being part of the session.
+/// Used to settle disputes about the p2ps of a protocol.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn verify_p2p(
+    public_key: &G1Affine,
+    session_id: &str,
+    from_party_uid: &str,
+    round_num: &str,
+    to_party_uid: &str,
+    share_ids: ShareIds,
+    payload: &[u8],
+    signature: &[u8],
+) -> bool {
+    let msg = traffic_msg(
+        session_id,
+        from_party_uid,
+        round_num,
+        TrafficKind::P2p,
+        to_party_uid,
+        share_ids,
+        &payload_digest(payload),
+    );
+    verify(public_key, &msg, signature)
+}
+
+/// verify a signature of `msg` under [TRAFFIC_DST]
+fn verify(public_key: &G1Affine, msg: &[u8], signature: &[u8]) -> bool {
+    let signature: Option<G2Affine> = match signature.try_into() {
+        Ok(bytes) => G2Affine::from_compressed(bytes).into(),
+        Err(_) => return false,
+    };
+    match signature {
+        Some(signature) => bls::verify_partial(
+            public_key,
+            &bls::hash_to_g2(msg, TRAFFIC_DST).into(),
+            &signature,
+        ),
+        None => false,
+    }
+}
+
+/// decode a compressed identity public key; the identity point is rejected
+pub(super) fn decode_public_key(bytes: &[u8]) -> Option<G1Affine> {
+    let public_key: Option<G1Affine> = G1Affine::from_compressed(bytes.try_into().ok()?).into();
+    public_key.filter(|public_key| !bool::from(public_key.is_identity()))
+}
+
+/// Kind of a protocol message. Signatures of one kind of message are not valid for another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum TrafficKind {
+    P2p,
+    Bcast,
+    Echo,
+}
+
+impl TrafficKind {
+    pub(super) fn of(traffic: &proto::TrafficIn) -> Self {
+        match (traffic.is_broadcast, traffic.is_echo) {
+            (_, true) => Self::Echo,
+            (true, false) => Self::Bcast,
+            (false, false) => Self::P2p,
+        }
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            Self::P2p => 0,
+            Self::Bcast => 1,
+            Self::Echo => 2,
+        }
+    }
+}
+
+/// The shares that send and receive a message, numbered across all parties.
+/// Keygen runs one protocol per share, so its traffic is sent between shares;
+/// the traffic of the protocols that tofnd runs by itself is sent between parties, with zero share ids.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) struct ShareIds {
+    pub(super) from: usize,
+    pub(super) to: usize, // recipient share of a p2p; zero for broadcasts and echoes
+}
+
+impl ShareIds {
+    pub(super) fn new(from: usize, to: usize) -> Self {
+        Self { from, to }
+    }
+
+    pub(super) fn of(traffic: &proto::TrafficIn) -> Self {
+        Self::new(traffic.from_share_id as usize, traffic.to_share_id as usize)
+    }
+}
+
+/// digest of a message payload
+pub(super) fn payload_digest(payload: &[u8]) -> [u8; 32] {
+    Sha256::digest(payload).into()
+}
+
+/// Signs outgoing and verifies incoming traffic of a protocol session, and encrypts its p2p payloads
+#[derive(Clone)]
+pub struct TrafficAuth {
+    session_id: String,
+    party_uids: Vec<String>,
+    my_index: usize,
+    identity_key: IdentityKey,
+    party_identity_keys: Vec<G1Affine>, // aligned with `party_uids`
+    p2p_ciphers_out: Vec<XChaCha20Poly1305>, // ciphers of p2ps to each party; aligned with `party_uids`
+    p2p_ciphers_in: Vec<XChaCha20Poly1305>, // ciphers of p2ps from each party; aligned with `party_uids`
+}
+
+impl TrafficAuth {
+    /// Create an authenticator for session `session_id`.
+    /// Fails if the identity keys of the parties are not aligned with their uids,
+    /// or if my identity key is not the one the client advertised for me.
+    pub(super) fn new(
+        session_id: &str,
+        party_uids: &[String],
+        my_index: usize,
+        identity_key: IdentityKey,
+        party_identity_keys: &[Vec<u8>],
+    ) -> TofndResult<Self> {
+        if party_identity_keys.len() != party_uids.len() {
+            return Err(anyhow!(
+                "expected {} party identity keys, got {}",
+                party_uids.len(),
+                party_identity_keys.len()
+            ));
+        }
+
+        let party_identity_keys = party_identity_keys
+            .iter()
+            .zip(party_uids)
+            .map(|(key, uid)| {
+                decode_public_key(key)
+                    .ok_or_else(|| anyhow!("invalid identity key of party {}", uid))
+            })
+            .collect::<TofndResult<Vec<_>>>()?;
+
+        if party_identity_keys.get(my_index) != Some(identity_key.public_key()) {
+            return Err(anyhow!(
+                "identity key of party {} does not match our identity key",
+                my_index
+            ));
+        }
+
+        let my_uid = &party_uids[my_index];
+        let (p2p_ciphers_out, p2p_ciphers_in) = party_identity_keys
+            .iter()
+            .zip(party_uids)
+            .map(|(public_key, uid)| {
+                let shared_secret = identity_key.shared_secret(public_key);
+                (
+                    p2p_cipher(&p2p_key(&shared_secret, session_id, my_uid, uid)),
+                    p2p_cipher(&p2p_key(&shared_secret, session_id, uid, my_uid)),
+                )
+            })
+            .unzip();
+
+        Ok(Self {
+            session_id: session_id.to_owned(),
+            party_uids: party_uids.to_vec(),
+            my_index,
+            identity_key,
+            party_identity_keys,
+            p2p_ciphers_out,
+            p2p_ciphers_in,
+        })
+    }
+
+    pub(super) fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// uid of the party we are running the session as
+    pub(super) fn my_uid(&self) -> &str {
+        &self.party_uids[self.my_index]
+    }
+
+    /// index of the party we are running the session as
+    pub(super) fn my_index(&self) -> usize {
+        self.my_index
+    }
+
+    /// Encrypt a p2p payload of round `round_num` for party `to`.
+    pub(super) fn seal(&self, to: usize, round_num: &str, payload: &[u8]) -> TofndResult<Vec<u8>> {
+        let cipher = self
+            .p2p_ciphers_out
+            .get(to)
+            .ok_or_else(|| anyhow!("party index {} out of bounds", to))?;
+
+        let mut nonce = XNonce::default();
+        rand::thread_rng().fill_bytes(nonce.as_mut_slice());
+
+        let mut ciphertext = payload.to_vec();
+        cipher
+            .encrypt_in_place(&nonce, round_num.as_bytes(), &mut ciphertext)
+            .map_err(|err| anyhow!("could not encrypt p2p for party {}: {}", to, err))?;
+
+        ciphertext.extend_from_slice(&nonce);
+        Ok(ciphertext)
+    }
+
+    /// Decrypt a p2p payload of round `round_num` from party `from`.
+    /// Returns `None` if the payload was not encrypted for us by `from`, or if it was tampered with.
+    pub(super) fn open(&self, from: usize, round_num: &str, payload: &[u8]) -> Option<Vec<u8>> {
+        open_with(self.p2p_ciphers_in.get(from)?, round_num, payload)
+    }
+
+    /// The key of the p2ps from party `from` to us in this session.
+    /// Revealing it lets anyone read these p2ps, and no other traffic of the session.
+    pub(super) fn p2p_key_in(&self, from: usize) -> Option<[u8; 32]> {
+        let public_key = self.party_identity_keys.get(from)?;
+        Some(p2p_key(
+            &self.identity_key.shared_secret(public_key),
+            &self.session_id,
+            &self.party_uids[from],
+            self.my_uid(),
+        ))
+    }
+
+    /// The key of the p2ps from us to party `to` in this session, see [Self::p2p_key_in].
+    pub(super) fn p2p_key_out(&self, to: usize) -> Option<[u8; 32]> {
+        let public_key = self.party_identity_keys.get(to)?;
+        Some(p2p_key(
+            &self.identity_key.shared_secret(public_key),
+            &self.session_id,
+            self.my_uid(),
+            &self.party_uids[to],
+        ))
+    }
+
+    /// Sign an outgoing message. `to_party_uid` is empty for broadcasts and echoes.
+    pub(super) fn sign(
+        &self,
+        round_num: &str,
+        kind: TrafficKind,
+        to_party_uid: &str,
+        share_ids: ShareIds,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let msg = traffic_msg(
+            &self.session_id,
+            &self.party_uids[self.my_index],
+            round_num,
+            kind,
+            to_party_uid,
+            share_ids,
+            &payload_digest(payload),
+        );
+        self.identity_key.sign(&msg).to_compressed().to_vec()
+    }
+
+    /// Verify an incoming message that claims to come from party `from`.
+    pub(super) fn verify(&self, from: usize, traffic: &proto::TrafficIn) -> bool {
+        // p2ps must be addressed to us; other messages have no recipient
+        let kind = TrafficKind::of(traffic);
+        let to_party_uid = match kind {
+            TrafficKind::P2p => self.party_uids[self.my_index].as_str(),
+            TrafficKind::Bcast | TrafficKind::Echo => "",
+        };
+
+        self.verify_digest(
+            from,
+            &traffic.round_num,
+            kind,
+            to_party_uid,
+            ShareIds::of(traffic),
+            &payload_digest(&traffic.payload),
+            &traffic.signature,
+        )
+    }
+
+    /// Verify that share `from_share` of party `from` signed a broadcast of round `round_num` whose payload has digest `digest`.
+    /// Used to check the broadcasts that other parties echo.
+    pub(super) fn verify_bcast(
+        &self,
+        from: usize,
+        from_share: usize,
+        round_num: &str,
+        digest: &[u8; 32],
+        signature: &[u8],
+    ) -> bool {
+        self.verify_digest(
+            from,
+            round_num,
+            TrafficKind::Bcast,
+            "",
+            ShareIds::new(from_share, 0),
+            digest,
+            signature,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn verify_digest(
+        &self,
+        from: usize,
+        round_num: &str,
+        kind: TrafficKind,
+        to_party_uid: &str,
+        share_ids: ShareIds,
+        digest: &[u8; 32],
+        signature: &[u8],
+    ) -> bool {
+        let (from_party_uid, public_key) = match (
+            self.party_uids.get(from),
+            self.party_identity_keys.get(from),
+        ) {
+            (Some(uid), Some(public_key)) => (uid, public_key),
+            _ => return false,
+        };
+
+        let msg = traffic_msg(
+            &self.session_id,
+            from_party_uid,
+            round_num,
+            kind,
+            to_party_uid,
+            share_ids,
+            digest,
+        );
+        verify(public_key, &msg, signature)
+    }
+}
+
+impl Gg20Service {
+    /// get our identity key from the mnemonic seed
+    pub(super) async fn identity_key(&self) -> TofndResult<IdentityKey> {
+        let key_material = self.kv_manager.derive_key(IDENTITY_KEY_DOMAIN).await?;
+        Ok(IdentityKey::from_key_material(&key_material))
+    }
+
+    pub(super) async fn handle_get_identity(&self) -> TofndResult<Vec<u8>> {
+        Ok(self
+            .identity_key()
+            .await?
+            .public_key()
+            .to_compressed()
+            .to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn party_auths(session_id: &str) -> Vec<TrafficAuth> {
+        let uids = vec!["alice".to_owned(), "bob".to_owned()];
+        let keys: Vec<_> = (0..uids.len())
+            .map(|i| IdentityKey::from_key_material(&[i as u8 + 1; 64]))
+            .collect();
+        let public_keys: Vec<_> = keys
+            .iter()
+            .map(|key| key.public_key().to_compressed().to_vec())
+            .collect();
+        keys.into_iter()
+            .enumerate()
+            .map(|(i, key)| TrafficAuth::new(session_id, &uids, i, key, &public_keys).unwrap())
+            .collect()
+    }
+
+    fn traffic_in(
+        from: &str,
+        payload: &[u8],
+        is_broadcast: bool,
+        signature: Vec<u8>,
+    ) -> proto::TrafficIn {
+        proto::TrafficIn {
+            from_party_uid: from.to_owned(),
+            payload: payload.to_vec(),
+            is_broadcast,
+            round_num: "0".to_owned(),
+            signature,
+            is_echo: false,
+            from_share_id: 0,
+            to_share_id: 0,
+            key_index: 0,
+        }
+    }
+
+    #[test]
+    fn test_traffic_auth() {
+        let auths = party_auths("session");
+        let (alice, bob) = (&auths[0], &auths[1]);
+
+        // valid bcast and p2p
+        let sig = alice.sign("0", TrafficKind::Bcast, "", ShareIds::default(), b"bcast");
+        assert!(bob.verify(0, &traffic_in("alice", b"bcast", true, sig.clone())));
+        let p2p_sig = alice.sign("0", TrafficKind::P2p, "bob", ShareIds::default(), b"p2p");
+        assert!(bob.verify(0, &traffic_in("alice", b"p2p", false, p2p_sig)));
+
+        // impersonation of alice by bob's index
+        assert!(!alice.verify(1, &traffic_in("bob", b"bcast", true, sig.clone())));
+
+        // tampered payload and kind
+        assert!(!bob.verify(0, &traffic_in("alice", b"other", true, sig.clone())));
+        assert!(!bob.verify(0, &traffic_in("alice", b"bcast", false, sig.clone())));
+        let mut echo = traffic_in("alice", b"bcast", true, sig.clone());
+        echo.is_echo = true;
+        assert!(!bob.verify(0, &echo));
+
+        // bcast signatures can be checked by the digest of their payload
+        let digest = payload_digest(b"bcast");
+        assert!(bob.verify_bcast(0, 0, "0", &digest, &sig));
+        assert!(!bob.verify_bcast(0, 0, "1", &digest, &sig));
+        assert!(!bob.verify_bcast(1, 0, "0", &digest, &sig));
+
+        // p2p addressed to another party
+        let p2p_sig = alice.sign("0", TrafficKind::P2p, "carol", ShareIds::default(), b"p2p");
+        assert!(!bob.verify(0, &traffic_in("alice", b"p2p", false, p2p_sig)));
+
+        // signatures are bound to the shares that send and receive a message
+        let share_sig = alice.sign("0", TrafficKind::P2p, "bob", ShareIds::new(1, 2), b"p2p");
+        let mut share_p2p = traffic_in("alice", b"p2p", false, share_sig);
+        assert!(!bob.verify(0, &share_p2p));
+        share_p2p.from_share_id = 1;
+        share_p2p.to_share_id = 2;
+        assert!(bob.verify(0, &share_p2p));
+        share_p2p.to_share_id = 3;
+        assert!(!bob.verify(0, &share_p2p));
+        assert!(!bob.verify_bcast(0, 1, "0", &digest, &sig));
+
+        // replay in another session
+        let other_auths = party_auths("other session");
+        assert!(!other_auths[1].verify(0, &traffic_in("alice", b"bcast", true, sig)));
+
+        // malformed signature
+        assert!(!bob.verify(0, &traffic_in("alice", b"bcast", true, vec![0; 3])));
+    }
+
+    #[test]
+    fn test_p2p_encryption() {
+        let auths = party_auths("session");
+        let (alice, bob) = (&auths[0], &auths[1]);
+
+        let ciphertext = alice.seal(1, "0", b"p2p").unwrap();
+        assert_ne!(&ciphertext[..3], b"p2p");
+        assert_eq!(bob.open(0, "0", &ciphertext).unwrap(), b"p2p");
+
+        // nonces are fresh
+        assert_ne!(alice.seal(1, "0", b"p2p").unwrap(), ciphertext);
+
+        // wrong sender, recipient, round and session
+        assert!(bob.open(1, "0", &ciphertext).is_none());
+        assert!(alice.open(0, "0", &ciphertext).is_none());
+        assert!(bob.open(0, "1", &ciphertext).is_none());
+        let other_auths = party_auths("other session");
+        assert!(other_auths[1].open(0, "0", &ciphertext).is_none());
+
+        // tampered and truncated payloads
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert!(bob.open(0, "0", &tampered).is_none());
+        assert!(bob.open(0, "0", &ciphertext[..NONCE_SIZE - 1]).is_none());
+
+        // revealed p2p keys open the p2ps of one direction only
+        let key = bob.p2p_key_in(0).unwrap();
+        assert_eq!(alice.p2p_key_out(1).unwrap(), key);
+        assert_eq!(open_p2p(&key, "0", &ciphertext).unwrap(), b"p2p");
+        assert!(open_p2p(&alice.p2p_key_in(1).unwrap(), "0", &ciphertext).is_none());
+        assert!(open_p2p(&key[..31], "0", &ciphertext).is_none());
+    }
+
+    #[test]
+    fn test_verify_p2p() {
+        let auths = party_auths("session");
+        let alice = &auths[0];
+        let public_key = alice.identity_key.public_key();
+
+        let share_ids = ShareIds::new(0, 1);
+        let sig = alice.sign("1", TrafficKind::P2p, "bob", share_ids, b"p2p");
+        assert!(verify_p2p(
+            public_key, "session", "alice", "1", "bob", share_ids, b"p2p", &sig
+        ));
+
+        // the signature is bound to the session, round, parties, shares and payload
+        assert!(!verify_p2p(
+            public_key, "other", "alice", "1", "bob", share_ids, b"p2p", &sig
+        ));
+        assert!(!verify_p2p(
+            public_key, "session", "alice", "2", "bob", share_ids, b"p2p", &sig
+        ));
+        assert!(!verify_p2p(
+            public_key, "session", "alice", "1", "carol", share_ids, b"p2p", &sig
+        ));
+        assert!(!verify_p2p(
+            public_key,
+            "session",
+            "alice",
+            "1",
+            "bob",
+            ShareIds::new(0, 2),
+            b"p2p",
+            &sig
+        ));
+        assert!(!verify_p2p(
+            public_key, "session", "alice", "1", "bob", share_ids, b"other", &sig
+        ));
+        let bob_key = auths[1].identity_key.public_key();
+        assert!(!verify_p2p(
+            bob_key, "session", "alice", "1", "bob", share_ids, b"p2p", &sig
+        ));
+    }
+
+    #[test]
+    fn test_traffic_auth_new() {
+        let uids = vec!["alice".to_owned(), "bob".to_owned()];
+        let alice = IdentityKey::from_key_material(&[1; 64]);
+        let bob = IdentityKey::from_key_material(&[2; 64]);
+        let keys = vec![
+            alice.public_key().to_compressed().to_vec(),
+            bob.public_key().to_compressed().to_vec(),
+        ];
+
+        assert!(TrafficAuth::new("s", &uids, 0, alice.clone(), &keys).is_ok());
+        // my advertised key is not mine
+        assert!(TrafficAuth::new("s", &uids, 1, alice.clone(), &keys).is_err());
+        // misaligned keys
+        assert!(TrafficAuth::new("s", &uids, 0, alice.clone(), &keys[..1]).is_err());
+        // malformed key
+        let bad_keys = vec![keys[0].clone(), vec![0; 48]];
+        assert!(TrafficAuth::new("s", &uids, 0, alice, &bad_keys).is_err());
+    }
+}
@@ -1,36 +1,129 @@
 //! This module handles the key_presence gRPC.
-//! Request includes [proto::message_in::Data::KeyPresenceRequest] struct and encrypted recovery info.
-//! The recovery info is decrypted by party's mnemonic seed and saved in the KvStore.
+//! Request includes [proto::KeyPresenceRequest] struct with the uid of the key and, optionally, its group public key.
+//! A key is present if its shares are in the KvStore, its group public key matches the requested one,
+//! and the mnemonic that generated it is still in the KvStore, either as the latest or as a rotated mnemonic.
+//! Keys that were stored before tofnd recorded their mnemonic are reported as such.
 
-use super::{proto, service::Gg20Service};
+use super::{delete_key::check_key_uid, proto, service::Gg20Service, types::PartyInfo};
+use proto::key_presence_response::Response;
 
 // logging
-use tracing::info;
+use tracing::{info, warn};
 
 // error handling
 use crate::TofndResult;
 
+use std::convert::TryInto;
+
 impl Gg20Service {
     pub(super) async fn handle_key_presence(
         &self,
         request: proto::KeyPresenceRequest,
-    ) -> TofndResult<proto::key_presence_response::Response> {
-        // check if mnemonic is available
-        let _ = self.kv_manager.seed().await?;
-
+    ) -> TofndResult<Response> {
         // check if requested key exists
-        if self.kv_manager.kv().exists(&request.key_uid).await? {
+        check_key_uid(&request.key_uid)?;
+        if !self.kv_manager.kv().exists(&request.key_uid).await? {
             info!(
-                "Found session-id {} in kv store during key presence check",
+                "Did not find session-id {} in kv store during key presence check",
                 request.key_uid
             );
-            Ok(proto::key_presence_response::Response::Present)
-        } else {
-            info!(
-                "Did not find session-id {} in kv store during key presence check",
+            return Ok(Response::Absent);
+        }
+
+        let party_info: PartyInfo = self
+            .kv_manager
+            .kv()
+            .get(&request.key_uid)
+            .await?
+            .try_into()?;
+
+        // check if the stored key is the requested one
+        if !request.pub_key.is_empty() && request.pub_key != party_info.common.encoded_pubkey() {
+            warn!(
+                "Found session-id {} in kv store during key presence check, but with a different public key",
                 request.key_uid
             );
-            Ok(proto::key_presence_response::Response::Absent)
+            return Ok(Response::WrongPubKey);
         }
+
+        // keys of earlier releases don't record their mnemonic
+        if party_info.tofnd.seed_fingerprint.is_empty() {
+            warn!(
+                "Found session-id {} in kv store during key presence check, but it does not record its mnemonic",
+                request.key_uid
+            );
+            return Ok(Response::MnemonicUnknown);
+        }
+
+        // check if the mnemonic that generated the key is still available
+        let seed_key = self
+            .kv_manager
+            .find_seed_key(&party_info.tofnd.seed_fingerprint)
+            .await?;
+        match seed_key {
+            Some(seed_key) => {
+                info!(
+                    "Found session-id {} in kv store during key presence check, generated by mnemonic '{}'",
+                    request.key_uid, seed_key
+                );
+                Ok(Response::Present)
+            }
+            None => {
+                warn!(
+                    "Found session-id {} in kv store during key presence check, but its mnemonic is no longer available",
+                    request.key_uid
+                );
+                Ok(Response::MnemonicAbsent)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::{
+        test_utils::{party_info, put_key, store_key, test_service},
+        vss,
+    };
+    use bls12_381::Scalar;
+    use testdir::testdir;
+    use tracing_test::traced_test;
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_key_presence() {
+        let service = test_service(testdir!()).await;
+        let poly = store_key(&service, "key", Scalar::from(7)).await;
+        let pub_key = vss::encode_pub_key(&poly.commitments()).unwrap();
+        let presence = |key_uid: &str, pub_key: &[u8]| {
+            service.handle_key_presence(proto::KeyPresenceRequest {
+                key_uid: key_uid.to_owned(),
+                pub_key: pub_key.to_vec(),
+            })
+        };
+
+        assert_eq!(presence("key", &[]).await.unwrap(), Response::Present);
+        assert_eq!(presence("key", &pub_key).await.unwrap(), Response::Present);
+        assert_eq!(
+            presence("key", &[1; 48]).await.unwrap(),
+            Response::WrongPubKey
+        );
+        assert_eq!(presence("unknown", &[]).await.unwrap(), Response::Absent);
+
+        // keys that don't record their mnemonic, or record a mnemonic that is not in the kv store
+        put_key(&service, "legacy", party_info(&poly, vec![])).await;
+        assert_eq!(
+            presence("legacy", &[]).await.unwrap(),
+            Response::MnemonicUnknown
+        );
+        put_key(&service, "orphan", party_info(&poly, vec![1; 32])).await;
+        assert_eq!(
+            presence("orphan", &[]).await.unwrap(),
+            Response::MnemonicAbsent
+        );
+
+        // mnemonic records are not keys
+        assert!(presence("mnemonic", &[]).await.is_err());
     }
 }
@@ -1,5 +1,5 @@
 //! This module creates and executes the keygen protocol
-//! On success it returns [super::TofnKeygenOutput]. A successful [Keygen] can produce either an Ok(SecretKeyShare) of an Err(Vec<Vec<Crime>>).
+//! On success it returns [super::TofnKeygenOutput] along with the [ProtocolReport](crate::gg20::protocol::ProtocolReport) of the session. A successful [Keygen] can produce either an Ok(SecretKeyShare) of an Err(Vec<Vec<Crime>>).
 //! On failure it returns [anyhow!] error if [Keygen] struct cannot be instantiated.
 
 use super::{
@@ -20,6 +20,10 @@ use tracing::{info, Span};
 // error handling
 use anyhow::anyhow;
 
+/// the last round of tofn's keygen; its rounds r1 to r4 are numbered from 0.
+/// Messages of later rounds are never used, so they are not buffered.
+const LAST_ROUND: usize = 3;
+
 impl Gg20Service {
     /// create a new keygen.
     /// The field of Gg20Service `safe_keygen` dictates whether the new keygen will use big primes of not
@@ -32,8 +36,7 @@ impl Gg20Service {
         new_keygen(
             party_share_counts.total_share_count(),
             ctx.threshold,
-            ctx.tofnd_index,
-            // ctx.tofnd_subindex,
+            ctx.share_id,
             &ctx.party_keygen_data.encryption_keypair.clone(),
             #[cfg(feature = "malicious")]
             self.cfg.behaviours.keygen.clone(),
@@ -64,6 +67,11 @@ impl Gg20Service {
             chans,
             &ctx.uids,
             &ctx.share_counts,
+            ctx.share_id.as_usize(),
+            LAST_ROUND,
+            ctx.round_timeout,
+            ctx.echo_broadcasts,
+            &ctx.traffic_auth,
             execute_span.clone(),
         )
         .await;
@@ -17,17 +17,20 @@ use super::{
     types::{KeygenInitSanitized, MAX_PARTY_SHARE_COUNT, MAX_TOTAL_SHARE_COUNT},
     Gg20Service,
 };
+use crate::gg20::{delete_key::check_key_uid, identity::TrafficAuth, types::align_with_uids};
 use crate::kv_manager::KeyReservation;
+use std::time::Duration;
 
 impl Gg20Service {
     /// Receives a message from the stream and tries to handle keygen init operations.
-    /// On success, it reserves a key in the KVStrore and returns a sanitized struct ready to be used by the protocol.
+    /// On success, it reserves the keys of the session in the KVStrore and returns a sanitized struct ready to be used by the protocol,
+    /// along with the [TrafficAuth] that authenticates the traffic of each key.
     /// On failure, returns a [KeygenInitError] and no changes are been made in the KvStore.
     pub(super) async fn handle_keygen_init(
         &self,
         stream: &mut tonic::Streaming<proto::MessageIn>,
         keygen_span: Span,
-    ) -> TofndResult<(KeygenInitSanitized, KeyReservation)> {
+    ) -> TofndResult<(KeygenInitSanitized, Vec<TrafficAuth>, Vec<KeyReservation>)> {
         // try to receive message
 
         let msg = stream
@@ -52,15 +55,16 @@ impl Gg20Service {
                 ))
             }
         };
-        
+
         // try to process incoming message
-        let (keygen_init, key_reservation) = self.process_keygen_init(keygen_init).await?;
+        let (keygen_init, traffic_auths, key_reservations) =
+            self.process_keygen_init(keygen_init).await?;
 
         // log keygen init state
         keygen_init.log_info(keygen_span);
 
-        // return sanitized key and its KvStore reservation
-        Ok((keygen_init, key_reservation))
+        // return sanitized keys, their traffic authenticators and their KvStore reservations
+        Ok((keygen_init, traffic_auths, key_reservations))
     }
 
     // makes all needed assertions on incoming data, and create structures that are
@@ -68,21 +72,46 @@ impl Gg20Service {
     async fn process_keygen_init(
         &self,
         keygen_init: proto::KeygenInit,
-    ) -> TofndResult<(KeygenInitSanitized, KeyReservation)> {
+    ) -> TofndResult<(KeygenInitSanitized, Vec<TrafficAuth>, Vec<KeyReservation>)> {
         // try to sanitize arguments
         let keygen_init = Self::keygen_sanitize_args(keygen_init)
             .map_err(|err| anyhow!("failed to sanitize KeygenInit: {}", err))?;
 
-        // reserve key
-        let key_uid_reservation = self
-            .kv_manager
-            .kv()
-            .reserve_key(keygen_init.new_key_uid.clone())
-            .await
-            .map_err(|err| anyhow!("failed to reseve key: {}", err))?;
+        // check identity keys of parties before anything is reserved
+        // the traffic of each key is bound to the key's uid, so that messages can't be replayed across keys
+        let identity_key = self.identity_key().await?;
+        let traffic_auths = keygen_init
+            .key_uids()
+            .iter()
+            .map(|key_uid| {
+                TrafficAuth::new(
+                    key_uid,
+                    &keygen_init.party_uids,
+                    keygen_init.my_index,
+                    identity_key.clone(),
+                    &keygen_init.party_identity_keys,
+                )
+                .map_err(|err| anyhow!("failed to authenticate parties: {}", err))
+            })
+            .collect::<TofndResult<Vec<_>>>()?;
+
+        // reserve keys; either all keys are reserved or none of them is
+        let kv = self.kv_manager.kv();
+        let mut key_uid_reservations = Vec::with_capacity(keygen_init.key_uids().len());
+        for key_uid in keygen_init.key_uids() {
+            match kv.reserve_key(key_uid.clone()).await {
+                Ok(reservation) => key_uid_reservations.push(reservation),
+                Err(err) => {
+                    for reservation in key_uid_reservations {
+                        kv.unreserve_key(reservation).await;
+                    }
+                    return Err(anyhow!("failed to reseve key {}: {}", key_uid, err));
+                }
+            }
+        }
 
-        // return sanitized keygen init and key reservation
-        Ok((keygen_init, key_uid_reservation))
+        // return sanitized keygen init, traffic authenticators and key reservations
+        Ok((keygen_init, traffic_auths, key_uid_reservations))
     }
 
     /// This function is pub(crate) because it is also needed in handle_recover
@@ -103,7 +132,7 @@ impl Gg20Service {
     ) -> TofndResult<KeygenInitSanitized> {
         // convert `u32`s to `usize`s
         use std::convert::TryFrom;
-       // debug!("my index: -----------------> {:?}", args.my_party_index);
+        // debug!("my index: -----------------> {:?}", args.my_party_index);
         let my_index = usize::try_from(args.my_party_index)?;
         let threshold = usize::try_from(args.threshold)?;
         let mut party_share_counts = args
@@ -162,18 +191,61 @@ impl Gg20Service {
             ));
         }
 
+        // identity keys and addresses are optional here; keygen requires identity keys, but recover does not
+        let mut identity_keys =
+            align_with_uids(&args.party_uids, args.party_identity_keys, "identity keys")?;
+        let mut addresses = align_with_uids(&args.party_uids, args.party_addresses, "addresses")?;
+
         // sort uids and share counts
         // we need to sort uids and shares because the caller does not necessarily
         // send the same vectors (in terms of order) to all tofnd instances.
         let (my_new_index, sorted_uids, sorted_share_counts) =
             sort_uids_and_shares(my_index, args.party_uids, party_share_counts)?;
 
+        // keep identity keys and addresses alligned with the sorted uids; uids are unique after sorting
+        let sorted_identity_keys = sorted_uids
+            .iter()
+            .filter_map(|uid| identity_keys.remove(uid))
+            .collect();
+        let sorted_addresses = sorted_uids
+            .iter()
+            .filter_map(|uid| addresses.remove(uid))
+            .collect();
+
+        // keys can't take the uids of archived keys or mnemonics
+        check_key_uid(&args.new_key_uid)?;
+        for key_uid in &args.new_key_uids {
+            check_key_uid(key_uid)?;
+        }
+
+        // keys of a batch must be distinct
+        let mut key_uids = args.new_key_uids.clone();
+        key_uids.sort();
+        key_uids.dedup();
+        if key_uids.len() != args.new_key_uids.len() {
+            return Err(anyhow!(
+                "new_key_uids vector contained a duplicate: {:?}",
+                args.new_key_uids
+            ));
+        }
+
+        // a zero timeout falls back to the default of tofnd's config
+        let round_timeout = match args.round_timeout_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+
         Ok(KeygenInitSanitized {
             new_key_uid: args.new_key_uid,
             party_uids: sorted_uids,
             party_share_counts: sorted_share_counts,
             my_index: my_new_index,
             threshold,
+            round_timeout,
+            party_identity_keys: sorted_identity_keys,
+            party_addresses: sorted_addresses,
+            echo_broadcasts: args.echo_broadcasts,
+            new_key_uids: args.new_key_uids,
         })
     }
 }
@@ -254,6 +326,11 @@ mod tests {
             party_share_counts: vec![2, 1],                               // unsorted shares
             my_party_index: 1,                                            // index of "party_1"
             threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         let sanitized_keygen_init = KeygenInitSanitized {
             new_key_uid: "test_uid".to_owned(), // should be same as in raw keygen init
@@ -261,6 +338,11 @@ mod tests {
             party_share_counts: vec![1, 2], // shares should be sorted with respect to parties
             my_index: 0,                    // index should track "party_1" in the sorted party_uids
             threshold: 1,                   // threshold should be the same
+            round_timeout: None,            // zero timeout should fall back to the default
+            party_identity_keys: vec![],    // identity keys are optional
+            party_addresses: vec![],        // addresses are optional
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         let res = Gg20Service::keygen_sanitize_args(raw_keygen_init).unwrap();
         assert_eq!(&res.new_key_uid, &sanitized_keygen_init.new_key_uid);
@@ -271,6 +353,44 @@ mod tests {
         );
         assert_eq!(&res.my_index, &sanitized_keygen_init.my_index);
         assert_eq!(&res.threshold, &sanitized_keygen_init.threshold);
+        assert_eq!(&res.round_timeout, &sanitized_keygen_init.round_timeout);
+        assert_eq!(
+            &res.party_identity_keys,
+            &sanitized_keygen_init.party_identity_keys
+        );
+
+        // check round timeout
+        let raw_keygen_init = proto::KeygenInit {
+            new_key_uid: "test_uid".to_owned(),
+            party_uids: vec!["party_1".to_owned(), "party_2".to_owned()],
+            party_share_counts: vec![1, 1],
+            my_party_index: 0,
+            threshold: 1,
+            round_timeout_ms: 1500,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
+        };
+        let res = Gg20Service::keygen_sanitize_args(raw_keygen_init).unwrap();
+        assert_eq!(res.round_timeout, Some(Duration::from_millis(1500)));
+
+        // check sorting of identity keys and addresses
+        let raw_keygen_init = proto::KeygenInit {
+            new_key_uid: "test_uid".to_owned(),
+            party_uids: vec!["party_2".to_owned(), "party_1".to_owned()],
+            party_share_counts: vec![1, 1],
+            my_party_index: 0,
+            threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![vec![2], vec![1]],
+            party_addresses: vec!["addr_2".to_owned(), "addr_1".to_owned()],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
+        };
+        let res = Gg20Service::keygen_sanitize_args(raw_keygen_init).unwrap();
+        assert_eq!(res.party_identity_keys, vec![vec![1], vec![2]]);
+        assert_eq!(res.party_addresses, vec!["addr_1", "addr_2"]);
 
         // check empty share counts
         let raw_keygen_init = proto::KeygenInit {
@@ -279,6 +399,11 @@ mod tests {
             party_share_counts: vec![], // empty share counts; should default to [1, 1]
             my_party_index: 0,
             threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         let res = Gg20Service::keygen_sanitize_args(raw_keygen_init).unwrap();
         assert_eq!(&res.party_share_counts, &vec![1, 1]);
@@ -289,6 +414,11 @@ mod tests {
             party_share_counts: vec![MAX_PARTY_SHARE_COUNT as u32], // should be ok
             my_party_index: 0,
             threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         let res = Gg20Service::keygen_sanitize_args(raw_keygen_init).unwrap();
         assert_eq!(&res.party_share_counts, &vec![MAX_PARTY_SHARE_COUNT]);
@@ -299,6 +429,11 @@ mod tests {
             party_share_counts: vec![MAX_TOTAL_SHARE_COUNT as u32 - 1, 1], // should be ok
             my_party_index: 0,
             threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         let res = Gg20Service::keygen_sanitize_args(raw_keygen_init).unwrap();
         assert_eq!(&res.party_share_counts, &vec![MAX_TOTAL_SHARE_COUNT - 1, 1]);
@@ -312,6 +447,11 @@ mod tests {
             party_share_counts: vec![1, 1, 1], // counts are not the same number as parties
             my_party_index: 0,
             threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         assert!(Gg20Service::keygen_sanitize_args(raw_keygen_init).is_err());
 
@@ -321,6 +461,11 @@ mod tests {
             party_share_counts: vec![1, 1],
             my_party_index: 0,
             threshold: 2, // incorrect threshold
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         assert!(Gg20Service::keygen_sanitize_args(raw_keygen_init).is_err());
 
@@ -330,6 +475,11 @@ mod tests {
             party_share_counts: vec![1, 1],
             my_party_index: 2, // index out of bounds
             threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         assert!(Gg20Service::keygen_sanitize_args(raw_keygen_init).is_err());
 
@@ -339,6 +489,11 @@ mod tests {
             party_share_counts: vec![(MAX_PARTY_SHARE_COUNT + 1) as u32], // party has more than max number of shares
             my_party_index: 0,
             threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         assert!(Gg20Service::keygen_sanitize_args(raw_keygen_init).is_err());
 
@@ -348,7 +503,54 @@ mod tests {
             party_share_counts: vec![MAX_TOTAL_SHARE_COUNT as u32, 1], // total share count is more than max total shares
             my_party_index: 0,
             threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![],
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
+        };
+        assert!(Gg20Service::keygen_sanitize_args(raw_keygen_init).is_err());
+
+        let raw_keygen_init = proto::KeygenInit {
+            new_key_uid: "test_uid".to_owned(),
+            party_uids: vec!["party_1".to_owned(), "party_2".to_owned()],
+            party_share_counts: vec![1, 1],
+            my_party_index: 0,
+            threshold: 1,
+            round_timeout_ms: 0,
+            party_identity_keys: vec![vec![1]], // identity keys not alligned with uids
+            party_addresses: vec![],
+            echo_broadcasts: false,
+            new_key_uids: vec![],
         };
         assert!(Gg20Service::keygen_sanitize_args(raw_keygen_init).is_err());
+
+        // check keys of a batch
+        let batch_keygen_init = |new_key_uids: Vec<&str>| proto::KeygenInit {
+            new_key_uid: "test_uid".to_owned(),
+            party_uids: vec!["party_1".to_owned(), "party_2".to_owned()],
+            party_share_counts: vec![1, 1],
+            my_party_index: 0,
+            threshold: 1,
+            new_key_uids: new_key_uids.into_iter().map(str::to_owned).collect(),
+            ..Default::default()
+        };
+        let res = Gg20Service::keygen_sanitize_args(batch_keygen_init(vec![])).unwrap();
+        assert!(!res.is_batch());
+        assert_eq!(res.key_uids(), ["test_uid"]);
+        let res =
+            Gg20Service::keygen_sanitize_args(batch_keygen_init(vec!["key_1", "key_2"])).unwrap();
+        assert!(res.is_batch());
+        assert_eq!(res.key_uids(), ["key_1", "key_2"]);
+        assert!(
+            Gg20Service::keygen_sanitize_args(batch_keygen_init(vec!["key_1", "key_1"])).is_err()
+        ); // keys of a batch must be distinct
+        assert!(
+            Gg20Service::keygen_sanitize_args(batch_keygen_init(vec!["key_1", "mnemonic"]))
+                .is_err()
+        ); // keys can't overwrite mnemonics
+        assert!(
+            Gg20Service::keygen_sanitize_args(batch_keygen_init(vec!["archived:key_1"])).is_err()
+        ); // or archived keys
     }
 }
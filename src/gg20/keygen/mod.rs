@@ -8,21 +8,33 @@
 //!   3. [self::result] Finally, the party receives the result of the protocol, which is also send to the client through the gRPC stream. Afterwards, the stream is closed.
 //!
 //! Shares:
-//!   Each party might have multiple shares. A single thread is created for each share.
-//!   We keep this information agnostic to the client, and we use the [crate::gg20::routing] layer to distribute the messages to each share.
+//!   Each party might have multiple shares. A single thread is created for each share, and tofn runs one party per share.
+//!   Traffic carries the share it was sent from and, for p2ps, the share it is sent to, and the [crate::gg20::broadcast] layer
+//!   distributes the messages to each share. A party echoes the broadcasts it received once, from its first share.
 //!   The result of the protocol is common across all shares, and unique for each party. We make use of [self::result] layer to aggregate and process the result.
 //!
+//! Transport:
+//!   By default, the client relays the messages of all parties across the gRPC stream.
+//!   If peer addresses are known, messages are exchanged directly with the other tofnd daemons instead; see [crate::gg20::peer].
+//!
 //! All relevant helper structs and types are defined in [self::types]
 
 use super::{
-    broadcast::broadcast_messages, proto, service::Gg20Service, types::ProtocolCommunication,
+    broadcast::{broadcast_messages, ShareSenders},
+    peer::PeerRouter,
+    proto,
+    service::Gg20Service,
+    types::ProtocolCommunication,
+    vss,
 };
 
 use tonic::Status;
 
 use tofn::{
     collections::TypedUsize,
-    gg20::keygen::{create_party_keypair_and_zksetup_unsafe, KeygenPartyId},
+    gg20::keygen::{
+        create_party_keypair_and_zksetup, create_party_keypair_and_zksetup_unsafe, KeygenPartyId,
+    },
 };
 
 // tonic cruft
@@ -50,17 +62,16 @@ impl Gg20Service {
         keygen_span: Span,
     ) -> TofndResult<()> {
         // 1. Receive KeygenInit, open message, sanitize arguments -> init mod
-        // 2. Spawn N keygen threads to execute the protocol in parallel; one of each of our shares -> execute mod
+        // 2. Spawn N keygen threads to execute the protocol in parallel; one of each of our shares and of each key -> execute mod
         // 3. Spawn 1 router thread to route messages from client to the respective keygen thread -> routing mod
         // 4. Wait for all keygen threads to finish and aggregate all responses -> result mod
 
         // 1.
-        // get KeygenInit message from stream, sanitize arguments and reserve key
-        info!("{:?}", stream_in);
-        let (keygen_init, key_uid_reservation) = self
+        // get KeygenInit message from stream, sanitize arguments and reserve keys
+        let (keygen_init, traffic_auths, key_uid_reservations) = self
             .handle_keygen_init(&mut stream_in, keygen_span.clone())
             .await?;
-        info!("fine");
+
         // 2.
         // find my share count to allocate channel vectors
         let my_share_count = keygen_init.my_shares_count();
@@ -71,12 +82,17 @@ impl Gg20Service {
             ));
         }
 
-        // create in and out channels for each share, and spawn as many threads
-        let mut keygen_senders = Vec::with_capacity(my_share_count);
-        let mut aggregator_receivers = Vec::with_capacity(my_share_count);
+        // in direct mode, outgoing traffic is routed to the peers instead of the client
+        let peer_addresses = self.peer_addresses(
+            &keygen_init.party_uids,
+            keygen_init.my_index,
+            &keygen_init.party_addresses,
+        )?;
 
-        // computation of (party_keypair, party_zksetup) is intensive so we compute them here once
+        // computation of (party_keypair, party_zksetup) is intensive so we compute them here once for all keys
         let secret_recovery_key = self.kv_manager.seed().await?;
+        // stored with the keys, so that we know which mnemonic generated them
+        let seed_fingerprint = self.kv_manager.seed_fingerprint().await?;
         let session_nonce = keygen_init.new_key_uid.as_bytes();
 
         info!("Generating keypair for party {} ...", keygen_init.my_index);
@@ -84,11 +100,7 @@ impl Gg20Service {
         let party_id = TypedUsize::<KeygenPartyId>::from_usize(keygen_init.my_index);
 
         let party_keygen_data = match self.cfg.safe_keygen {
-            true => create_party_keypair_and_zksetup_unsafe(
-                party_id,
-                &secret_recovery_key,
-                session_nonce,
-            ),
+            true => create_party_keypair_and_zksetup(party_id, &secret_recovery_key, session_nonce),
             false => create_party_keypair_and_zksetup_unsafe(
                 party_id,
                 &secret_recovery_key,
@@ -102,56 +114,135 @@ impl Gg20Service {
             keygen_init.my_index
         );
 
-        for my_tofnd_subindex in 0..my_share_count {
-            // channels for communication between router (sender) and protocol threads (receivers)
-            let (keygen_sender, keygen_receiver) = mpsc::unbounded_channel();
-            keygen_senders.push(keygen_sender);
-            // channels for communication between protocol threads (senders) and final result aggregator (receiver)
-            let (aggregator_sender, aggregator_receiver) = oneshot::channel();
-            aggregator_receivers.push(aggregator_receiver);
-
-            // wrap channels needed by internal threads; receiver chan for router and sender chan gRPC stream
-            let chans = ProtocolCommunication::new(keygen_receiver, stream_out_sender.clone());
-            // wrap all context data needed for each thread
-            let ctx = Context::new(
-                &keygen_init,
-                keygen_init.my_index,
-                party_keygen_data.clone(),
-                // party_keygen_data.clone(),
-            );
-            // clone gg20 service because tokio thread takes ownership
-            let gg20 = self.clone();
-
-            // set up log state
-            // let log_info = ctx.log_info();
-            let state = "log_info.as_str()";
-            let execute_span = span!(parent: &keygen_span, Level::DEBUG, "execute", state);
-
-            // spawn keygen thread and continue immediately
-            tokio::spawn(async move {
-                // wait for keygen's result inside thread
-                let secret_key_share = gg20.execute_keygen(chans, &ctx, execute_span).await;
-                // send result to aggregator
-                let _ = aggregator_sender.send(secret_key_share);
-            });
+        let first_share_id =
+            vss::share_indices(&keygen_init.party_share_counts)[keygen_init.my_index][0];
+
+        // create in and out channels for each share of each key, and spawn as many threads
+        let key_count = keygen_init.key_uids().len();
+        let mut key_senders = Vec::with_capacity(key_count);
+        let mut key_forwarders = Vec::with_capacity(key_count);
+        let mut aggregator_receivers = Vec::with_capacity(key_count);
+
+        for (key_index, (key_uid, traffic_auth)) in
+            keygen_init.key_uids().iter().zip(traffic_auths).enumerate()
+        {
+            // the traffic of each key is tagged with its index, so that it is multiplexed over the gRPC stream
+            let (protocol_sender, protocol_receiver) = mpsc::unbounded_channel();
+            match &peer_addresses {
+                Some(addresses) => {
+                    let router = PeerRouter::new(
+                        self.peer_sessions.clone(),
+                        key_uid,
+                        &keygen_init.party_uids,
+                        keygen_init.my_index,
+                        addresses,
+                        keygen_span.clone(),
+                    )?;
+                    tokio::spawn(router.run(protocol_receiver, stream_out_sender.clone()));
+                }
+                None => key_forwarders.push(tokio::spawn(forward_key_traffic(
+                    key_index,
+                    protocol_receiver,
+                    stream_out_sender.clone(),
+                ))),
+            }
+
+            let mut keygen_senders = Vec::with_capacity(my_share_count);
+            let mut share_receivers = Vec::with_capacity(my_share_count);
+
+            for my_tofnd_subindex in 0..my_share_count {
+                // channels for communication between router (sender) and protocol threads (receivers)
+                let (keygen_sender, keygen_receiver) = mpsc::unbounded_channel();
+                keygen_senders.push(keygen_sender);
+                // channels for communication between protocol threads (senders) and final result aggregator (receiver)
+                let (aggregator_sender, aggregator_receiver) = oneshot::channel();
+                share_receivers.push(aggregator_receiver);
+
+                // wrap channels needed by internal threads; receiver chan for router and sender chan gRPC stream
+                let chans = ProtocolCommunication::new(keygen_receiver, protocol_sender.clone());
+                // wrap all context data needed for each thread
+                let ctx = Context::new(
+                    &keygen_init,
+                    key_uid,
+                    keygen_init.my_index,
+                    my_tofnd_subindex,
+                    party_keygen_data.clone(),
+                    self.cfg.round_timeout,
+                    traffic_auth.clone(),
+                );
+                // clone gg20 service because tokio thread takes ownership
+                let gg20 = self.clone();
+
+                let execute_span = span!(
+                    parent: &keygen_span,
+                    Level::DEBUG,
+                    "execute",
+                    key_uid = key_uid.as_str(),
+                    share_id = ctx.share_id.as_usize()
+                );
+
+                // spawn keygen thread and continue immediately
+                tokio::spawn(async move {
+                    // wait for keygen's result inside thread
+                    let secret_key_share = gg20.execute_keygen(chans, &ctx, execute_span).await;
+                    // send result to aggregator
+                    let _ = aggregator_sender.send(secret_key_share);
+                });
+            }
+
+            key_senders.push(ShareSenders::shares(first_share_id, keygen_senders));
+            aggregator_receivers.push(share_receivers);
         }
 
         // 3.
         // spin up broadcaster thread and return immediately
+        // in direct mode, incoming traffic comes from the peers; the client stream is only watched for an abort
+        let sessions: Vec<_> = match peer_addresses {
+            Some(_) => keygen_init
+                .key_uids()
+                .iter()
+                .zip(std::mem::take(&mut key_senders))
+                .map(|(key_uid, senders)| self.peer_sessions.register(key_uid, senders))
+                .collect(),
+            None => vec![],
+        };
         tokio::spawn(async move {
-            broadcast_messages(&mut stream_in, keygen_senders, keygen_span).await;
+            broadcast_messages(&mut stream_in, key_senders, keygen_span).await;
+            drop(sessions);
         });
 
         // 4.
         // wait for all keygen threads to end, aggregate their responses, and store data in KV store
         self.aggregate_results(
             aggregator_receivers,
+            key_forwarders,
             &mut stream_out_sender,
-            key_uid_reservation,
+            key_uid_reservations,
             keygen_init,
+            seed_fingerprint,
         )
         .await?;
 
         Ok(())
     }
 }
+
+/// Forward the outgoing traffic of the key with index `key_index` to the gRPC stream, tagged with the index of the key.
+/// Returns when the keygen threads of the key are done.
+async fn forward_key_traffic(
+    key_index: usize,
+    mut protocol_receiver: mpsc::UnboundedReceiver<Result<proto::MessageOut, Status>>,
+    stream_out_sender: mpsc::UnboundedSender<Result<proto::MessageOut, Status>>,
+) {
+    while let Some(mut msg) = protocol_receiver.recv().await {
+        if let Ok(proto::MessageOut {
+            data: Some(proto::message_out::Data::Traffic(traffic)),
+        }) = &mut msg
+        {
+            traffic.key_index = key_index as u32;
+        }
+        if stream_out_sender.send(msg).is_err() {
+            break;
+        }
+    }
+}
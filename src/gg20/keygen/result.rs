@@ -3,23 +3,36 @@
 //!  1. the public key - must be the same across all results; stored in KvStore
 //!  2. all secret share data - data used to allow parties to participate to future Signs; stored in KvStore
 //!  3. all secret share recovery info - information used to allow client to issue secret share recovery in case of data loss; sent to client
+//!  4. all VSS complaints that were made by us or against us - sent to client along with the result
+//!
+//! If the session generates several keys, the results of all keys are sent to the client together,
+//! and the keys are stored only if all of them completed.
 
 use tofn::{gg20::keygen::SecretKeyShare, sdk::api::serialize};
 
 use super::{
     proto::{self},
-    types::{BytesVec, KeygenInitSanitized, TofnKeygenOutput, TofndKeygenOutput},
+    types::{BytesVec, KeygenFaults, KeygenInitSanitized, TofnKeygenOutput, TofndKeygenOutput},
     Gg20Service,
 };
-use crate::{gg20::types::PartyInfo, kv_manager::KeyReservation};
+use crate::{
+    gg20::{protocol::ProtocolReport, types::PartyInfo, vss},
+    kv_manager::KeyReservation,
+};
 
 // tonic cruft
-use tokio::sync::{
-    mpsc,
-    oneshot::{self, Receiver},
+use tokio::{
+    sync::{
+        mpsc,
+        oneshot::{self, Receiver},
+    },
+    task::JoinHandle,
 };
 use tonic::Status;
 
+// logging
+use tracing::warn;
+
 // error handling
 use crate::TofndResult;
 use anyhow::anyhow;
@@ -27,75 +40,181 @@ use anyhow::anyhow;
 use std::convert::TryInto;
 
 impl Gg20Service {
-    /// aggregate results from all keygen threads, create a record and insert it in the KvStore
+    /// Aggregate results from all keygen threads of all keys, create a record of each key and insert them in the KvStore.
+    /// Keys are stored only if all keys of the session completed; otherwise, all reservations are released.
     pub(super) async fn aggregate_results(
         &self,
-        aggregator_receivers: Vec<oneshot::Receiver<TofndKeygenOutput>>,
+        aggregator_receivers: Vec<Vec<oneshot::Receiver<TofndKeygenOutput>>>,
+        key_forwarders: Vec<JoinHandle<()>>,
         stream_out_sender: &mut mpsc::UnboundedSender<Result<proto::MessageOut, Status>>,
-        key_uid_reservation: KeyReservation,
+        key_uid_reservations: Vec<KeyReservation>,
         keygen_init: KeygenInitSanitized,
+        seed_fingerprint: Vec<u8>,
     ) -> TofndResult<()> {
         // wait all keygen threads and aggregate results
-        // can't use `map_err` because of `.await` func :(
-        let keygen_outputs = match Self::aggregate_keygen_outputs(aggregator_receivers).await {
-            Ok(keygen_outputs) => keygen_outputs,
+        let kv = self.kv_manager.kv();
+        let mut key_outputs = Vec::with_capacity(aggregator_receivers.len());
+        for receivers in aggregator_receivers {
+            // can't use `map_err` because of `.await` func :(
+            match Self::aggregate_keygen_outputs(receivers).await {
+                Ok(keygen_outputs) => key_outputs.push(keygen_outputs),
+                Err(err) => {
+                    self.unreserve_keys(key_uid_reservations).await;
+                    return Err(anyhow!(
+                        "Error at Keygen output aggregation. Unreserving keys {}",
+                        err
+                    ));
+                }
+            }
+        }
+
+        // the traffic of all keys must reach the client before the result
+        for forwarder in key_forwarders {
+            if let Err(err) = forwarder.await {
+                self.unreserve_keys(key_uid_reservations).await;
+                return Err(err.into());
+            }
+        }
+
+        // try to process the keygen outputs of each key
+        let results = match Self::process_key_outputs(&keygen_init, key_outputs, &seed_fingerprint)
+        {
+            Ok(results) => results,
             Err(err) => {
-                self.kv_manager
-                    .kv()
-                    .unreserve_key(key_uid_reservation)
-                    .await;
-                return Err(anyhow!(
-                    "Error at Keygen output aggregation. Unreserving key {}",
-                    err
-                ));
+                self.unreserve_keys(key_uid_reservations).await;
+                return Err(err);
+            }
+        };
+
+        // store all keys if all of them completed; release all reservations otherwise
+        let completed = results.iter().all(|(result, _)| result.is_ok());
+        let (results, kv_data): (Vec<_>, Vec<_>) = results
+            .into_iter()
+            .map(|(result, report)| match result {
+                Ok((keygen_output, kv_data)) => ((Ok(keygen_output), report), Some(kv_data)),
+                Err(faults) => ((Err(faults), report), None),
+            })
+            .unzip();
+        if completed {
+            let kv_values = kv_data
+                .into_iter()
+                .flatten()
+                .map(|kv_data| Ok(kv_data.try_into()?))
+                .collect::<TofndResult<Vec<Vec<u8>>>>();
+            let kv_values = match kv_values {
+                Ok(kv_values) => kv_values,
+                Err(err) => {
+                    self.unreserve_keys(key_uid_reservations).await;
+                    return Err(err);
+                }
+            };
+            // reservations are released by the kv store if the values can't be put
+            let values = key_uid_reservations.into_iter().zip(kv_values).collect();
+            kv.put_all(values).await.map_err(|err| anyhow!(err))?;
+        } else {
+            self.unreserve_keys(key_uid_reservations).await;
+        }
+
+        // try to send result; keys that completed have no result if the session failed
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|(result, report)| match result {
+                Ok(_) if !completed => (None, report),
+                result => (Some(result), report),
+            })
+            .collect();
+        let msg = match keygen_init.is_batch() {
+            true => proto::MessageOut::new_batch_keygen_result(&keygen_init.party_uids, results),
+            false => {
+                let (result, report) = results
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!("Party {} generated no key", keygen_init.my_index))?;
+                let result = result.ok_or_else(|| anyhow!("missing result of key"))?;
+                proto::MessageOut::new_keygen_result(&keygen_init.party_uids, result, &report)
             }
         };
+        stream_out_sender.send(Ok(msg))?;
 
-        // try to process keygen outputs
-        let (pub_key, secret_key_shares) =
-            Self::process_keygen_outputs(&keygen_init, keygen_outputs, stream_out_sender)?;
-
-        // try to retrieve private recovery info from all shares
-        let private_recover_info =
-            Self::get_private_recovery_data(&secret_key_shares).map_err(|err| anyhow!(err))?;
-
-        // combine responses from all keygen threads to a single struct
-        let kv_data = PartyInfo::get_party_info(
-            secret_key_shares,
-            keygen_init.party_uids.clone(),
-            keygen_init.party_share_counts.clone(),
-            keygen_init.my_index,
-        );
-
-        // try to put data inside kv store
-        self.kv_manager
-            .kv()
-            .put(key_uid_reservation, kv_data.try_into()?)
-            .await
-            .map_err(|err| anyhow!(err))?;
-
-        // try to send result
-        Ok(
-            stream_out_sender.send(Ok(proto::MessageOut::new_keygen_result(
-                &keygen_init.party_uids,
-                Ok(proto::KeygenOutput {
-                    pub_key,
-
-                    private_recover_info,
-                }),
-            )))?,
-        )
+        match completed {
+            true => Ok(()),
+            false => Err(anyhow!(
+                "Party {} found crimes in keygen of keys {:?}",
+                keygen_init.my_index,
+                keygen_init.key_uids()
+            )),
+        }
+    }
+
+    /// release the reservations of the keys of a session that stores no key
+    async fn unreserve_keys(&self, reservations: Vec<KeyReservation>) {
+        for reservation in reservations {
+            self.kv_manager.kv().unreserve_key(reservation).await;
+        }
+    }
+
+    /// Process the keygen outputs of each key along with the report of its keygen.
+    /// Returns the output that is sent to the client and the [PartyInfo] that is stored for each completed key,
+    /// or the faults of the parties if the keygen of the key failed.
+    #[allow(clippy::type_complexity)]
+    fn process_key_outputs(
+        keygen_init: &KeygenInitSanitized,
+        key_outputs: Vec<Vec<(TofnKeygenOutput, ProtocolReport)>>,
+        seed_fingerprint: &[u8],
+    ) -> TofndResult<
+        Vec<(
+            Result<(proto::KeygenOutput, PartyInfo), KeygenFaults>,
+            ProtocolReport,
+        )>,
+    > {
+        let mut results = Vec::with_capacity(key_outputs.len());
+        for keygen_outputs in key_outputs {
+            // complaints and faults are reported to the client whether keygen succeeded or not
+            let (keygen_outputs, reports): (Vec<_>, Vec<_>) = keygen_outputs.into_iter().unzip();
+            let report = ProtocolReport::merge(reports);
+
+            // try to process keygen outputs
+            let (pub_key, secret_key_shares) =
+                match Self::process_keygen_outputs(keygen_init, keygen_outputs)? {
+                    Ok(outputs) => outputs,
+                    Err(faults) => {
+                        results.push((Err(faults), report));
+                        continue;
+                    }
+                };
+
+            // try to retrieve private recovery info from all shares
+            let private_recover_info =
+                Self::get_private_recovery_data(&secret_key_shares).map_err(|err| anyhow!(err))?;
+
+            // combine responses from all keygen threads to a single struct
+            let kv_data = PartyInfo::get_party_info(
+                secret_key_shares,
+                keygen_init.party_uids.clone(),
+                keygen_init.party_share_counts.clone(),
+                keygen_init.my_index,
+                seed_fingerprint.to_vec(),
+            );
+
+            let keygen_output = proto::KeygenOutput {
+                pub_key,
+                private_recover_info,
+                seed_fingerprint: seed_fingerprint.to_vec(),
+            };
+            results.push((Ok((keygen_output, kv_data)), report));
+        }
+        Ok(results)
     }
 
     /// iterate all keygen outputs, and return data that need to be permenantly stored
     /// we perform a sanity check that all shares produces the same pubkey and group recovery
     /// and then return a single copy of the common info and a vec with `SecretKeyShares` of each party
     /// This vec is later used to derive private recovery info
+    /// Returns the faults of the parties if the keygen failed.
     fn process_keygen_outputs(
         keygen_init: &KeygenInitSanitized,
         keygen_outputs: Vec<TofnKeygenOutput>,
-        stream_out_sender: &mut mpsc::UnboundedSender<Result<proto::MessageOut, Status>>,
-    ) -> TofndResult<(BytesVec, Vec<SecretKeyShare>)> {
+    ) -> TofndResult<Result<(BytesVec, Vec<SecretKeyShare>), KeygenFaults>> {
         // Collect all key shares unless there's a protocol fault
         let keygen_outputs = keygen_outputs
             .into_iter()
@@ -128,20 +247,25 @@ impl Gg20Service {
                     }
                 }
 
-                Ok((pub_key, secret_key_shares))
+                // sanity check: each share thread produced the share it was assigned; see [Context::new]
+                let my_share_ids =
+                    &vss::share_indices(&keygen_init.party_share_counts)[keygen_init.my_index];
+                for (subindex, secret_key_share) in secret_key_shares.iter().enumerate() {
+                    if secret_key_share.share().index().as_usize() != my_share_ids[subindex] {
+                        return Err(anyhow!(
+                            "Party {}'s share {} returned share index {}",
+                            keygen_init.my_index,
+                            subindex,
+                            secret_key_share.share().index()
+                        ));
+                    }
+                }
+
+                Ok(Ok((pub_key, secret_key_shares)))
             }
             Err(crimes) => {
-                // send crimes and exit with an error
-                stream_out_sender.send(Ok(proto::MessageOut::new_keygen_result(
-                    &keygen_init.party_uids,
-                    Err(crimes.clone()),
-                )))?;
-
-                Err(anyhow!(
-                    "Party {} found crimes: {:?}",
-                    keygen_init.my_index,
-                    crimes
-                ))
+                warn!("Party {} found crimes: {:?}", keygen_init.my_index, crimes);
+                Ok(Err(crimes))
             }
         }
     }
@@ -166,10 +290,10 @@ impl Gg20Service {
         Ok(private_bytes)
     }
 
-    /// wait all keygen threads and get keygen outputs
+    /// wait all keygen threads and get keygen outputs along with their reports
     async fn aggregate_keygen_outputs(
         aggregator_receivers: Vec<Receiver<TofndKeygenOutput>>,
-    ) -> TofndResult<Vec<TofnKeygenOutput>> {
+    ) -> TofndResult<Vec<(TofnKeygenOutput, ProtocolReport)>> {
         let mut keygen_outputs = Vec::with_capacity(aggregator_receivers.len());
 
         for aggregator in aggregator_receivers {
@@ -1,36 +1,60 @@
 //! Helper structs and implementations for [crate::gg20::keygen].
 
 use tofn::{
-    collections::TypedUsize,
+    collections::{FillVecMap, TypedUsize},
     gg20::keygen::{KeygenPartyId, KeygenPartyShareCounts, PartyKeygenData, SecretKeyShare},
-    sdk::api::ProtocolOutput,
+    sdk::api::{Fault, ProtocolOutput},
 };
 
 pub(super) type PartyShareCounts = KeygenPartyShareCounts;
 pub const MAX_PARTY_SHARE_COUNT: usize = tofn::gg20::keygen::MAX_PARTY_SHARE_COUNT;
 pub const MAX_TOTAL_SHARE_COUNT: usize = tofn::gg20::keygen::MAX_TOTAL_SHARE_COUNT;
 
+use crate::gg20::identity::TrafficAuth;
+use crate::gg20::protocol::ProtocolReport;
+use crate::gg20::vss;
 use crate::TofndResult;
 use anyhow::anyhow;
+use std::time::Duration;
 use tracing::{info, span, Level, Span};
 
 /// tofn's ProtocolOutput for Keygen
 pub type TofnKeygenOutput = ProtocolOutput<SecretKeyShare, KeygenPartyId>;
-/// tofnd's ProtocolOutput for Keygen
-pub type TofndKeygenOutput = TofndResult<TofnKeygenOutput>;
+/// faults of the parties of a failed Keygen
+pub type KeygenFaults = FillVecMap<KeygenPartyId, Fault>;
+/// tofnd's ProtocolOutput for Keygen, along with what the party observed during the protocol
+pub type TofndKeygenOutput = TofndResult<(TofnKeygenOutput, ProtocolReport)>;
 /// type for bytes
 pub use tofn::sdk::api::BytesVec;
 
 /// KeygenInitSanitized holds all arguments needed by Keygen in the desired form; populated by proto::KeygenInit
 /// pub because it is also needed by recovery module
 pub struct KeygenInitSanitized {
-    pub new_key_uid: String,            // session's UID
+    pub new_key_uid: String,               // session's UID
     pub party_uids: Vec<String>, // vector of party uids; this is alligned with party_share_count vector
     pub party_share_counts: Vec<usize>, // vector of share counts; this is alligned with party_uids vector
     pub my_index: usize, // the _tofnd_ index of the party inside party_uids and party_shares_counts
     pub threshold: usize, // protocol's threshold
+    pub round_timeout: Option<Duration>, // deadline of each protocol round; `None` for tofnd's default
+    pub party_identity_keys: Vec<Vec<u8>>, // identity keys of parties; alligned with party_uids, or empty
+    pub party_addresses: Vec<String>, // peer addresses of parties; alligned with party_uids, or empty
+    pub echo_broadcasts: bool,        // whether parties echo the broadcasts they received
+    pub new_key_uids: Vec<String>,    // uids of a batch of keys; empty if a single key is generated
 }
 impl KeygenInitSanitized {
+    /// get the uids of the keys that are generated in the session
+    pub fn key_uids(&self) -> &[String] {
+        match self.is_batch() {
+            true => &self.new_key_uids,
+            false => std::slice::from_ref(&self.new_key_uid),
+        }
+    }
+
+    /// whether the client requested a batch of keys
+    pub(super) fn is_batch(&self) -> bool {
+        !self.new_key_uids.is_empty()
+    }
+
     // get the share count of `my_index`th party
     pub(super) fn my_shares_count(&self) -> usize {
         self.party_share_counts[self.my_index] as usize
@@ -42,10 +66,10 @@ impl KeygenInitSanitized {
         let init_span = span!(parent: &keygen_span, Level::INFO, "init");
         let _enter = init_span.enter();
         info!(
-            "[uid:{}, shares:{}] starting Keygen with [key: {}, (t,n)=({},{}), participants:{:?}",
+            "[uid:{}, shares:{}] starting Keygen with [keys: {:?}, (t,n)=({},{}), participants:{:?}",
             self.party_uids[self.my_index],
             self.my_shares_count(),
-            self.new_key_uid,
+            self.key_uids(),
             self.threshold,
             self.party_share_counts.iter().sum::<usize>(),
             self.party_uids,
@@ -55,32 +79,42 @@ impl KeygenInitSanitized {
 
 /// Context holds the all arguments that need to be passed from keygen gRPC call into protocol execution
 pub struct Context {
-    pub(super) key_id: String,           // session id; used for logs
+    pub(super) key_id: String,           // uid of the key; used for logs
     pub(super) uids: Vec<String>,        // all party uids; alligned with `share_counts`
     pub(super) share_counts: Vec<usize>, // all party share counts; alligned with `uids`
     pub(super) threshold: usize,         // protocol's threshold
-    pub(super) tofnd_index: TypedUsize<KeygenPartyId>, // tofnd index of party
-    // pub(super) tofnd_subindex: usize,    // index of party's share
+    // tofn index of our share; tofn runs one party per share
+    pub(super) share_id: TypedUsize<KeygenPartyId>,
     pub(super) party_keygen_data: PartyKeygenData,
+    pub(super) round_timeout: Duration, // deadline of each protocol round
+    pub(super) traffic_auth: TrafficAuth, // signs and verifies the traffic of the session
+    pub(super) echo_broadcasts: bool,   // whether parties echo the broadcasts they received
 }
 
 impl Context {
     /// create a new Context
     pub fn new(
         keygen_init: &KeygenInitSanitized,
+        key_uid: &str,
         tofnd_index: usize,
-        // tofnd_subindex: usize,
+        tofnd_subindex: usize,
         party_keygen_data: PartyKeygenData,
+        default_round_timeout: Duration,
+        traffic_auth: TrafficAuth,
     ) -> Self {
-        let tofnd_index = TypedUsize::from_usize(tofnd_index);
+        let share_id = TypedUsize::from_usize(
+            vss::share_indices(&keygen_init.party_share_counts)[tofnd_index][tofnd_subindex],
+        );
         Context {
-            key_id: keygen_init.new_key_uid.clone(),
+            key_id: key_uid.to_owned(),
             uids: keygen_init.party_uids.clone(),
             share_counts: keygen_init.party_share_counts.clone(),
             threshold: keygen_init.threshold,
-            tofnd_index,
-            // tofnd_subindex,
+            share_id,
             party_keygen_data,
+            round_timeout: keygen_init.round_timeout.unwrap_or(default_round_timeout),
+            traffic_auth,
+            echo_broadcasts: keygen_init.echo_broadcasts,
         }
     }
 
@@ -0,0 +1,183 @@
+//! This module handles the list_keys gRPC.
+//! Request includes an optional `start_after` key uid and a page `limit`.
+//! All [PartyInfo] records of the kv store are listed in lexicographic order of their key uids.
+//! Mnemonic entries, archived keys and reserved keys are skipped.
+
+use super::{delete_key::ARCHIVED_KEY_PREFIX, proto, service::Gg20Service, types::PartyInfo};
+use crate::mnemonic::is_mnemonic_key;
+
+// logging
+use tracing::{info, warn};
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::convert::{TryFrom, TryInto};
+
+impl Gg20Service {
+    pub(super) async fn handle_list_keys(
+        &self,
+        request: proto::ListKeysRequest,
+    ) -> TofndResult<proto::KeyList> {
+        let limit = match request.limit {
+            0 => usize::MAX,
+            limit => usize::try_from(limit)?,
+        };
+
+        // keys are returned sorted by the kv store
+        let key_uids: Vec<String> = self
+            .kv_manager
+            .kv()
+            .keys("")
+            .await
+            .map_err(|err| anyhow!("could not list keys: {}", err))?
+            .into_iter()
+            .filter(|key_uid| {
+                !is_mnemonic_key(key_uid) && !key_uid.starts_with(ARCHIVED_KEY_PREFIX)
+            })
+            .filter(|key_uid| request.start_after.is_empty() || *key_uid > request.start_after)
+            .collect();
+
+        let mut keys = Vec::<proto::KeyInfo>::new();
+        let mut next_start_after = String::new();
+        for key_uid in key_uids {
+            // there are more keys; next page continues after the last key of this page
+            if keys.len() == limit {
+                if let Some(last) = keys.last() {
+                    next_start_after = last.key_uid.clone();
+                }
+                break;
+            }
+
+            let party_info: PartyInfo = match self.kv_manager.kv().get(&key_uid).await {
+                Ok(value) => match value.try_into() {
+                    Ok(party_info) => party_info,
+                    Err(err) => {
+                        warn!("Skipping key {}: not a party info record: {}", key_uid, err);
+                        continue;
+                    }
+                },
+                // key may have been deleted in the meantime
+                Err(err) => {
+                    warn!("Skipping key {}: {}", key_uid, err);
+                    continue;
+                }
+            };
+
+            keys.push(Self::key_info(key_uid, &party_info)?);
+        }
+
+        info!("Listed {} keys", keys.len());
+
+        Ok(proto::KeyList {
+            keys,
+            next_start_after,
+        })
+    }
+
+    /// get the metadata of a stored key
+    fn key_info(key_uid: String, party_info: &PartyInfo) -> TofndResult<proto::KeyInfo> {
+        let tofnd = &party_info.tofnd;
+        let my_party_uid = tofnd
+            .party_uids
+            .get(tofnd.index)
+            .ok_or_else(|| anyhow!("index {} out of party_uids bounds", tofnd.index))?
+            .clone();
+
+        Ok(proto::KeyInfo {
+            key_uid,
+            threshold: u32::try_from(party_info.common.threshold())?,
+            party_count: u32::try_from(tofnd.party_uids.len())?,
+            my_party_uid,
+            my_share_count: u32::try_from(party_info.shares.len())?,
+            created_at: tofnd.created_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::{
+        refresh,
+        test_utils::{party_info, put_key, store_key, test_service},
+        vss,
+    };
+    use bls12_381::Scalar;
+    use testdir::testdir;
+    use tracing_test::traced_test;
+
+    /// list a page of keys and get their uids and the start of the next page
+    async fn list(service: &Gg20Service, start_after: &str, limit: u32) -> (Vec<String>, String) {
+        let list = service
+            .handle_list_keys(proto::ListKeysRequest {
+                start_after: start_after.to_owned(),
+                limit,
+            })
+            .await
+            .unwrap();
+        let key_uids = list.keys.into_iter().map(|key| key.key_uid).collect();
+        (key_uids, list.next_start_after)
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_keys() {
+        // the kv store holds the mnemonic of the service, and `mnemonic` sorts between the keys
+        let service = test_service(testdir!()).await;
+        for key_uid in ["a", "b", "n", "z"].iter() {
+            store_key(&service, key_uid, Scalar::from(7)).await;
+        }
+
+        // archived keys, including the archive of an unconfirmed refresh, are skipped
+        service
+            .handle_delete_key(proto::DeleteKeyRequest {
+                key_uid: "b".to_owned(),
+                mode: proto::delete_key_request::Mode::Archive as i32,
+            })
+            .await
+            .unwrap();
+        let poly = vss::Polynomial::random(Scalar::from(7), 1);
+        put_key(
+            &service,
+            &refresh::archive_key("n"),
+            party_info(&poly, vec![]),
+        )
+        .await;
+
+        let keys = |uids: &[&str]| uids.iter().map(|uid| uid.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            list(&service, "", 0).await,
+            (keys(&["a", "n", "z"]), String::new())
+        );
+
+        // pages continue after the last key of the previous page
+        assert_eq!(
+            list(&service, "", 2).await,
+            (keys(&["a", "n"]), "n".to_owned())
+        );
+        assert_eq!(list(&service, "n", 2).await, (keys(&["z"]), String::new()));
+        assert_eq!(
+            list(&service, "", 3).await,
+            (keys(&["a", "n", "z"]), String::new())
+        );
+        assert_eq!(list(&service, "b", 1).await, (keys(&["n"]), "n".to_owned()));
+        assert_eq!(list(&service, "z", 1).await, (keys(&[]), String::new()));
+
+        // metadata of our shares
+        let list = service
+            .handle_list_keys(proto::ListKeysRequest {
+                start_after: String::new(),
+                limit: 1,
+            })
+            .await
+            .unwrap();
+        let key = &list.keys[0];
+        assert_eq!(key.threshold, 1);
+        assert_eq!(key.party_count, 2);
+        assert_eq!(key.my_party_uid, "alice");
+        assert_eq!(key.my_share_count, 2);
+        assert!(key.created_at > 0);
+    }
+}
@@ -2,7 +2,15 @@
 //! Available gRPCs are:
 //!     [recover] - Recovers private data of a party provided a mnemonic.
 //!     [keygen] - Starts keygen.
-//!     [sign] - Starts sing.
+//!     [sign] - Computes partial signatures of all party's shares.
+//!     [extract] - Computes identity key shares of all party's shares.
+//!     [combine] - Verifies and combines partial signatures or identity key shares of any parties.
+//!     [public_info] - Returns the public data of a key.
+//!     [list_keys] - Lists the metadata of all stored keys.
+//!     [delete_key] - Archives or purges a key, and restores archived keys.
+//!     [dispute] - Settles a VSS complaint between two parties of any keygen.
+//!     [refresh] - Starts a refresh of the shares of a key.
+//!     [reshare] - Starts a reshare of a key to new parties with a new threshold.
 
 // tonic cruft
 use super::proto;
@@ -15,19 +23,60 @@ pub mod proto_helpers;
 use tracing::{error, info, span, Level};
 
 // gRPC
+mod bls;
 mod broadcast;
+mod combine;
+mod delete_key;
+mod dispute;
+mod echo;
+mod exchange;
+mod extract;
+mod identity;
 mod key_presence;
 mod keygen;
+mod list_keys;
+pub mod peer;
 mod protocol;
+mod public_info;
 mod recover;
+mod refresh;
+mod reshare;
 pub mod service;
-//mod sign;
+mod sign;
+#[cfg(test)]
+mod test_utils;
 pub mod types;
+mod vss;
 use types::*;
 
 #[tonic::async_trait]
 impl proto::gg20_server::Gg20 for service::Gg20Service {
     type KeygenStream = UnboundedReceiverStream<Result<proto::MessageOut, tonic::Status>>;
+    type RefreshStream = UnboundedReceiverStream<Result<proto::MessageOut, tonic::Status>>;
+    type ReshareStream = UnboundedReceiverStream<Result<proto::MessageOut, tonic::Status>>;
+
+    /// Recover unary gRPC. See [recover].
+    async fn recover(
+        &self,
+        request: tonic::Request<proto::RecoverRequest>,
+    ) -> Result<Response<proto::RecoverResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_recover(request).await {
+            Ok(()) => {
+                info!("Recovery completed successfully!");
+                proto::recover_response::Response::Success
+            }
+            Err(err) => {
+                error!("Unable to complete recovery: {}", err);
+                proto::recover_response::Response::Fail
+            }
+        };
+
+        Ok(Response::new(proto::RecoverResponse {
+            response: response as i32,
+        }))
+    }
 
     // /// KeyPresence unary gRPC. See [key_presence].
     async fn key_presence(
@@ -52,6 +101,238 @@ impl proto::gg20_server::Gg20 for service::Gg20Service {
         }))
     }
 
+    /// Sign unary gRPC. See [sign].
+    async fn sign(
+        &self,
+        request: tonic::Request<proto::SignRequest>,
+    ) -> Result<Response<proto::SignResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_sign(request).await {
+            Ok(signatures) => {
+                info!("Sign completed successfully!");
+                proto::sign_response::SignResponse::Signatures(proto::PartialSignatures {
+                    signatures,
+                })
+            }
+            Err(err) => {
+                error!("Unable to complete sign: {}", err);
+                proto::sign_response::SignResponse::Error(err.to_string())
+            }
+        };
+
+        Ok(Response::new(proto::SignResponse {
+            sign_response: Some(response),
+        }))
+    }
+
+    /// Extract unary gRPC. See [extract].
+    async fn extract(
+        &self,
+        request: tonic::Request<proto::ExtractRequest>,
+    ) -> Result<Response<proto::ExtractResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_extract(request).await {
+            Ok(key_shares) => {
+                info!("Extract completed successfully!");
+                proto::extract_response::ExtractResponse::KeyShares(proto::IdentityKeyShares {
+                    key_shares,
+                })
+            }
+            Err(err) => {
+                error!("Unable to complete extract: {}", err);
+                proto::extract_response::ExtractResponse::Error(err.to_string())
+            }
+        };
+
+        Ok(Response::new(proto::ExtractResponse {
+            extract_response: Some(response),
+        }))
+    }
+
+    /// Combine unary gRPC. See [combine].
+    async fn combine(
+        &self,
+        request: tonic::Request<proto::CombineRequest>,
+    ) -> Result<Response<proto::CombineResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_combine(request).await {
+            Ok(result) => {
+                info!("Combine completed successfully!");
+                proto::combine_response::CombineResponse::Result(result)
+            }
+            Err(err) => {
+                error!("Unable to complete combine: {}", err);
+                proto::combine_response::CombineResponse::Error(err.to_string())
+            }
+        };
+
+        Ok(Response::new(proto::CombineResponse {
+            combine_response: Some(response),
+        }))
+    }
+
+    /// GetPublicInfo unary gRPC. See [public_info].
+    async fn get_public_info(
+        &self,
+        request: tonic::Request<proto::PublicInfoRequest>,
+    ) -> Result<Response<proto::PublicInfoResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_get_public_info(request).await {
+            Ok(public_info) => {
+                info!("Get public info completed successfully!");
+                proto::public_info_response::PublicInfoResponse::PublicInfo(public_info)
+            }
+            Err(err) => {
+                error!("Unable to get public info: {}", err);
+                proto::public_info_response::PublicInfoResponse::Error(err.to_string())
+            }
+        };
+
+        Ok(Response::new(proto::PublicInfoResponse {
+            public_info_response: Some(response),
+        }))
+    }
+
+    /// ListKeys unary gRPC. See [list_keys].
+    async fn list_keys(
+        &self,
+        request: tonic::Request<proto::ListKeysRequest>,
+    ) -> Result<Response<proto::ListKeysResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_list_keys(request).await {
+            Ok(keys) => {
+                info!("List keys completed successfully!");
+                proto::list_keys_response::ListKeysResponse::Keys(keys)
+            }
+            Err(err) => {
+                error!("Unable to list keys: {}", err);
+                proto::list_keys_response::ListKeysResponse::Error(err.to_string())
+            }
+        };
+
+        Ok(Response::new(proto::ListKeysResponse {
+            list_keys_response: Some(response),
+        }))
+    }
+
+    /// DeleteKey unary gRPC. See [delete_key].
+    async fn delete_key(
+        &self,
+        request: tonic::Request<proto::DeleteKeyRequest>,
+    ) -> Result<Response<proto::DeleteKeyResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_delete_key(request).await {
+            Ok(()) => {
+                info!("Delete key completed successfully!");
+                proto::delete_key_response::Response::Success
+            }
+            Err(err) => {
+                error!("Unable to delete key: {}", err);
+                proto::delete_key_response::Response::Fail
+            }
+        };
+
+        Ok(Response::new(proto::DeleteKeyResponse {
+            response: response as i32,
+        }))
+    }
+
+    /// RestoreKey unary gRPC. See [delete_key].
+    async fn restore_key(
+        &self,
+        request: tonic::Request<proto::RestoreKeyRequest>,
+    ) -> Result<Response<proto::RestoreKeyResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_restore_key(request).await {
+            Ok(()) => {
+                info!("Restore key completed successfully!");
+                proto::restore_key_response::Response::Success
+            }
+            Err(err) => {
+                error!("Unable to restore key: {}", err);
+                proto::restore_key_response::Response::Fail
+            }
+        };
+
+        Ok(Response::new(proto::RestoreKeyResponse {
+            response: response as i32,
+        }))
+    }
+
+    /// GetIdentity unary gRPC. See [identity].
+    async fn get_identity(
+        &self,
+        _request: tonic::Request<proto::IdentityRequest>,
+    ) -> Result<Response<proto::IdentityResponse>, Status> {
+        let response = match self.handle_get_identity().await {
+            Ok(identity_key) => {
+                info!("Get identity completed successfully!");
+                proto::identity_response::IdentityResponse::IdentityKey(identity_key)
+            }
+            Err(err) => {
+                error!("Unable to get identity: {}", err);
+                proto::identity_response::IdentityResponse::Error(err.to_string())
+            }
+        };
+
+        Ok(Response::new(proto::IdentityResponse {
+            identity_response: Some(response),
+        }))
+    }
+
+    /// VerifyDispute unary gRPC. See [dispute].
+    async fn verify_dispute(
+        &self,
+        request: tonic::Request<proto::VerifyDisputeRequest>,
+    ) -> Result<Response<proto::VerifyDisputeResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_verify_dispute(request).await {
+            Ok(verdict) => {
+                info!("Verify dispute completed successfully!");
+                proto::verify_dispute_response::VerifyDisputeResponse::Verdict(verdict as i32)
+            }
+            Err(err) => {
+                error!("Unable to verify dispute: {}", err);
+                proto::verify_dispute_response::VerifyDisputeResponse::Error(err.to_string())
+            }
+        };
+
+        Ok(Response::new(proto::VerifyDisputeResponse {
+            verify_dispute_response: Some(response),
+        }))
+    }
+
+    /// FinalizeRefresh unary gRPC. See [refresh].
+    async fn finalize_refresh(
+        &self,
+        request: tonic::Request<proto::FinalizeRefreshRequest>,
+    ) -> Result<Response<proto::FinalizeRefreshResponse>, Status> {
+        let request = request.into_inner();
+
+        let response = match self.handle_finalize_refresh(request).await {
+            Ok(()) => {
+                info!("Finalize refresh completed successfully!");
+                proto::finalize_refresh_response::Response::Success
+            }
+            Err(err) => {
+                error!("Unable to finalize refresh: {}", err);
+                proto::finalize_refresh_response::Response::Fail
+            }
+        };
+
+        Ok(Response::new(proto::FinalizeRefreshResponse {
+            response: response as i32,
+        }))
+    }
+
     /// Keygen streaming gRPC. See [keygen].
     async fn keygen(
         &self,
@@ -81,4 +362,64 @@ impl proto::gg20_server::Gg20 for service::Gg20Service {
 
         Ok(Response::new(UnboundedReceiverStream::new(rx)))
     }
+
+    /// Refresh streaming gRPC. See [refresh].
+    async fn refresh(
+        &self,
+        request: Request<tonic::Streaming<proto::MessageIn>>,
+    ) -> Result<Response<Self::RefreshStream>, Status> {
+        info!("Refresh called succesfully!");
+
+        let stream_in = request.into_inner();
+        let (msg_sender, rx) = mpsc::unbounded_channel();
+
+        // log span for refresh
+        let span = span!(Level::INFO, "Refresh");
+        let _enter = span.enter();
+        let s = span.clone();
+        let gg20 = self.clone();
+
+        tokio::spawn(async move {
+            // can't return an error from a spawned thread
+            if let Err(e) = gg20.handle_refresh(stream_in, msg_sender.clone(), s).await {
+                error!("refresh failure: {:?}", e.to_string());
+                // we can't handle errors in tokio threads. Log error if we are unable to send the status code to client.
+                if let Err(e) = msg_sender.send(Err(Status::invalid_argument(e.to_string()))) {
+                    error!("could not send error to client: {}", e.to_string());
+                }
+            }
+        });
+
+        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+    }
+
+    /// Reshare streaming gRPC. See [reshare].
+    async fn reshare(
+        &self,
+        request: Request<tonic::Streaming<proto::MessageIn>>,
+    ) -> Result<Response<Self::ReshareStream>, Status> {
+        info!("Reshare called succesfully!");
+
+        let stream_in = request.into_inner();
+        let (msg_sender, rx) = mpsc::unbounded_channel();
+
+        // log span for reshare
+        let span = span!(Level::INFO, "Reshare");
+        let _enter = span.enter();
+        let s = span.clone();
+        let gg20 = self.clone();
+
+        tokio::spawn(async move {
+            // can't return an error from a spawned thread
+            if let Err(e) = gg20.handle_reshare(stream_in, msg_sender.clone(), s).await {
+                error!("reshare failure: {:?}", e.to_string());
+                // we can't handle errors in tokio threads. Log error if we are unable to send the status code to client.
+                if let Err(e) = msg_sender.send(Err(Status::invalid_argument(e.to_string()))) {
+                    error!("could not send error to client: {}", e.to_string());
+                }
+            }
+        });
+
+        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+    }
 }
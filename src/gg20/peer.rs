@@ -0,0 +1,593 @@
+//! Direct transport between tofnd daemons.
+//!
+//! By default, the client relays all protocol traffic across the Keygen stream. If addresses of the parties are known,
+//! either from [proto::KeygenInit] or from tofnd's config, tofnd sends its traffic straight to the [proto::peer_server::Peer]
+//! service of the other daemons instead, and the client only starts the session and receives its result.
+//!
+//! Incoming traffic is routed to the protocol threads of a session through the [PeerSessions] registry.
+//! Traffic that arrives before its session has started is buffered until the session is registered, or until it expires.
+//! Peer traffic is not trusted: it is authenticated by the identity keys of the parties like relayed traffic.
+
+use super::{broadcast::ShareSenders, proto, service::Gg20Service};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// tonic cruft
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tonic::{transport::Endpoint, Request, Response, Status};
+
+// logging
+use tracing::{debug, error, span, warn, Level, Span};
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+/// maximum number of messages that are buffered for a session that has not started yet
+const MAX_PENDING_MSGS: usize = 1024;
+/// maximum number of sessions that have buffered messages
+const MAX_PENDING_SESSIONS: usize = 64;
+/// maximum number of sessions with buffered messages that a single sender can open
+const MAX_PENDING_SESSIONS_PER_SENDER: usize = 8;
+/// time after which buffered messages of a session that has not started are dropped
+const PENDING_TTL: Duration = Duration::from_secs(300);
+/// number of attempts to deliver a message to a peer
+const DELIVERY_ATTEMPTS: usize = 10;
+/// delay between attempts to deliver a message to a peer
+const DELIVERY_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// deadline of each attempt to deliver a message to a peer
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Sessions {
+    active: HashMap<String, ShareSenders>, // channels to the protocol threads of each active session
+    pending: HashMap<String, Pending>,     // traffic of sessions that have not started yet
+}
+
+/// Traffic of a session that has not started yet
+struct Pending {
+    opened_at: Instant,
+    opened_by: String, // the sender of the first message of the session
+    traffic: Vec<proto::TrafficIn>,
+}
+
+impl Sessions {
+    /// Make room for a new pending session opened by `sender`: drop expired sessions, then the oldest one if still full.
+    fn make_room(&mut self, sender: &str, now: Instant) -> Result<(), &'static str> {
+        self.pending
+            .retain(|_, pending| now.duration_since(pending.opened_at) < PENDING_TTL);
+
+        let opened_by_sender = self
+            .pending
+            .values()
+            .filter(|pending| pending.opened_by == sender)
+            .count();
+        if opened_by_sender >= MAX_PENDING_SESSIONS_PER_SENDER {
+            return Err("too many pending sessions of sender");
+        }
+
+        if self.pending.len() >= MAX_PENDING_SESSIONS {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, pending)| pending.opened_at)
+                .map(|(session_id, _)| session_id.clone());
+            if let Some(oldest) = oldest {
+                warn!("drop buffered msgs of pending session {}", oldest);
+                self.pending.remove(&oldest);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Registry of sessions that receive traffic from peers
+#[derive(Clone, Default)]
+pub struct PeerSessions {
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+impl PeerSessions {
+    /// Register the channels of the protocol threads of `session_id` and replay any traffic that arrived early.
+    /// The session is unregistered when the returned [SessionGuard] is dropped.
+    pub(super) fn register(&self, session_id: &str, senders: ShareSenders) -> SessionGuard {
+        let mut sessions = self.sessions.lock().unwrap();
+        let pending = sessions.pending.remove(session_id);
+        for traffic in pending.map(|pending| pending.traffic).unwrap_or_default() {
+            senders.send(traffic);
+        }
+        sessions.active.insert(session_id.to_owned(), senders);
+
+        SessionGuard {
+            sessions: self.clone(),
+            session_id: session_id.to_owned(),
+        }
+    }
+
+    /// Route traffic of `sender` to the protocol threads of `session_id`, or buffer it if the session has not started yet.
+    pub(super) fn deliver(
+        &self,
+        session_id: &str,
+        sender: &str,
+        traffic: proto::TrafficIn,
+    ) -> Result<(), &'static str> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(senders) = sessions.active.get(session_id) {
+            senders.send(traffic);
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        if !sessions.pending.contains_key(session_id) {
+            sessions.make_room(sender, now)?;
+        }
+        let pending = sessions
+            .pending
+            .entry(session_id.to_owned())
+            .or_insert_with(|| Pending {
+                opened_at: now,
+                opened_by: sender.to_owned(),
+                traffic: Vec::new(),
+            });
+        if pending.traffic.len() >= MAX_PENDING_MSGS {
+            return Err("too many pending messages for session");
+        }
+        pending.traffic.push(traffic);
+        Ok(())
+    }
+}
+
+/// Unregisters a session from [PeerSessions] on drop
+pub(super) struct SessionGuard {
+    sessions: PeerSessions,
+    session_id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let mut sessions = self.sessions.sessions.lock().unwrap();
+        sessions.active.remove(&self.session_id);
+    }
+}
+
+/// PeerService
+#[derive(Clone)]
+pub struct PeerService {
+    sessions: PeerSessions,
+}
+
+/// create a new Peer gRPC server
+pub fn new_service(sessions: PeerSessions) -> impl proto::peer_server::Peer {
+    PeerService { sessions }
+}
+
+#[tonic::async_trait]
+impl proto::peer_server::Peer for PeerService {
+    /// Deliver unary gRPC. Routes traffic of a peer to the protocol threads of its session.
+    async fn deliver(
+        &self,
+        request: Request<proto::PeerMessage>,
+    ) -> Result<Response<proto::PeerAck>, Status> {
+        let remote_addr = request.remote_addr();
+        let proto::PeerMessage {
+            session_id,
+            traffic,
+        } = request.into_inner();
+        let traffic = traffic.ok_or_else(|| Status::invalid_argument("missing `traffic` field"))?;
+
+        // the claimed uid of the sender is not authenticated yet, so senders are told apart by their address
+        let sender = match remote_addr {
+            Some(address) => address.ip().to_string(),
+            None => traffic.from_party_uid.clone(),
+        };
+        self.sessions
+            .deliver(&session_id, &sender, traffic)
+            .map_err(|err| {
+                warn!("unable to deliver msg of session {}: {}", session_id, err);
+                Status::resource_exhausted(err)
+            })?;
+
+        Ok(Response::new(proto::PeerAck {}))
+    }
+}
+
+/// Sends the outgoing traffic of a session to the other parties
+pub(super) struct PeerRouter {
+    sessions: PeerSessions,
+    session_id: String,
+    party_uids: Vec<String>,
+    my_index: usize,
+    peers: Vec<Option<UnboundedSender<proto::PeerMessage>>>, // delivery queue of each peer; `None` for us
+}
+
+impl PeerRouter {
+    /// Create a router for `session_id`. `addresses` is alligned with `party_uids`; our own address is not used.
+    /// Spawns a delivery thread for each peer.
+    pub(super) fn new(
+        sessions: PeerSessions,
+        session_id: &str,
+        party_uids: &[String],
+        my_index: usize,
+        addresses: &[String],
+        span: Span,
+    ) -> TofndResult<Self> {
+        if addresses.len() != party_uids.len() {
+            return Err(anyhow!(
+                "expected {} peer addresses, got {}",
+                party_uids.len(),
+                addresses.len()
+            ));
+        }
+
+        let mut peers = Vec::with_capacity(party_uids.len());
+        for (i, (uid, address)) in party_uids.iter().zip(addresses).enumerate() {
+            if i == my_index {
+                peers.push(None);
+                continue;
+            }
+
+            let endpoint = peer_endpoint(address)
+                .map_err(|err| anyhow!("invalid address of party {}: {}", uid, err))?;
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let peer_span = span!(parent: &span, Level::DEBUG, "peer", uid = uid.as_str());
+            tokio::spawn(deliver_to_peer(endpoint, receiver, peer_span));
+            peers.push(Some(sender));
+        }
+
+        Ok(Self {
+            sessions,
+            session_id: session_id.to_owned(),
+            party_uids: party_uids.to_vec(),
+            my_index,
+            peers,
+        })
+    }
+
+    /// Route outgoing messages until all protocol threads are done.
+    /// Traffic is sent to its recipients; all other messages are forwarded to the client.
+    pub(super) async fn run(
+        self,
+        mut receiver: UnboundedReceiver<Result<proto::MessageOut, Status>>,
+        client_sender: UnboundedSender<Result<proto::MessageOut, Status>>,
+    ) {
+        while let Some(msg) = receiver.recv().await {
+            match msg {
+                Ok(proto::MessageOut {
+                    data: Some(proto::message_out::Data::Traffic(traffic)),
+                }) => self.route(traffic),
+                msg => {
+                    let _ = client_sender.send(msg);
+                }
+            }
+        }
+    }
+
+    fn route(&self, traffic: proto::TrafficOut) {
+        let recipients: Vec<usize> = if traffic.is_broadcast {
+            (0..self.party_uids.len()).collect()
+        } else {
+            match self
+                .party_uids
+                .iter()
+                .position(|uid| uid == &traffic.to_party_uid)
+            {
+                Some(to) => vec![to],
+                None => {
+                    error!(
+                        "ignore outgoing msg: unknown recipient {}",
+                        traffic.to_party_uid
+                    );
+                    return;
+                }
+            }
+        };
+
+        let traffic = proto::TrafficIn {
+            from_party_uid: self.party_uids[self.my_index].clone(),
+            payload: traffic.payload,
+            is_broadcast: traffic.is_broadcast,
+            round_num: traffic.round_num,
+            signature: traffic.signature,
+            is_echo: traffic.is_echo,
+            from_share_id: traffic.from_share_id,
+            to_share_id: traffic.to_share_id,
+            key_index: traffic.key_index,
+        };
+
+        for to in recipients {
+            match &self.peers[to] {
+                Some(peer) => {
+                    let _ = peer.send(proto::PeerMessage {
+                        session_id: self.session_id.clone(),
+                        traffic: Some(traffic.clone()),
+                    });
+                }
+                // our own messages are looped back to our shares
+                None => {
+                    let me = &self.party_uids[self.my_index];
+                    if let Err(err) = self.sessions.deliver(&self.session_id, me, traffic.clone()) {
+                        error!("unable to deliver own msg: {}", err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Gg20Service {
+    /// Get the peer addresses of a session, alligned with `party_uids`.
+    /// Addresses of the init message take precedence over the peers of tofnd's config.
+    /// Returns `None` if no addresses are known, in which case traffic is relayed by the client.
+    pub(super) fn peer_addresses(
+        &self,
+        party_uids: &[String],
+        my_index: usize,
+        party_addresses: &[String],
+    ) -> TofndResult<Option<Vec<String>>> {
+        if !party_addresses.is_empty() {
+            return Ok(Some(party_addresses.to_vec()));
+        }
+        if self.cfg.peers.is_empty() {
+            return Ok(None);
+        }
+
+        let addresses = party_uids
+            .iter()
+            .enumerate()
+            .map(|(i, uid)| match self.cfg.peers.get(uid) {
+                Some(address) => Ok(address.clone()),
+                None if i == my_index => Ok(String::new()),
+                None => Err(anyhow!("no peer address for party {}", uid)),
+            })
+            .collect::<TofndResult<_>>()?;
+        Ok(Some(addresses))
+    }
+}
+
+/// parse the address of a peer; addresses without a scheme default to http
+fn peer_endpoint(address: &str) -> TofndResult<Endpoint> {
+    let address = match address.contains("://") {
+        true => address.to_owned(),
+        false => format!("http://{}", address),
+    };
+    Ok(Endpoint::from_shared(address)?
+        .connect_timeout(DELIVERY_TIMEOUT)
+        .timeout(DELIVERY_TIMEOUT))
+}
+
+/// deliver messages to a peer in order. Each message is retried until the peer accepts it or attempts run out.
+async fn deliver_to_peer(
+    endpoint: Endpoint,
+    mut receiver: UnboundedReceiver<proto::PeerMessage>,
+    span: Span,
+) {
+    let mut client = proto::peer_client::PeerClient::new(endpoint.connect_lazy());
+
+    while let Some(msg) = receiver.recv().await {
+        for attempt in 1..=DELIVERY_ATTEMPTS {
+            match client.deliver(Request::new(msg.clone())).await {
+                Ok(_) => break,
+                Err(status) => {
+                    let _enter = span.enter();
+                    if attempt == DELIVERY_ATTEMPTS {
+                        error!("unable to deliver msg to {}: {}", endpoint.uri(), status);
+                    } else {
+                        debug!(
+                            "attempt {} to deliver msg to {} failed: {}",
+                            attempt,
+                            endpoint.uri(),
+                            status
+                        );
+                    }
+                }
+            }
+            tokio::time::sleep(DELIVERY_RETRY_DELAY).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::identity::ShareIds;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    /// start a peer gRPC server on a free localhost port
+    async fn spawn_daemon() -> (PeerSessions, String) {
+        let sessions = PeerSessions::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let service = proto::peer_server::PeerServer::new(new_service(sessions.clone()));
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        (sessions, address)
+    }
+
+    fn traffic_out(to: &str, payload: &[u8], is_broadcast: bool) -> proto::MessageOut {
+        let share_ids = ShareIds::new(0, if is_broadcast { 0 } else { 2 });
+        proto::MessageOut::new_traffic(to, payload, is_broadcast, "0", share_ids, vec![])
+    }
+
+    async fn recv(receiver: &mut UnboundedReceiver<Option<proto::TrafficIn>>) -> proto::TrafficIn {
+        tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_peer_transport() {
+        let uids: Vec<String> = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let session_id = "session";
+
+        let mut daemons = Vec::new();
+        for _ in &uids {
+            daemons.push(spawn_daemon().await);
+        }
+        let addresses: Vec<String> = daemons.iter().map(|(_, address)| address.clone()).collect();
+
+        // parties "a" and "b" start the session; "c" starts it late
+        let mut receivers = Vec::new();
+        let mut guards = Vec::new();
+        for (sessions, _) in &daemons[..2] {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            guards.push(sessions.register(session_id, ShareSenders::party(sender)));
+            receivers.push(receiver);
+        }
+
+        // "a" sends a bcast, a p2p to "c", and its result
+        let router = PeerRouter::new(
+            daemons[0].0.clone(),
+            session_id,
+            &uids,
+            0,
+            &addresses,
+            span!(Level::DEBUG, "test"),
+        )
+        .unwrap();
+        let (protocol_sender, protocol_receiver) = mpsc::unbounded_channel();
+        let (client_sender, mut client_receiver) = mpsc::unbounded_channel();
+        tokio::spawn(router.run(protocol_receiver, client_sender));
+
+        protocol_sender
+            .send(Ok(traffic_out("", b"bcast", true)))
+            .unwrap();
+        protocol_sender
+            .send(Ok(traffic_out("c", b"p2p", false)))
+            .unwrap();
+        protocol_sender
+            .send(Ok(proto::MessageOut::need_recover()))
+            .unwrap();
+
+        // bcast reaches "a" itself and "b"
+        for receiver in receivers.iter_mut() {
+            let traffic = recv(receiver).await;
+            assert_eq!(traffic.from_party_uid, "a");
+            assert_eq!(traffic.payload, b"bcast");
+            assert!(traffic.is_broadcast);
+        }
+
+        // non-traffic messages go to the client
+        let msg = client_receiver.recv().await.unwrap().unwrap();
+        assert_eq!(msg, proto::MessageOut::need_recover());
+
+        // wait until the buffered traffic of "c" arrives, then start its session
+        for _ in 0..100 {
+            if daemons[2]
+                .0
+                .sessions
+                .lock()
+                .unwrap()
+                .pending
+                .get(session_id)
+                .map(|pending| pending.traffic.len())
+                == Some(2)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let _guard = daemons[2]
+            .0
+            .register(session_id, ShareSenders::party(sender));
+        assert_eq!(recv(&mut receiver).await.payload, b"bcast");
+        let p2p = recv(&mut receiver).await;
+        assert_eq!(p2p.payload, b"p2p");
+        assert!(!p2p.is_broadcast);
+        assert_eq!(p2p.to_share_id, 2);
+
+        // "b" did not get the p2p
+        assert!(receivers[1].try_recv().is_err());
+    }
+
+    #[test]
+    fn test_session_registry() {
+        let sessions = PeerSessions::default();
+
+        // traffic of unknown sessions is buffered up to a limit
+        for _ in 0..MAX_PENDING_MSGS {
+            sessions
+                .deliver("s", "a", proto::TrafficIn::default())
+                .unwrap();
+        }
+        assert!(sessions
+            .deliver("s", "a", proto::TrafficIn::default())
+            .is_err());
+
+        // registering replays buffered traffic
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let guard = sessions.register("s", ShareSenders::party(sender));
+        for _ in 0..MAX_PENDING_MSGS {
+            assert!(receiver.try_recv().unwrap().is_some());
+        }
+        sessions
+            .deliver("s", "a", proto::TrafficIn::default())
+            .unwrap();
+        assert!(receiver.try_recv().unwrap().is_some());
+
+        // dropping the guard unregisters the session
+        drop(guard);
+        sessions
+            .deliver("s", "a", proto::TrafficIn::default())
+            .unwrap();
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_pending_sessions() {
+        let sessions = PeerSessions::default();
+        let deliver = |session_id: &str, sender: &str| {
+            sessions.deliver(session_id, sender, proto::TrafficIn::default())
+        };
+        let pending = |session_id: &str| {
+            let sessions = sessions.sessions.lock().unwrap();
+            sessions.pending.contains_key(session_id)
+        };
+
+        // a sender can only open a limited number of pending sessions
+        for i in 0..MAX_PENDING_SESSIONS_PER_SENDER {
+            deliver(&format!("junk {}", i), "junk").unwrap();
+        }
+        assert!(deliver("one too many", "junk").is_err());
+        assert!(!pending("one too many"));
+
+        // other senders can still add traffic to those sessions, and open their own
+        deliver("junk 0", "a").unwrap();
+        deliver("s", "a").unwrap();
+
+        // expired sessions are dropped
+        {
+            let mut sessions = sessions.sessions.lock().unwrap();
+            let now = Instant::now();
+            sessions.pending.get_mut("junk 0").unwrap().opened_at = now - PENDING_TTL;
+            sessions.pending.get_mut("junk 1").unwrap().opened_at = now - Duration::from_secs(1);
+        }
+        deliver("s 0", "b").unwrap();
+        assert!(!pending("junk 0"));
+        deliver("one too many", "junk").unwrap();
+
+        // once full, the oldest session is dropped
+        let pending_len = || sessions.sessions.lock().unwrap().pending.len();
+        let mut i = 1;
+        while pending_len() < MAX_PENDING_SESSIONS {
+            deliver(&format!("s {}", i), &format!("sender {}", i)).unwrap();
+            i += 1;
+        }
+        assert!(pending("junk 1"));
+        deliver("new", "c").unwrap();
+        assert!(pending("new"));
+        assert!(!pending("junk 1"));
+        assert!(pending("s"));
+        assert_eq!(pending_len(), MAX_PENDING_SESSIONS);
+    }
+}
@@ -2,31 +2,58 @@
 
 use tofn::{collections::FillVecMap, gg20::keygen::KeygenPartyId, sdk::api::Fault};
 
+use super::identity::ShareIds;
+use super::protocol::{FaultRecord, ProtocolReport, ShareInfoDispute};
 use crate::proto;
+use group::GroupEncoding;
 type KeygenFaults = FillVecMap<KeygenPartyId, Fault>;
 type KeygenResultData = Result<proto::KeygenOutput, KeygenFaults>;
+type RefreshResultData = Result<proto::RefreshOutput, KeygenFaults>;
+type ReshareResultData = Result<proto::ReshareOutput, KeygenFaults>;
 use proto::message_out::criminal_list::criminal::CrimeType as ProtoCrimeType;
 use proto::message_out::criminal_list::Criminal as ProtoCriminal;
 use proto::message_out::keygen_result::KeygenResultData::Criminals as ProtoKeygenCriminals;
 use proto::message_out::keygen_result::KeygenResultData::Data as ProtoKeygenData;
+use proto::message_out::refresh_result::RefreshResultData::Criminals as ProtoRefreshCriminals;
+use proto::message_out::refresh_result::RefreshResultData::Data as ProtoRefreshData;
+use proto::message_out::reshare_result::ReshareResultData::Criminals as ProtoReshareCriminals;
+use proto::message_out::reshare_result::ReshareResultData::Data as ProtoReshareData;
 use proto::message_out::CriminalList as ProtoCriminalList;
 
 // convenience constructors
 impl proto::MessageOut {
-    pub(super) fn new_bcast(bcast: &[u8], round_num: &str) -> Self {
-        Self::new_traffic("", bcast, true, round_num)
+    pub(super) fn new_bcast(
+        bcast: &[u8],
+        round_num: &str,
+        share_ids: ShareIds,
+        signature: Vec<u8>,
+    ) -> Self {
+        Self::new_traffic("", bcast, true, round_num, share_ids, signature)
     }
-    pub(super) fn new_bcast_r3(bcast: &[u8], round_num: &str) -> Self {
-        Self::new_traffic("r3", bcast, true, round_num)
+    pub(super) fn new_bcast_r3(
+        bcast: &[u8],
+        round_num: &str,
+        share_ids: ShareIds,
+        signature: Vec<u8>,
+    ) -> Self {
+        Self::new_traffic("r3", bcast, true, round_num, share_ids, signature)
     }
-    pub(super) fn new_p2p(receiver_id: &str, p2p: &[u8], round_num: &str) -> Self {
-        Self::new_traffic(receiver_id, p2p, false, round_num)
+    pub(super) fn new_p2p(
+        receiver_id: &str,
+        p2p: &[u8],
+        round_num: &str,
+        share_ids: ShareIds,
+        signature: Vec<u8>,
+    ) -> Self {
+        Self::new_traffic(receiver_id, p2p, false, round_num, share_ids, signature)
     }
     pub(super) fn new_traffic(
         receiver_id: &str,
         msg: &[u8],
         is_broadcast: bool,
         round_num: &str,
+        share_ids: ShareIds,
+        signature: Vec<u8>,
     ) -> Self {
         proto::MessageOut {
             data: Some(proto::message_out::Data::Traffic(proto::TrafficOut {
@@ -34,6 +61,31 @@ impl proto::MessageOut {
                 payload: msg.to_vec(),
                 is_broadcast,
                 round_num: round_num.to_string(),
+                signature,
+                is_echo: false,
+                from_share_id: share_ids.from as u32,
+                to_share_id: share_ids.to as u32,
+                key_index: 0,
+            })),
+        }
+    }
+    pub(super) fn new_echo(
+        echo: &[u8],
+        round_num: &str,
+        from_share_id: usize,
+        signature: Vec<u8>,
+    ) -> Self {
+        proto::MessageOut {
+            data: Some(proto::message_out::Data::Traffic(proto::TrafficOut {
+                to_party_uid: String::new(),
+                payload: echo.to_vec(),
+                is_broadcast: true,
+                round_num: round_num.to_string(),
+                signature,
+                is_echo: true,
+                from_share_id: from_share_id as u32,
+                to_share_id: 0,
+                key_index: 0,
             })),
         }
     }
@@ -43,24 +95,103 @@ impl proto::MessageOut {
         }
     }
 
-    pub(super) fn new_keygen_result(participant_uids: &[String], result: KeygenResultData) -> Self {
+    pub(super) fn new_keygen_result(
+        participant_uids: &[String],
+        result: KeygenResultData,
+        report: &ProtocolReport,
+    ) -> Self {
+        proto::MessageOut {
+            data: Some(proto::message_out::Data::KeygenResult(
+                proto::message_out::KeygenResult::new(participant_uids, Some(result), report),
+            )),
+        }
+    }
+
+    /// result of a keygen of several keys; `None` for the keys that completed if the batch failed
+    pub(super) fn new_batch_keygen_result(
+        participant_uids: &[String],
+        results: Vec<(Option<KeygenResultData>, ProtocolReport)>,
+    ) -> Self {
+        proto::MessageOut {
+            data: Some(proto::message_out::Data::BatchKeygenResult(
+                proto::message_out::BatchKeygenResult {
+                    results: results
+                        .into_iter()
+                        .map(|(result, report)| {
+                            proto::message_out::KeygenResult::new(participant_uids, result, &report)
+                        })
+                        .collect(),
+                },
+            )),
+        }
+    }
+
+    pub(super) fn new_refresh_result(
+        participant_uids: &[String],
+        result: RefreshResultData,
+        report: &ProtocolReport,
+    ) -> Self {
         let result = match result {
-            Ok(keygen_output) => ProtoKeygenData(keygen_output),
-            Err(faults) => ProtoKeygenCriminals(ProtoCriminalList::from_tofn_faults(
+            Ok(refresh_output) => ProtoRefreshData(refresh_output),
+            Err(faults) => ProtoRefreshCriminals(ProtoCriminalList::from_tofn_faults(
                 faults,
                 participant_uids,
+                report,
             )),
         };
         proto::MessageOut {
-            data: Some(proto::message_out::Data::KeygenResult(
-                proto::message_out::KeygenResult {
-                    keygen_result_data: Some(result),
+            data: Some(proto::message_out::Data::RefreshResult(
+                proto::message_out::RefreshResult {
+                    refresh_result_data: Some(result),
+                },
+            )),
+        }
+    }
+
+    pub(super) fn new_reshare_result(
+        participant_uids: &[String],
+        result: ReshareResultData,
+        report: &ProtocolReport,
+    ) -> Self {
+        let result = match result {
+            Ok(reshare_output) => ProtoReshareData(reshare_output),
+            Err(faults) => ProtoReshareCriminals(ProtoCriminalList::from_tofn_faults(
+                faults,
+                participant_uids,
+                report,
+            )),
+        };
+        proto::MessageOut {
+            data: Some(proto::message_out::Data::ReshareResult(
+                proto::message_out::ReshareResult {
+                    reshare_result_data: Some(result),
                 },
             )),
         }
     }
 }
 
+impl proto::message_out::KeygenResult {
+    fn new(
+        participant_uids: &[String],
+        result: Option<KeygenResultData>,
+        report: &ProtocolReport,
+    ) -> Self {
+        let result = result.map(|result| match result {
+            Ok(keygen_output) => ProtoKeygenData(keygen_output),
+            Err(faults) => ProtoKeygenCriminals(ProtoCriminalList::from_tofn_faults(
+                faults,
+                participant_uids,
+                report,
+            )),
+        });
+        Self {
+            keygen_result_data: result,
+            complaints: report.complaints.clone(),
+        }
+    }
+}
+
 fn fault_to_crime(f: &Fault) -> ProtoCrimeType {
     match f {
         Fault::MissingMessage | Fault::CorruptedMessage => ProtoCrimeType::NonMalicious,
@@ -69,14 +200,122 @@ fn fault_to_crime(f: &Fault) -> ProtoCrimeType {
 }
 
 impl ProtoCriminalList {
-    fn from_tofn_faults<P>(faults: FillVecMap<P, Fault>, uids: &[String]) -> Self {
+    /// Faults that tofnd detected are detailed by the `report`. A record of the `report` only details a fault
+    /// of tofn if both agree on the fault; otherwise, e.g. if tofn blames a party that tofnd saw time out for a
+    /// protocol fault, the fault is reported as tofn detected it, without a round.
+    fn from_tofn_faults<P>(
+        faults: FillVecMap<P, Fault>,
+        uids: &[String],
+        report: &ProtocolReport,
+    ) -> Self {
         let criminals = faults
             .into_iter_some()
-            .map(|(i, fault)| ProtoCriminal {
-                party_uid: uids[i.as_usize()].clone(),
-                crime_type: fault_to_crime(&fault) as i32, // why `as i32`? https://github.com/danburkert/prost#enumerations
+            .map(|(i, fault)| {
+                let (round, kind, reason) = match report.faults.get(&i.as_usize()) {
+                    Some(record) if record.fault() == fault => (
+                        Some(record.round as u32),
+                        record.kind,
+                        record.reason.clone(),
+                    ),
+                    _ => {
+                        let (kind, reason) = FaultRecord::describe_tofn(&fault);
+                        (None, kind, reason.to_string())
+                    }
+                };
+                ProtoCriminal {
+                    party_uid: uids[i.as_usize()].clone(),
+                    crime_type: fault_to_crime(&fault) as i32, // why `as i32`? https://github.com/danburkert/prost#enumerations
+                    round,
+                    fault_kind: kind as i32,
+                    reason,
+                }
             })
             .collect();
         Self { criminals }
     }
 }
+
+impl proto::Complaint {
+    pub(super) fn new(
+        accuser_uid: &str,
+        accused_uid: &str,
+        round: usize,
+        dispute: &ShareInfoDispute,
+        encrypted_share: Option<proto::EncryptedShare>,
+    ) -> Self {
+        let (c, z) = &dispute.proof;
+        Self {
+            accuser_uid: accuser_uid.to_string(),
+            accused_uid: accused_uid.to_string(),
+            round: round as u32,
+            share_index: dispute.share.index as u32,
+            share: dispute.share.scalar.to_bytes().to_vec(),
+            kij: dispute.kij.to_bytes().as_ref().to_vec(),
+            proof: [&c[..], &z[..]].concat(),
+            encrypted_share,
+        }
+    }
+}
+
+impl proto::EncryptedShare {
+    pub(super) fn new(
+        session_id: &str,
+        round_num: &str,
+        share_ids: ShareIds,
+        payload: &[u8],
+        signature: &[u8],
+        p2p_key: [u8; 32],
+    ) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            round_num: round_num.to_string(),
+            from_share_id: share_ids.from as u32,
+            to_share_id: share_ids.to as u32,
+            payload: payload.to_vec(),
+            signature: signature.to_vec(),
+            p2p_key: p2p_key.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::message_out::criminal_list::criminal::FaultKind;
+    use tofn::collections::TypedUsize;
+
+    #[test]
+    fn test_criminal_list() {
+        let uids: Vec<String> = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let mut faults = FillVecMap::<KeygenPartyId, Fault>::with_size(3);
+        faults
+            .set(TypedUsize::from_usize(0), Fault::CorruptedMessage)
+            .unwrap();
+        faults
+            .set(TypedUsize::from_usize(1), Fault::ProtocolFault)
+            .unwrap();
+        faults
+            .set(TypedUsize::from_usize(2), Fault::MissingMessage)
+            .unwrap();
+
+        // "a" sent a bad signature, and "b" timed out but was blamed for a protocol fault by tofn
+        let mut report = ProtocolReport::default();
+        let bad_signature = FaultRecord::new(1, FaultKind::BadSignature, "invalid signature");
+        report.faults.insert(0, bad_signature);
+        report
+            .faults
+            .insert(1, FaultRecord::new(2, FaultKind::Timeout, "timeout"));
+
+        let criminals = ProtoCriminalList::from_tofn_faults(faults, &uids, &report).criminals;
+        assert_eq!(criminals[0].round, Some(1));
+        assert_eq!(criminals[0].fault_kind, FaultKind::BadSignature as i32);
+        assert_eq!(criminals[0].reason, "invalid signature");
+
+        // records that disagree with tofn are ignored, and tofn's faults have no round
+        assert_eq!(criminals[1].round, None);
+        assert_eq!(criminals[1].fault_kind, FaultKind::Protocol as i32);
+        assert_eq!(criminals[1].crime_type, ProtoCrimeType::Malicious as i32);
+        assert_eq!(criminals[2].round, None);
+        assert_eq!(criminals[2].fault_kind, FaultKind::Missing as i32);
+    }
+}
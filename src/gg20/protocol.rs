@@ -1,18 +1,32 @@
 //! Abstract functionality used by keygen, sign, etc.
 
-use std::{convert::TryInto, time::Duration};
-use group::GroupEncoding;
 use bls12_381::G1Affine;
+use group::GroupEncoding;
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    time::Duration,
+};
 use tofn::{
-    collections::TypedUsize,
-    sdk::api::{Protocol, ProtocolOutput, Round},
+    collections::{FillVecMap, TypedUsize},
+    sdk::api::{Fault, Protocol, ProtocolOutput, Round},
 };
-use serde_json;
-use tracing_subscriber::field::debug;
 // tonic cruft
-use super::{proto, ProtocolCommunication};
-use tokio::{sync::mpsc::{UnboundedReceiver, UnboundedSender}, time::timeout};
-use serde::{Deserialize, Serialize, Serializer, ser::SerializeStruct, Deserializer};
+use super::{
+    echo::Echo,
+    identity::{ShareIds, TrafficAuth, TrafficKind},
+    proto, vss, ProtocolCommunication,
+};
+use proto::message_out::criminal_list::criminal::FaultKind;
+use serde::{
+    de::Error as _, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer,
+};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    time::{timeout_at, Instant},
+};
 // logging
 use tracing::{debug, error, span, warn, Level, Span};
 
@@ -27,24 +41,41 @@ pub struct P2pSad {
 pub(super) struct ShareInfoDispute {
     pub(super) share: Share,
     pub(super) kij: bls12_381::G1Projective,
-    pub(super) proof: ([u8; 32], [u8;32])
+    pub(super) proof: ([u8; 32], [u8; 32]),
 }
 #[derive(Clone, Debug, PartialEq)]
 
 pub struct Share {
-    scalar: bls12_381::Scalar,
-    index: usize,
+    pub(super) scalar: bls12_381::Scalar,
+    pub(super) index: usize,
 }
 impl<'de> Deserialize<'de> for ShareInfoDispute {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        
-        let (share, mut r, p) = <(Share, [u8;32], ([u8; 32], [u8;32]))>::deserialize(deserializer)?;
-      let r_vec: & [u8] = r.as_mut();
-        let kij = G1Affine::from_compressed(r_vec.try_into().unwrap()).unwrap().into();
-        Ok(ShareInfoDispute { share: share, kij: kij, proof: p } )
+        // mirrors the fields of `Serialize`; `kij` is a compressed G1 point
+        #[derive(Deserialize)]
+        struct Fields {
+            share: Share,
+            kij: Vec<u8>,
+            proof: ([u8; 32], [u8; 32]),
+        }
+        let fields = Fields::deserialize(deserializer)?;
+
+        let kij: Option<G1Affine> = fields
+            .kij
+            .as_slice()
+            .try_into()
+            .ok()
+            .and_then(|kij| G1Affine::from_compressed(kij).into());
+        let kij = kij.ok_or_else(|| D::Error::custom("invalid kij point"))?;
+
+        Ok(ShareInfoDispute {
+            share: fields.share,
+            kij: kij.into(),
+            proof: fields.proof,
+        })
     }
 }
 impl Serialize for ShareInfoDispute {
@@ -52,12 +83,11 @@ impl Serialize for ShareInfoDispute {
     where
         S: Serializer,
     {
-        let mut shareDispute = serializer.serialize_struct("ShareInfoDispute",3).unwrap();
+        let mut shareDispute = serializer.serialize_struct("ShareInfoDispute", 3).unwrap();
         shareDispute.serialize_field("share", &self.share)?;
         shareDispute.serialize_field("kij", &self.kij.to_bytes().as_ref())?;
         shareDispute.serialize_field("proof", &self.proof)?;
         shareDispute.end()
-        
     }
 }
 impl<'de> Deserialize<'de> for Share {
@@ -65,11 +95,22 @@ impl<'de> Deserialize<'de> for Share {
     where
         D: Deserializer<'de>,
     {
-        
-        let (c, r) = <([u8;32], usize)>::deserialize(deserializer)?;
-        let shareScalar = bls12_381::Scalar::from_bytes(&c).unwrap();
-    
-        Ok(Share { scalar:shareScalar, index: r })
+        // mirrors the fields of `Serialize`
+        #[derive(Deserialize)]
+        struct Fields {
+            scalar: [u8; 32],
+            index: usize,
+        }
+        let fields = Fields::deserialize(deserializer)?;
+
+        let scalar: Option<bls12_381::Scalar> =
+            bls12_381::Scalar::from_bytes(&fields.scalar).into();
+        let scalar = scalar.ok_or_else(|| D::Error::custom("invalid share scalar"))?;
+
+        Ok(Share {
+            scalar,
+            index: fields.index,
+        })
     }
 }
 impl From<Share> for (bls12_381::Scalar, usize) {
@@ -90,7 +131,328 @@ impl Serialize for Share {
         state.end()
     }
 }
-/// execute gg20 protocol
+impl P2pSad {
+    /// parse the VSS complaints of a p2p payload, if any
+    fn parse(payload: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<Self>(payload)
+            .ok()
+            .filter(|p2p_sad| !p2p_sad.vss_complaint.is_empty())
+    }
+}
+
+/// The p2p in which keygen deals a share, as far as tofnd needs it: the recipient's share, encrypted under `kij`.
+/// The scalar of the share is masked, see [super::dispute::share_mask].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct P2pShare {
+    pub(super) encrypted_u_i_share: Share,
+}
+
+impl P2pShare {
+    /// parse the dealt share of a p2p payload, if any
+    pub(super) fn parse(payload: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<Self>(payload).ok()
+    }
+}
+
+/// What a party observed during a protocol, besides tofn's output
+#[derive(Debug, Clone, Default)]
+pub struct ProtocolReport {
+    /// VSS complaints that we made or that were made against us.
+    /// Complaints are sent as p2p [P2pSad] messages from the accuser to the accused.
+    pub(super) complaints: Vec<proto::Complaint>,
+    /// faults that tofnd detected, or that explain the faults of tofn, indexed by party
+    pub(super) faults: BTreeMap<usize, FaultRecord>,
+    /// the p2ps in which shares were dealt, as evidence for complaints about them; indexed by (dealer share, recipient share)
+    dealings: HashMap<(usize, usize), proto::EncryptedShare>,
+}
+
+/// why a party faulted, and when
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct FaultRecord {
+    pub(super) round: usize,
+    pub(super) kind: FaultKind,
+    pub(super) reason: String,
+}
+
+impl FaultRecord {
+    pub(super) fn new(round: usize, kind: FaultKind, reason: impl Into<String>) -> Self {
+        Self {
+            round,
+            kind,
+            reason: reason.into(),
+        }
+    }
+
+    /// the tofn fault that corresponds to the record
+    pub(super) fn fault(&self) -> Fault {
+        match self.kind {
+            FaultKind::Missing | FaultKind::Timeout | FaultKind::Unspecified => {
+                Fault::MissingMessage
+            }
+            FaultKind::Corrupted | FaultKind::BadSignature => Fault::CorruptedMessage,
+            FaultKind::Protocol | FaultKind::Equivocation => Fault::ProtocolFault,
+        }
+    }
+
+    /// the kind and reason of a fault that tofn detected without further details; tofn does not tell its round
+    pub(super) fn describe_tofn(fault: &Fault) -> (FaultKind, &'static str) {
+        match fault {
+            Fault::MissingMessage => (FaultKind::Missing, "missing message"),
+            Fault::CorruptedMessage => (FaultKind::Corrupted, "corrupted message"),
+            Fault::ProtocolFault => (FaultKind::Protocol, "protocol fault"),
+        }
+    }
+}
+
+impl ProtocolReport {
+    /// record a fault of party `party`; only the first fault of each party is kept
+    fn record_fault(&mut self, party: usize, record: FaultRecord) {
+        self.faults.entry(party).or_insert(record);
+    }
+
+    /// mark party `party` as a faulter and record why. Parties that already faulted are left as is.
+    pub(super) fn set_fault<P>(
+        &mut self,
+        faulters: &mut FillVecMap<P, Fault>,
+        party: usize,
+        record: FaultRecord,
+    ) -> TofndResult<()> {
+        let party_id = TypedUsize::from_usize(party);
+        if faulters
+            .is_none(party_id)
+            .map_err(|_| anyhow!("party index {} out of bounds", party))?
+        {
+            faulters
+                .set(party_id, record.fault())
+                .map_err(|_| anyhow!("party index {} out of bounds", party))?;
+            self.faults.insert(party, record);
+        }
+        Ok(())
+    }
+
+    /// Keep the first p2p with share ids `share_ids` that deals a share, as evidence for complaints about the share.
+    /// `plaintext` is the decrypted payload of the p2p; `dealing` builds the evidence.
+    fn record_dealing(
+        &mut self,
+        share_ids: ShareIds,
+        plaintext: &[u8],
+        dealing: impl FnOnce() -> Option<proto::EncryptedShare>,
+    ) {
+        let key = (share_ids.from, share_ids.to);
+        if self.dealings.contains_key(&key) || P2pShare::parse(plaintext).is_none() {
+            return;
+        }
+        if let Some(dealing) = dealing() {
+            self.dealings.insert(key, dealing);
+        }
+    }
+
+    /// Record the complaints of a p2p from `accuser_uid` to `accused_uid` in round `round`.
+    /// `share_ids` are the shares of the accuser and the accused; complaints carry the dealing of the accused, if we have it.
+    fn record_p2p(
+        &mut self,
+        accuser_uid: &str,
+        accused_uid: &str,
+        round: usize,
+        share_ids: ShareIds,
+        payload: &[u8],
+    ) {
+        if let Some(p2p_sad) = P2pSad::parse(payload) {
+            warn!(
+                "party {} complains about the VSS shares of party {} in round {}",
+                accuser_uid, accused_uid, round
+            );
+            let dealing = self.dealings.get(&(share_ids.to, share_ids.from));
+            if dealing.is_none() {
+                warn!(
+                    "no dealing of share {} to share {} to back the complaint",
+                    share_ids.to, share_ids.from
+                );
+            }
+            let complaints: Vec<_> = p2p_sad
+                .vss_complaint
+                .iter()
+                .map(|dispute| {
+                    proto::Complaint::new(
+                        accuser_uid,
+                        accused_uid,
+                        round,
+                        dispute,
+                        dealing.cloned(),
+                    )
+                })
+                .collect();
+            self.complaints.extend(complaints);
+        }
+    }
+
+    /// merge the reports of all shares of a party
+    pub(super) fn merge(reports: impl IntoIterator<Item = Self>) -> Self {
+        let mut merged = Self::default();
+        for report in reports {
+            for complaint in report.complaints {
+                if !merged.complaints.contains(&complaint) {
+                    merged.complaints.push(complaint);
+                }
+            }
+            // keep the earliest fault of each party
+            for (party, record) in report.faults {
+                match merged.faults.get(&party) {
+                    Some(merged_record) if merged_record.round <= record.round => {}
+                    _ => {
+                        merged.faults.insert(party, record);
+                    }
+                }
+            }
+        }
+        merged
+    }
+}
+
+/// incoming messages of future rounds, indexed by round
+pub(super) type FutureMessages = BTreeMap<usize, Vec<proto::TrafficIn>>;
+
+/// maximum number of messages that are buffered for future rounds per sender
+const MAX_BUFFERED_MSGS_PER_SENDER: usize = 1024;
+
+/// incoming messages that can't be used yet
+pub(super) struct Inbox {
+    last_round: usize,                     // messages of later rounds are dropped
+    msgs: FutureMessages,                  // messages of future rounds
+    echoes: FutureMessages,                // echoes of the current and future rounds
+    buffered_msgs: HashMap<String, usize>, // number of buffered messages of each sender
+}
+
+impl Inbox {
+    pub(super) fn new(last_round: usize) -> Self {
+        Self {
+            last_round,
+            msgs: FutureMessages::new(),
+            echoes: FutureMessages::new(),
+            buffered_msgs: HashMap::new(),
+        }
+    }
+
+    /// Buffer a message of round `round` from one of `party_uids`.
+    /// Messages of rounds after the last one, of unknown senders, or beyond the quota of their sender are dropped.
+    pub(super) fn buffer(
+        &mut self,
+        round: usize,
+        traffic: proto::TrafficIn,
+        party_uids: &[String],
+    ) {
+        if round > self.last_round {
+            warn!(
+                "ignore incoming msg from {}: round {} is after the last round {}",
+                traffic.from_party_uid, round, self.last_round
+            );
+            return;
+        }
+        if !party_uids.contains(&traffic.from_party_uid) {
+            warn!(
+                "ignore incoming msg: unknown sender {}",
+                traffic.from_party_uid
+            );
+            return;
+        }
+        let buffered_msgs = self
+            .buffered_msgs
+            .entry(traffic.from_party_uid.clone())
+            .or_default();
+        if *buffered_msgs >= MAX_BUFFERED_MSGS_PER_SENDER {
+            warn!(
+                "ignore incoming msg from {}: too many buffered msgs",
+                traffic.from_party_uid
+            );
+            return;
+        }
+        *buffered_msgs += 1;
+
+        let msgs = match traffic.is_echo {
+            true => &mut self.echoes,
+            false => &mut self.msgs,
+        };
+        msgs.entry(round).or_default().push(traffic);
+    }
+
+    /// take the buffered messages of round `round`
+    pub(super) fn take(&mut self, round: usize) -> Vec<proto::TrafficIn> {
+        let msgs = self.msgs.remove(&round).unwrap_or_default();
+        self.release(msgs)
+    }
+
+    /// take the buffered echoes of round `round`
+    fn take_echoes(&mut self, round: usize) -> Vec<proto::TrafficIn> {
+        let echoes = self.echoes.remove(&round).unwrap_or_default();
+        self.release(echoes)
+    }
+
+    fn release(&mut self, msgs: Vec<proto::TrafficIn>) -> Vec<proto::TrafficIn> {
+        for traffic in &msgs {
+            if let Some(buffered_msgs) = self.buffered_msgs.get_mut(&traffic.from_party_uid) {
+                *buffered_msgs -= 1;
+            }
+        }
+        msgs
+    }
+}
+
+/// Get the round of an incoming message.
+/// Returns `None` if `round_num` is missing or invalid; such messages are malformed and dropped without blaming the sender.
+pub(super) fn msg_round(traffic: &proto::TrafficIn) -> Option<usize> {
+    traffic.round_num.parse().ok()
+}
+
+/// outcome of checking an incoming message against the messages already delivered this round
+#[derive(Debug, PartialEq)]
+pub(super) enum Delivery {
+    New,
+    Duplicate,
+    Equivocation,
+}
+
+/// digests of the messages that each sender delivered in the current round, per message kind
+pub(super) struct DeliveredMessages {
+    digests: HashMap<(usize, bool), Vec<[u8; 32]>>,
+}
+
+impl DeliveredMessages {
+    pub(super) fn new() -> Self {
+        Self {
+            digests: HashMap::new(),
+        }
+    }
+
+    /// check a message of party `from` that is allowed to deliver `expected` messages of its kind per round.
+    /// Exact copies of delivered messages are duplicates; more distinct messages than expected are equivocation.
+    pub(super) fn check(
+        &mut self,
+        from: usize,
+        is_broadcast: bool,
+        payload: &[u8],
+        expected: usize,
+    ) -> Delivery {
+        let digest: [u8; 32] = Sha256::digest(payload).into();
+        let digests = self.digests.entry((from, is_broadcast)).or_default();
+        if digests.contains(&digest) {
+            return Delivery::Duplicate;
+        }
+        if digests.len() >= expected {
+            return Delivery::Equivocation;
+        }
+        digests.push(digest);
+        Delivery::New
+    }
+
+    /// whether party `from` delivered any message this round
+    fn delivered_any(&self, from: usize) -> bool {
+        self.digests.keys().any(|(sender, _)| *sender == from)
+    }
+}
+
+/// Execute gg20 protocol for our share `my_share_id`.
+/// tofn runs one party per share, so its messages and faults are indexed by share; faults are attributed to the parties that hold the shares.
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn execute_protocol<F, K, P, const MAX_MSG_IN_LEN: usize>(
     mut party: Protocol<F, K, P, MAX_MSG_IN_LEN>,
     mut chans: ProtocolCommunication<
@@ -99,33 +461,84 @@ pub(super) async fn execute_protocol<F, K, P, const MAX_MSG_IN_LEN: usize>(
     >,
     party_uids: &[String],
     party_share_counts: &[usize],
+    my_share_id: usize,
+    last_round: usize,
+    round_timeout: Duration,
+    echo_broadcasts: bool,
+    traffic_auth: &TrafficAuth,
     span: Span,
-) -> TofndResult<ProtocolOutput<F, P>>
+) -> TofndResult<(ProtocolOutput<F, P>, ProtocolReport)>
 where
     K: Clone,
 {
+    let mut report = ProtocolReport::default();
+
     // set up counters for logging
     let total_num_of_shares = party_share_counts.iter().fold(0, |acc, s| acc + *s);
     let mut total_round_p2p_msgs = total_num_of_shares * (total_num_of_shares - 1); // total number of messages is n(n-1)
 
+    // messages that arrive before their round has started are replayed when the round starts
+    let mut inbox = Inbox::new(last_round);
+
     let mut round_count = 0;
     while let Protocol::NotDone(mut round) = party {
         round_count += 1;
-       
+
         // handle outgoing traffic
-        handle_outgoing(&chans.sender, &round, party_uids, round_count, span.clone())?;
+        handle_outgoing(
+            &chans.sender,
+            &round,
+            party_uids,
+            party_share_counts,
+            my_share_id,
+            round_count,
+            traffic_auth,
+            &mut report,
+            span.clone(),
+        )?;
 
-        handle_incoming(
+        let (faulters, echo) = handle_incoming(
             &mut chans.receiver,
             &mut round,
+            &mut inbox,
             party_uids,
+            party_share_counts,
+            my_share_id,
             total_round_p2p_msgs,
             total_num_of_shares,
             round_count,
+            round_timeout,
+            traffic_auth,
+            &mut report,
             span.clone(),
         )
         .await?;
 
+        // parties compare the broadcasts of this round before they use them
+        let faulters = if echo_broadcasts && round.bcast_out().is_some() && faulters.is_empty() {
+            handle_echo(
+                &mut chans,
+                &echo,
+                &mut inbox,
+                round.info().round(),
+                party_uids,
+                party_share_counts,
+                my_share_id,
+                round_timeout,
+                traffic_auth,
+                &mut report,
+                span.clone(),
+            )
+            .await?
+        } else {
+            faulters
+        };
+
+        // abort if a party equivocated or forged messages this round
+        if !faulters.is_empty() {
+            return Ok((Err(faulters), report));
+        }
+
         // check if everything was ok this round
         party = round
             .execute_next_round()
@@ -134,169 +547,821 @@ where
 
     match party {
         Protocol::NotDone(_) => Err(anyhow!("Protocol failed to complete")),
-        Protocol::Done(result) => Ok(result),
+        Protocol::Done(result) => {
+            let result = match result {
+                Ok(output) => Ok(output),
+                Err(faults) => Err(party_faults(faults, party_share_counts)?),
+            };
+            Ok((result, report))
+        }
     }
 }
 
+/// attribute the faults of tofn's shares to the parties that hold them; the first fault of a party is kept
+fn party_faults<P>(
+    share_faults: FillVecMap<P, Fault>,
+    party_share_counts: &[usize],
+) -> TofndResult<FillVecMap<P, Fault>> {
+    let mut faults = FillVecMap::with_size(party_share_counts.len());
+    for (share_id, fault) in share_faults.into_iter_some() {
+        let party = vss::share_owner(party_share_counts, share_id.as_usize())
+            .ok_or_else(|| anyhow!("share {} out of bounds", share_id))?;
+        let party_id = TypedUsize::from_usize(party);
+        if faults
+            .is_none(party_id)
+            .map_err(|_| anyhow!("party index {} out of bounds", party))?
+        {
+            faults
+                .set(party_id, fault)
+                .map_err(|_| anyhow!("party index {} out of bounds", party))?;
+        }
+    }
+    Ok(faults)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_outgoing<F, K, P, const MAX_MSG_IN_LEN: usize>(
     sender: &UnboundedSender<Result<proto::MessageOut, tonic::Status>>,
     round: &Round<F, K, P, MAX_MSG_IN_LEN>,
     party_uids: &[String],
+    party_share_counts: &[usize],
+    my_share_id: usize,
     round_count: usize,
+    traffic_auth: &TrafficAuth,
+    report: &mut ProtocolReport,
     span: Span,
 ) -> TofndResult<()> {
     let send_span = span!(parent: &span, Level::DEBUG, "outgoing", round = round_count);
     let _start = send_span.enter();
-   
+
+    let round_num = round.info().round().to_string();
+
     // send outgoing bcasts
     if let Some(bcast) = round.bcast_out() {
-     
-     if round.info().round() == 2{
-        sender.send(Ok(proto::MessageOut::new_bcast_r3(bcast, &round.info().round().to_string())))?
-     }else{
-        sender.send(Ok(proto::MessageOut::new_bcast(bcast,&round.info().round().to_string())))?
-     }
-        
-    }
-    
+        let share_ids = ShareIds::new(my_share_id, 0);
+        let signature = traffic_auth.sign(&round_num, TrafficKind::Bcast, "", share_ids, bcast);
+        if round.info().round() == 2 {
+            sender.send(Ok(proto::MessageOut::new_bcast_r3(
+                bcast, &round_num, share_ids, signature,
+            )))?
+        } else {
+            sender.send(Ok(proto::MessageOut::new_bcast(
+                bcast, &round_num, share_ids, signature,
+            )))?
+        }
+    }
+
     // send outgoing p2ps
     if let Some(p2ps_out) = round.p2ps_out() {
-
-        let mut p2p_msg_count = 1;
         for (i, p2p) in p2ps_out.iter() {
-            // get tofnd index from tofn
-            let tofnd_idx = round
-                .info()
-                .party_share_counts()
-                .share_to_party_id(i)
-                .map_err(|_| anyhow!("Unable to get tofnd index for party {}", i))?;
-
-            
-            p2p_msg_count += 1;
+            // tofn indexes p2ps by the share of their recipient; get the party that holds it
+            let to_share_id = i.as_usize();
+            let tofnd_idx = vss::share_owner(party_share_counts, to_share_id)
+                .ok_or_else(|| anyhow!("Unable to get tofnd index for share {}", i))?;
 
-            // send message to gRPC client
+            // encrypt p2p for its recipient and send message to gRPC client
+            let to_party_uid = &party_uids[tofnd_idx];
+            let share_ids = ShareIds::new(my_share_id, to_share_id);
+            report.record_p2p(
+                traffic_auth.my_uid(),
+                to_party_uid,
+                round.info().round(),
+                share_ids,
+                p2p,
+            );
+            let sealed = traffic_auth.seal(tofnd_idx, &round_num, p2p)?;
+            let signature = traffic_auth.sign(
+                &round_num,
+                TrafficKind::P2p,
+                to_party_uid,
+                share_ids,
+                &sealed,
+            );
+
+            // keep our dealings, so that complaints against them can be settled
+            report.record_dealing(share_ids, p2p, || {
+                Some(proto::EncryptedShare::new(
+                    traffic_auth.session_id(),
+                    &round_num,
+                    share_ids,
+                    &sealed,
+                    &signature,
+                    traffic_auth.p2p_key_out(tofnd_idx)?,
+                ))
+            });
             sender.send(Ok(proto::MessageOut::new_p2p(
-                &party_uids[tofnd_idx.as_usize()],
-                p2p,&round.info().round().to_string()
+                to_party_uid,
+                &sealed,
+                &round_num,
+                share_ids,
+                signature,
             )))?
         }
     }
-    
+
     Ok(())
 }
 
+/// receive messages of this round until no more messages are needed or the round's deadline passes.
+/// On timeout, the round is executed with the messages received so far and tofn marks the missing ones as faults.
+/// Messages of future rounds are buffered in `future_msgs`; messages of past rounds are dropped.
+/// Duplicate messages are dropped. Returns the parties that sent more distinct messages than expected this round,
+/// or messages whose signature does not verify against their identity key, or p2ps that can't be decrypted,
+/// along with the [Echo] of the broadcasts that were received. Complaints against us are recorded in `report`.
+#[allow(clippy::too_many_arguments)]
 async fn handle_incoming<F, K, P, const MAX_MSG_IN_LEN: usize>(
     receiver: &mut UnboundedReceiver<Option<proto::TrafficIn>>,
     round: &mut Round<F, K, P, MAX_MSG_IN_LEN>,
+    inbox: &mut Inbox,
     party_uids: &[String],
+    party_share_counts: &[usize],
+    my_share_id: usize,
     total_round_p2p_msgs: usize,
     total_num_of_shares: usize,
     round_count: usize,
+    round_timeout: Duration,
+    traffic_auth: &TrafficAuth,
+    report: &mut ProtocolReport,
     span: Span,
-) -> TofndResult<()> {
+) -> TofndResult<(FillVecMap<P, Fault>, Echo)> {
     let mut p2p_msg_count = 0;
     let mut bcast_msg_count = 0;
-    
-    let mut i = 0;
-    let mut continue_loop = round.expecting_more_msgs_this_round(); 
+
+    let mut delivered_msgs = DeliveredMessages::new();
+    let mut faulters = FillVecMap::with_size(party_uids.len());
+    let mut echo = Echo::new(party_uids.len());
+
+    let deadline = Instant::now() + round_timeout;
+    let current_round = round.info().round();
+
+    // replay messages that arrived before this round started
+    let mut buffered_msgs = inbox.take(current_round).into_iter();
+
     // loop until no more messages are needed for this round
-    while continue_loop {
-        
-        i = i+ 1;
-        
-        let traffic = receiver.recv().await.ok_or(format!(
-            "{}: stream closed by client before protocol has completed",
-            round_count
-        ));
-      
-       // debug!("now {}",i);
-        // unpeel TrafficIn
-        let traffic = match traffic.clone() {
-            Ok(traffic_opt) => match traffic_opt {
-                Some(traffic) => traffic,
-                None => {
-                    // if data is missing, ignore the message,
-                    warn!("ignore incoming msg: missing `data` field");
+    while round.expecting_more_msgs_this_round() {
+        let traffic = match buffered_msgs.next() {
+            Some(traffic) => traffic,
+            None => {
+                let traffic = match timeout_at(deadline, receiver.recv()).await {
+                    Ok(traffic) => traffic,
+                    Err(_) => {
+                        warn!(
+                            "round {} timed out after {:?}; proceeding with missing messages",
+                            round_count, round_timeout
+                        );
+                        // tofn reports the missing messages; remember that they are missing because of the deadline
+                        let my_uid = traffic_auth.my_uid();
+                        let share_ids = vss::share_indices(party_share_counts);
+                        for (i, uid) in party_uids.iter().enumerate() {
+                            if uid != my_uid
+                                && !share_ids[i]
+                                    .iter()
+                                    .any(|share_id| delivered_msgs.delivered_any(*share_id))
+                            {
+                                report.record_fault(
+                                    i,
+                                    FaultRecord::new(
+                                        current_round,
+                                        FaultKind::Timeout,
+                                        format!(
+                                            "no message before the deadline of {:?}",
+                                            round_timeout
+                                        ),
+                                    ),
+                                );
+                            }
+                        }
+                        break;
+                    }
+                };
+
+                // unpeel TrafficIn
+                let traffic = match traffic {
+                    Some(Some(traffic)) => traffic,
+                    Some(None) => {
+                        // if data is missing, ignore the message,
+                        warn!("ignore incoming msg: missing `data` field");
+                        continue;
+                    }
+                    None => {
+                        // if channel is closed, stop
+                        error!("{}: internal channel closed prematurely", round_count);
+                        break;
+                    }
+                };
+
+                // check the round of the message
+                let msg_round = match msg_round(&traffic) {
+                    Some(msg_round) => msg_round,
+                    None => {
+                        warn!(
+                            "ignore incoming msg from {}: invalid round_num {:?}",
+                            traffic.from_party_uid, traffic.round_num
+                        );
+                        continue;
+                    }
+                };
+                // echoes of this round are used after all of its messages are received
+                if traffic.is_echo {
+                    if msg_round >= current_round {
+                        inbox.buffer(msg_round, traffic, party_uids);
+                    }
                     continue;
                 }
-            },
-            Err(_) => {
-                // if channel is closed, stop
-                error!("internal channel closed prematurely");
-                break;
+
+                match msg_round.cmp(&current_round) {
+                    Ordering::Less => {
+                        warn!(
+                            "ignore incoming msg from {}: round {} is over, current round is {}",
+                            traffic.from_party_uid, msg_round, current_round
+                        );
+                        continue;
+                    }
+                    Ordering::Greater => {
+                        debug!(
+                            "buffer incoming msg from {} for round {}, current round is {}",
+                            traffic.from_party_uid, msg_round, current_round
+                        );
+                        inbox.buffer(msg_round, traffic, party_uids);
+                        continue;
+                    }
+                    Ordering::Equal => traffic,
+                }
             }
         };
-        let mut timeout_msg =false;
-        if (traffic.clone().payload.len()) >= 7{
-            if traffic.clone().payload[0..=6] == "timeout".as_bytes().to_vec(){
-                timeout_msg = true;
-            }
+
+        // p2ps are sent to one of the shares of a party; the ones of our other shares are not ours
+        if !traffic.is_broadcast && traffic.to_share_id as usize != my_share_id {
+            continue;
         }
-        if timeout_msg{
-           
-        if traffic.clone().payload == *("timeout".to_owned()+&round_count.to_string()).as_bytes().to_vec(){
-            
-            debug!("timeout {:?}",round_count);
-                continue_loop = false;
-                break;
-            
-            // continue_loop = round.expecting_more_msgs_this_round(); 
-           
-        }
-        continue_loop = round.expecting_more_msgs_this_round(); 
-    }
-        else{
-        
-       // debug!("then {}",i);
+
         // We have to spawn a new span it in each loop because `async` calls don't work well with tracing
         // See details on how we need to make spans curve around `.await`s here:
         // https://docs.rs/tracing/0.1.25/tracing/span/index.html#entering-a-span
         let recv_span = span!(parent: &span, Level::DEBUG, "incoming", round = round_count);
         let _start = recv_span.enter();
 
+        // get sender's party index
+        let from = match party_uids
+            .iter()
+            .position(|uid| uid == &traffic.from_party_uid)
+        {
+            Some(from) => from,
+            None => {
+                warn!(
+                    "ignore incoming msg: unknown sender {}",
+                    traffic.from_party_uid
+                );
+                continue;
+            }
+        };
+        let from_id = TypedUsize::from_usize(from);
+
+        // ignore parties that already faulted this round
+        if !faulters
+            .is_none(from_id)
+            .map_err(|_| anyhow!("from index {} out of bounds", from))?
+        {
+            continue;
+        }
+
+        // messages must be signed by the identity key of their sender
+        if !traffic_auth.verify(from, &traffic) {
+            warn!(
+                "invalid signature of incoming msg from {} in round {}",
+                traffic.from_party_uid, round_count
+            );
+            report.set_fault(
+                &mut faulters,
+                from,
+                FaultRecord::new(current_round, FaultKind::BadSignature, "invalid signature"),
+            )?;
+            continue;
+        }
+
+        // the sender's signature binds the share it sent the msg from, which must be one of its own
+        let from_share_id = traffic.from_share_id as usize;
+        if vss::share_owner(party_share_counts, from_share_id) != Some(from) {
+            warn!(
+                "incoming msg from {} in round {} claims share {}, which is not its own",
+                traffic.from_party_uid, round_count, from_share_id
+            );
+            report.set_fault(
+                &mut faulters,
+                from,
+                FaultRecord::new(
+                    current_round,
+                    FaultKind::Corrupted,
+                    "sent a msg from a share other than its own",
+                ),
+            )?;
+            continue;
+        }
+
+        // each share of the sender delivers one message of each kind per round
+        match delivered_msgs.check(from_share_id, traffic.is_broadcast, &traffic.payload, 1) {
+            Delivery::New => {}
+            Delivery::Duplicate => {
+                warn!(
+                    "ignore duplicate incoming msg from {} in round {}",
+                    traffic.from_party_uid, round_count
+                );
+                continue;
+            }
+            Delivery::Equivocation => {
+                let kind = if traffic.is_broadcast { "bcast" } else { "p2p" };
+                warn!(
+                    "party {} sent conflicting {} msgs in round {}",
+                    traffic.from_party_uid, kind, round_count
+                );
+                report.set_fault(
+                    &mut faulters,
+                    from,
+                    FaultRecord::new(
+                        current_round,
+                        FaultKind::Equivocation,
+                        format!("sent conflicting {} msgs", kind),
+                    ),
+                )?;
+                continue;
+            }
+        }
+
         // log incoming message
-        if traffic.clone().is_broadcast {
+        if traffic.is_broadcast {
             bcast_msg_count += 1;
             debug!(
-                "{} got incoming bcast message {}/{}",round.info().party_id().to_string(),
-                bcast_msg_count, total_num_of_shares
+                "{} got incoming bcast message {}/{}",
+                round.info().party_id().to_string(),
+                bcast_msg_count,
+                total_num_of_shares
             );
         } else {
             p2p_msg_count += 1;
             debug!(
-                "{} got incoming p2p message {}/{}",round.info().party_id().to_string(),
-                p2p_msg_count, total_round_p2p_msgs
+                "{} got incoming p2p message {}/{}",
+                round.info().party_id().to_string(),
+                p2p_msg_count,
+                total_round_p2p_msgs
             );
         }
-   
-      //  debug!("traffic: {:?}", traffic.clone());
-        // get sender's party index 
-        let from = party_uids
-            .iter()
-            .position(|uid| uid == &traffic.clone().from_party_uid)
-            .ok_or_else(|| anyhow!("from uid does not exist in party uids"))?;
+
+        // remember broadcasts for the echo of this round
+        if traffic.is_broadcast {
+            echo.record(from, from_share_id, &traffic.payload, &traffic.signature);
+        }
+
+        // p2ps are encrypted for us by their sender
+        let payload = if traffic.is_broadcast {
+            traffic.payload
+        } else {
+            match traffic_auth.open(from, &traffic.round_num, &traffic.payload) {
+                Some(payload) => {
+                    // dealings back the complaints that we make against their sender
+                    let share_ids = ShareIds::of(&traffic);
+                    report.record_dealing(share_ids, &payload, || {
+                        Some(proto::EncryptedShare::new(
+                            traffic_auth.session_id(),
+                            &traffic.round_num,
+                            share_ids,
+                            &traffic.payload,
+                            &traffic.signature,
+                            traffic_auth.p2p_key_in(from)?,
+                        ))
+                    });
+                    report.record_p2p(
+                        &traffic.from_party_uid,
+                        traffic_auth.my_uid(),
+                        current_round,
+                        share_ids,
+                        &payload,
+                    );
+                    payload
+                }
+                None => {
+                    warn!(
+                        "unable to decrypt incoming p2p msg from {} in round {}",
+                        traffic.from_party_uid, round_count
+                    );
+                    report.set_fault(
+                        &mut faulters,
+                        from,
+                        FaultRecord::new(
+                            current_round,
+                            FaultKind::Corrupted,
+                            "undecryptable p2p msg",
+                        ),
+                    )?;
+                    continue;
+                }
+            }
+        };
 
         // try to set a message
-        if round_count == 3{
-            if round
-            .msg_inr4(TypedUsize::from_usize(from), &traffic.clone().payload)
-            .is_err()
-        {
-            return Err(anyhow!("error calling tofn::msg_in with [from: {}]", from));
+        let from_share = TypedUsize::from_usize(from_share_id);
+        let res = if round_count == 3 {
+            round.msg_inr4(from_share, &payload)
+        } else {
+            round.msg_in(from_share, &payload)
         };
+        if res.is_err() {
+            return Err(anyhow!(
+                "error calling tofn::msg_in with [from: {}, share: {}]",
+                from,
+                from_share_id
+            ));
         }
-        if round_count != 3{
-        if round
-            .msg_in(TypedUsize::from_usize(from), &traffic.payload)
-            .is_err()
+    }
+
+    // buffered messages that this round did not need can't be used by later rounds
+    let unused_msgs: Vec<_> = buffered_msgs.collect();
+    if !unused_msgs.is_empty() {
+        warn!(
+            "dropping {} buffered msgs of round {}: round is over",
+            unused_msgs.len(),
+            current_round
+        );
+    }
+
+    Ok((faulters, echo))
+}
+
+/// Broadcast the [Echo] of this round and receive the echoes of all parties until the round's deadline passes.
+/// A party echoes once, from its first share.
+/// Returns the parties that are proven to have sent different broadcasts to different parties,
+/// that echoed broadcasts that were not signed by their senders, or that did not echo.
+#[allow(clippy::too_many_arguments)]
+async fn handle_echo<P>(
+    chans: &mut ProtocolCommunication<
+        Option<proto::TrafficIn>,
+        Result<proto::MessageOut, tonic::Status>,
+    >,
+    echo: &Echo,
+    inbox: &mut Inbox,
+    current_round: usize,
+    party_uids: &[String],
+    party_share_counts: &[usize],
+    my_share_id: usize,
+    round_timeout: Duration,
+    traffic_auth: &TrafficAuth,
+    report: &mut ProtocolReport,
+    span: Span,
+) -> TofndResult<FillVecMap<P, Fault>> {
+    let echo_span = span!(parent: &span, Level::DEBUG, "echo", round = current_round);
+    let round_num = current_round.to_string();
+
+    // send our echo; all of our shares received the same bcasts
+    let my_first_share_id = vss::share_indices(party_share_counts)[traffic_auth.my_index()][0];
+    if my_share_id == my_first_share_id {
+        let payload = echo.to_bytes()?;
+        let share_ids = ShareIds::new(my_share_id, 0);
+        let signature = traffic_auth.sign(&round_num, TrafficKind::Echo, "", share_ids, &payload);
+        chans.sender.send(Ok(proto::MessageOut::new_echo(
+            &payload,
+            &round_num,
+            my_share_id,
+            signature,
+        )))?;
+    }
+
+    let mut faulters = FillVecMap::with_size(party_uids.len());
+    let mut echoed = vec![false; party_uids.len()];
+    let mut delivered_echoes = DeliveredMessages::new();
+    let mut timed_out = false;
+
+    let deadline = Instant::now() + round_timeout;
+    let mut buffered_echoes = inbox.take_echoes(current_round).into_iter();
+
+    // loop until all parties echoed
+    while echoed.contains(&false) {
+        let traffic = match buffered_echoes.next() {
+            Some(traffic) => traffic,
+            None => match timeout_at(deadline, chans.receiver.recv()).await {
+                Ok(Some(Some(traffic))) => traffic,
+                Ok(Some(None)) => continue,
+                Ok(None) => {
+                    error!("{}: internal channel closed prematurely", current_round);
+                    break;
+                }
+                Err(_) => {
+                    warn!(
+                        "echo of round {} timed out after {:?}",
+                        current_round, round_timeout
+                    );
+                    timed_out = true;
+                    break;
+                }
+            },
+        };
+        let _start = echo_span.enter();
+
+        // messages of future rounds are kept for later; all other messages are of no use now
+        let msg_round = match msg_round(&traffic) {
+            Some(msg_round) => msg_round,
+            None => {
+                warn!(
+                    "ignore incoming msg from {}: invalid round_num {:?}",
+                    traffic.from_party_uid, traffic.round_num
+                );
+                continue;
+            }
+        };
+        if msg_round > current_round {
+            inbox.buffer(msg_round, traffic, party_uids);
+            continue;
+        }
+        if msg_round < current_round || !traffic.is_echo {
+            debug!(
+                "ignore incoming msg from {} during echo of round {}",
+                traffic.from_party_uid, current_round
+            );
+            continue;
+        }
+
+        let from = match party_uids
+            .iter()
+            .position(|uid| uid == &traffic.from_party_uid)
         {
-            return Err(anyhow!("error calling tofn::msg_in with [from: {}]", from));
-        };}
-        continue_loop = round.expecting_more_msgs_this_round(); 
-        
-    
+            Some(from) => from,
+            None => {
+                warn!("ignore echo: unknown sender {}", traffic.from_party_uid);
+                continue;
+            }
+        };
 
-    }}
+        // echoes must be signed by their sender, and each party echoes once
+        let faults = if !traffic_auth.verify(from, &traffic) {
+            warn!("invalid signature of echo from {}", traffic.from_party_uid);
+            vec![(
+                from,
+                FaultKind::BadSignature,
+                "invalid signature of echo".to_string(),
+            )]
+        } else {
+            match delivered_echoes.check(from, true, &traffic.payload, 1) {
+                Delivery::Duplicate => continue,
+                Delivery::Equivocation => {
+                    warn!("party {} sent conflicting echoes", traffic.from_party_uid);
+                    vec![(
+                        from,
+                        FaultKind::Equivocation,
+                        "sent conflicting echoes".to_string(),
+                    )]
+                }
+                Delivery::New => match Echo::from_bytes(&traffic.payload) {
+                    Some(their_echo) => echo
+                        .check(from, &their_echo, &round_num, traffic_auth)
+                        .into_iter()
+                        .map(|(faulter, fault)| match fault {
+                            Fault::ProtocolFault => (
+                                faulter,
+                                FaultKind::Equivocation,
+                                format!("sent a different bcast to {}", traffic.from_party_uid),
+                            ),
+                            _ => (
+                                faulter,
+                                FaultKind::Corrupted,
+                                "echoed a bcast that was not signed by its sender".to_string(),
+                            ),
+                        })
+                        .collect(),
+                    None => vec![(from, FaultKind::Corrupted, "malformed echo".to_string())],
+                },
+            }
+        };
+        echoed[from] = true;
 
-    Ok(())
+        for (faulter, kind, reason) in faults {
+            warn!(
+                "echo of {} in round {} blames party {}: {}",
+                traffic.from_party_uid, current_round, party_uids[faulter], reason
+            );
+            report.set_fault(
+                &mut faulters,
+                faulter,
+                FaultRecord::new(current_round, kind, reason),
+            )?;
+        }
+    }
+
+    // without the echoes of all parties, we can't know that our view of this round is shared
+    for (i, _) in echoed.iter().enumerate().filter(|(_, echoed)| !**echoed) {
+        let record = match timed_out {
+            true => FaultRecord::new(
+                current_round,
+                FaultKind::Timeout,
+                "no echo before the deadline",
+            ),
+            false => FaultRecord::new(current_round, FaultKind::Missing, "no echo"),
+        };
+        report.set_fault(&mut faulters, i, record)?;
+    }
+
+    Ok(faulters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tofn::gg20::keygen::KeygenPartyId;
+
+    #[test]
+    fn test_delivered_messages() {
+        let mut delivered = DeliveredMessages::new();
+
+        // first message of each kind is new
+        assert_eq!(delivered.check(0, true, b"bcast", 1), Delivery::New);
+        assert_eq!(delivered.check(0, false, b"p2p", 1), Delivery::New);
+        assert_eq!(delivered.check(1, true, b"bcast", 1), Delivery::New);
+
+        // exact copies are duplicates
+        assert_eq!(delivered.check(0, true, b"bcast", 1), Delivery::Duplicate);
+        assert_eq!(delivered.check(0, false, b"p2p", 1), Delivery::Duplicate);
+
+        // a different message of the same kind is equivocation
+        assert_eq!(
+            delivered.check(0, true, b"other bcast", 1),
+            Delivery::Equivocation
+        );
+        assert_eq!(
+            delivered.check(0, false, b"other p2p", 1),
+            Delivery::Equivocation
+        );
+
+        // parties with multiple shares can deliver one message per share
+        assert_eq!(delivered.check(2, true, b"bcast 1", 2), Delivery::New);
+        assert_eq!(delivered.check(2, true, b"bcast 2", 2), Delivery::New);
+        assert_eq!(
+            delivered.check(2, true, b"bcast 3", 2),
+            Delivery::Equivocation
+        );
+    }
+
+    #[test]
+    fn test_inbox() {
+        let uids = vec!["a".to_owned(), "b".to_owned()];
+        let traffic = |from: &str, round_num: &str, is_echo: bool| proto::TrafficIn {
+            from_party_uid: from.to_owned(),
+            round_num: round_num.to_owned(),
+            is_echo,
+            ..Default::default()
+        };
+        let mut inbox = Inbox::new(3);
+
+        // messages of rounds after the last one and of unknown senders are dropped
+        inbox.buffer(4, traffic("a", "4", false), &uids);
+        inbox.buffer(2, traffic("c", "2", false), &uids);
+        assert!(inbox.msgs.is_empty());
+
+        // msgs and echoes are buffered separately
+        inbox.buffer(2, traffic("a", "2", false), &uids);
+        inbox.buffer(2, traffic("a", "2", true), &uids);
+        assert_eq!(inbox.take(2).len(), 1);
+        assert_eq!(inbox.take_echoes(2).len(), 1);
+        assert!(inbox.take(2).is_empty());
+
+        // each sender can only buffer a limited number of msgs; taken msgs don't count
+        for _ in 0..MAX_BUFFERED_MSGS_PER_SENDER {
+            inbox.buffer(3, traffic("a", "3", false), &uids);
+        }
+        inbox.buffer(2, traffic("a", "2", false), &uids);
+        inbox.buffer(2, traffic("b", "2", false), &uids);
+        assert_eq!(inbox.take(2).len(), 1);
+        assert_eq!(inbox.take(3).len(), MAX_BUFFERED_MSGS_PER_SENDER);
+        inbox.buffer(2, traffic("a", "2", false), &uids);
+        assert_eq!(inbox.take(2).len(), 1);
+
+        // missing and invalid round_nums are rejected
+        assert_eq!(msg_round(&traffic("a", "3", false)), Some(3));
+        assert_eq!(msg_round(&traffic("a", "", false)), None);
+        assert_eq!(msg_round(&traffic("a", "three", false)), None);
+    }
+
+    #[test]
+    fn test_complaints() {
+        let dispute = ShareInfoDispute {
+            share: Share {
+                scalar: bls12_381::Scalar::from(7),
+                index: 2,
+            },
+            kij: bls12_381::G1Projective::generator() * bls12_381::Scalar::from(3),
+            proof: ([1; 32], [2; 32]),
+        };
+        let payload = serde_json::to_vec(&P2pSad {
+            vss_complaint: vec![dispute.clone()],
+        })
+        .unwrap();
+
+        // complaints survive encoding
+        let decoded = P2pSad::parse(&payload).unwrap();
+        assert_eq!(decoded.vss_complaint[0].share, dispute.share);
+        assert_eq!(decoded.vss_complaint[0].kij, dispute.kij);
+        assert_eq!(decoded.vss_complaint[0].proof, dispute.proof);
+
+        // p2ps without complaints are not reported
+        let empty = serde_json::to_vec(&P2pSad {
+            vss_complaint: vec![],
+        })
+        .unwrap();
+        assert!(P2pSad::parse(&empty).is_none());
+        assert!(P2pSad::parse(b"not a complaint").is_none());
+
+        // complaints of all shares are merged
+        // complaints carry the dealing of the accused share to the accuser
+        let mut report = ProtocolReport::default();
+        let dealing = serde_json::to_vec(&P2pShare {
+            encrypted_u_i_share: dispute.share.clone(),
+        })
+        .unwrap();
+        let evidence = proto::EncryptedShare {
+            session_id: "session".to_string(),
+            from_share_id: 1,
+            ..Default::default()
+        };
+        report.record_dealing(ShareIds::new(1, 0), b"not a dealing", || {
+            Some(proto::EncryptedShare::default())
+        });
+        report.record_dealing(ShareIds::new(1, 0), &dealing, || Some(evidence.clone()));
+        report.record_dealing(ShareIds::new(1, 0), &dealing, || {
+            Some(proto::EncryptedShare::default())
+        });
+        report.record_p2p("alice", "bob", 2, ShareIds::new(0, 1), &payload);
+        report.record_p2p("alice", "carol", 2, ShareIds::new(0, 2), &empty);
+        assert_eq!(report.complaints.len(), 1);
+        assert_eq!(report.complaints[0].encrypted_share, Some(evidence));
+
+        let merged = ProtocolReport::merge(vec![report.clone(), report]);
+        assert_eq!(merged.complaints.len(), 1);
+        let complaint = &merged.complaints[0];
+        assert_eq!(complaint.accuser_uid, "alice");
+        assert_eq!(complaint.accused_uid, "bob");
+        assert_eq!(complaint.round, 2);
+        assert_eq!(complaint.share_index, 2);
+        assert_eq!(complaint.kij.len(), 48);
+        assert_eq!(complaint.proof.len(), 64);
+    }
+
+    #[test]
+    fn test_fault_records() {
+        let mut faulters = FillVecMap::<KeygenPartyId, Fault>::with_size(3);
+        let mut report = ProtocolReport::default();
+
+        // timeouts are only recorded; tofn decides whether they are faults
+        report.record_fault(0, FaultRecord::new(1, FaultKind::Timeout, "timeout"));
+        assert!(faulters.is_empty());
+
+        // the first fault of a party is kept
+        let bad_signature = FaultRecord::new(2, FaultKind::BadSignature, "invalid signature");
+        report
+            .set_fault(&mut faulters, 1, bad_signature.clone())
+            .unwrap();
+        report
+            .set_fault(
+                &mut faulters,
+                1,
+                FaultRecord::new(2, FaultKind::Equivocation, "equivocation"),
+            )
+            .unwrap();
+        assert_eq!(
+            faulters.get(TypedUsize::from_usize(1)).unwrap(),
+            Some(&Fault::CorruptedMessage)
+        );
+        assert_eq!(report.faults[&1], bad_signature);
+        assert!(report.set_fault(&mut faulters, 3, bad_signature).is_err());
+
+        // merged reports keep the earliest fault of each party
+        let mut later = ProtocolReport::default();
+        later.record_fault(0, FaultRecord::new(3, FaultKind::Missing, "missing"));
+        later.record_fault(2, FaultRecord::new(3, FaultKind::Protocol, "protocol"));
+        let merged = ProtocolReport::merge(vec![later, report]);
+        assert_eq!(merged.faults[&0].kind, FaultKind::Timeout);
+        assert_eq!(merged.faults[&1].kind, FaultKind::BadSignature);
+        assert_eq!(merged.faults[&2].kind, FaultKind::Protocol);
+    }
+
+    #[test]
+    fn test_party_faults() {
+        // 4 shares held by 2 parties; both shares of party 1 faulted
+        let mut share_faults = FillVecMap::<KeygenPartyId, Fault>::with_size(4);
+        share_faults
+            .set(TypedUsize::from_usize(2), Fault::MissingMessage)
+            .unwrap();
+        share_faults
+            .set(TypedUsize::from_usize(3), Fault::CorruptedMessage)
+            .unwrap();
+
+        let faults = party_faults(share_faults, &[2, 2]).unwrap();
+        assert_eq!(faults.get(TypedUsize::from_usize(0)).unwrap(), None);
+        assert_eq!(
+            faults.get(TypedUsize::from_usize(1)).unwrap(),
+            Some(&Fault::MissingMessage)
+        );
+
+        // faults of shares that no party holds are errors
+        let mut share_faults = FillVecMap::<KeygenPartyId, Fault>::with_size(5);
+        share_faults
+            .set(TypedUsize::from_usize(4), Fault::MissingMessage)
+            .unwrap();
+        assert!(party_faults(share_faults, &[2, 2]).is_err());
+    }
 }
@@ -0,0 +1,107 @@
+//! This module handles the get_public_info gRPC.
+//! Request includes a `key_uid`. The response contains the public data of the key stored in [PartyInfo],
+//! which is needed to verify partial outputs of any party without running another tofnd.
+
+use super::{bls, delete_key::check_key_uid, proto, service::Gg20Service, types::PartyInfo};
+use bls12_381::G1Affine;
+
+// logging
+use tracing::info;
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::convert::{TryFrom, TryInto};
+
+impl Gg20Service {
+    pub(super) async fn handle_get_public_info(
+        &self,
+        request: proto::PublicInfoRequest,
+    ) -> TofndResult<proto::PublicInfo> {
+        // get party info from kv store
+        check_key_uid(&request.key_uid)?;
+        let party_info: PartyInfo = self
+            .kv_manager
+            .kv()
+            .get(&request.key_uid)
+            .await
+            .map_err(|err| anyhow!("could not find key {}: {}", request.key_uid, err))?
+            .try_into()?;
+
+        info!("Getting public info of key {}", request.key_uid);
+
+        let commitments = party_info.common.vss_commitments();
+        let total_share_count: usize = party_info.tofnd.share_counts.iter().sum();
+
+        let share_pub_keys = (0..total_share_count)
+            .map(|share_index| {
+                G1Affine::from(bls::share_public_key(commitments, share_index))
+                    .to_compressed()
+                    .to_vec()
+            })
+            .collect();
+
+        let party_share_counts = party_info
+            .tofnd
+            .share_counts
+            .iter()
+            .map(|&count| u32::try_from(count))
+            .collect::<Result<_, _>>()?;
+
+        Ok(proto::PublicInfo {
+            pub_key: party_info.common.encoded_pubkey(),
+            vss_commitments: commitments
+                .iter()
+                .map(|commitment| G1Affine::from(commitment).to_compressed().to_vec())
+                .collect(),
+            share_pub_keys,
+            threshold: u32::try_from(party_info.common.threshold())?,
+            party_uids: party_info.tofnd.party_uids,
+            party_share_counts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::test_utils::{store_key, test_service};
+    use bls12_381::{G1Projective, Scalar};
+    use testdir::testdir;
+    use tracing_test::traced_test;
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_get_public_info() {
+        let service = test_service(testdir!()).await;
+        let secret = Scalar::from(7);
+        let poly = store_key(&service, "key", secret).await;
+        let request = |key_uid: &str| proto::PublicInfoRequest {
+            key_uid: key_uid.to_owned(),
+        };
+        let compressed = |point: G1Projective| G1Affine::from(point).to_compressed().to_vec();
+
+        let info = service
+            .handle_get_public_info(request("key"))
+            .await
+            .unwrap();
+        assert_eq!(info.pub_key, compressed(G1Projective::generator() * secret));
+        let commitments: Vec<_> = poly.commitments().into_iter().map(compressed).collect();
+        assert_eq!(info.vss_commitments, commitments);
+        assert_eq!(info.threshold, 1);
+        assert_eq!(info.party_uids, vec!["alice", "bob"]);
+        assert_eq!(info.party_share_counts, vec![2, 2]);
+
+        // public keys `x_i * g1` of the shares of all parties
+        let share_pub_keys: Vec<_> = (0..4)
+            .map(|index| compressed(G1Projective::generator() * poly.eval(index)))
+            .collect();
+        assert_eq!(info.share_pub_keys, share_pub_keys);
+
+        assert!(service
+            .handle_get_public_info(request("unknown"))
+            .await
+            .is_err());
+    }
+}
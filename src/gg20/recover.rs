@@ -1,15 +1,30 @@
 //! This module handles the recover gRPC.
 //! Request includes [proto::message_in::Data::KeygenInit] struct and encrypted recovery info.
-//! The recovery info is decrypted by party's mnemonic seed and saved in the KvStore.
+//! The recovery info is decrypted by the mnemonic seed that generated the key, as recorded in [proto::KeygenOutput], and saved in the KvStore.
+//! Keys of a batch keygen are recovered one at a time; the request selects the key by its index in the batch.
+//!
+//! Refresh replaces the shares that keygen's recovery info restores, so it returns recovery info of its own, see [proto::RefreshOutput]:
+//! the refreshed shares of the party, encrypted with a key derived from the mnemonic seed and bound to the key uid.
+//! Encrypted recovery info is <ciphertext, nonce>. Recovered shares must match the VSS commitments of the key, as of its latest refresh.
 
-use super::{keygen::types::KeygenInitSanitized, proto, service::Gg20Service, types::PartyInfo};
+use super::{
+    keygen::types::KeygenInitSanitized,
+    proto,
+    protocol::Share,
+    service::Gg20Service,
+    types::{GroupInfo, PartyInfo, ShareSecret},
+    vss,
+};
+use chacha20poly1305::aead::{AeadInPlace, NewAead};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
 use tofn::{
     collections::TypedUsize,
     gg20::keygen::{
-     recover_party_keypair_unsafe, KeygenPartyId, SecretKeyShare,
+        recover_party_keypair, recover_party_keypair_unsafe, KeygenPartyId, SecretKeyShare,
         SecretRecoveryKey,
     },
-    sdk::api::{deserialize, BytesVec, PartyShareCounts},
+    sdk::api::{deserialize, serialize, BytesVec, PartyShareCounts},
 };
 
 // logging
@@ -21,28 +36,244 @@ use anyhow::anyhow;
 
 use std::convert::TryInto;
 
+/// domain of the key that encrypts the recovery info of refreshed shares
+const REFRESH_RECOVERY_DOMAIN: &[u8] = b"tofnd refresh recovery key";
+
+/// size of the nonce that is appended to encrypted recovery info
+const NONCE_SIZE: usize = 24;
+
 impl Gg20Service {
+    pub(super) async fn handle_recover(&self, request: proto::RecoverRequest) -> TofndResult<()> {
+        // get keygen init sanitized from request
+        let keygen_init = {
+            let keygen_init = request
+                .keygen_init
+                .ok_or_else(|| anyhow!("missing keygen_init field in recovery request"))?;
+            Self::keygen_sanitize_args(keygen_init)?
+        };
+
+        let keygen_output = request
+            .keygen_output
+            .ok_or_else(|| anyhow!("missing keygen_output field in recovery request"))?;
+
+        // get the uid of the recovered key
+        let key_index = request.key_index;
+        let key_uid = keygen_init
+            .key_uids()
+            .get(key_index as usize)
+            .ok_or_else(|| {
+                anyhow!(
+                    "key index {} is out of bounds of {} keys",
+                    key_index,
+                    keygen_init.key_uids().len()
+                )
+            })?
+            .clone();
+
+        // check if key-uid already exists in kv-store. If yes, return success and don't update the kv-store
+        if self
+            .kv_manager
+            .kv()
+            .exists(&key_uid)
+            .await
+            .map_err(|err| anyhow!(err))?
+        {
+            warn!(
+                "Request to recover shares for [key {}, party {}] but shares already exist in kv-store. Abort request.",
+                key_uid, keygen_init.party_uids[keygen_init.my_index]
+            );
+            return Ok(());
+        }
+
+        // recover secret key shares from request
+        // get the mnemonic seed that generated the key; outputs that don't record it were generated by the latest one
+        let (secret_recovery_key, seed_fingerprint) =
+            match keygen_output.seed_fingerprint.is_empty() {
+                true => (
+                    self.kv_manager.seed().await?,
+                    self.kv_manager.seed_fingerprint().await?,
+                ),
+                false => {
+                    let seed_key = self
+                        .kv_manager
+                        .find_seed_key(&keygen_output.seed_fingerprint)
+                        .await?
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "the mnemonic that generated key {} is not in the kv store",
+                                key_uid
+                            )
+                        })?;
+                    info!("Recovering key {} with mnemonic '{}'", key_uid, seed_key);
+                    (
+                        self.kv_manager.get_seed(&seed_key).await?,
+                        keygen_output.seed_fingerprint.clone(),
+                    )
+                }
+            };
+        let secret_key_shares = self
+            .recover_secret_key_shares(&secret_recovery_key, &keygen_init, &keygen_output)
+            .map_err(|err| anyhow!("Failed to acquire secret key share {}", err))?;
+        let mut party_info = PartyInfo::get_party_info(
+            secret_key_shares,
+            keygen_init.party_uids,
+            keygen_init.party_share_counts,
+            keygen_init.my_index,
+            seed_fingerprint,
+        );
+
+        // refreshed keys are restored from the recovery info of their latest refresh
+        if let Some(refresh_output) = request.refresh_output {
+            self.recover_refreshed_shares(&key_uid, &mut party_info, &refresh_output)
+                .await?;
+        }
+
+        // recovery info of an earlier refresh restores shares that the current commitments reject
+        verify_recovered_shares(&party_info)?;
+
+        self.update_share_kv_store(key_uid, party_info).await
+    }
+
+    /// Encrypt the refreshed shares of key `key_uid` as recovery info, with the latest mnemonic.
+    /// Returns the recovery info and the fingerprint of the mnemonic.
+    pub(super) async fn refresh_recovery_info(
+        &self,
+        key_uid: &str,
+        shares: &[ShareSecret],
+    ) -> TofndResult<(Vec<u8>, Vec<u8>)> {
+        let key_material = self.kv_manager.derive_key(REFRESH_RECOVERY_DOMAIN).await?;
+        let seed_fingerprint = self.kv_manager.seed_fingerprint().await?;
+        Ok((
+            seal_shares(&key_material, key_uid, shares)?,
+            seed_fingerprint,
+        ))
+    }
 
-    /// attempt to write recovered secret key shares to the kv-store
-    async fn update_share_kv_store(
+    /// replace the group info and the shares of `party_info` with the ones of the refresh that returned `output`
+    async fn recover_refreshed_shares(
         &self,
-        keygen_init_sanitized: KeygenInitSanitized,
-        secret_key_shares: Vec<SecretKeyShare>,
+        key_uid: &str,
+        party_info: &mut PartyInfo,
+        output: &proto::RefreshOutput,
     ) -> TofndResult<()> {
+        if output.pub_key != party_info.common.encoded_pubkey() {
+            return Err(anyhow!(
+                "refresh output does not match the public key of key {}",
+                key_uid
+            ));
+        }
+        let vss_commitments = vss::decode_points(&output.vss_commitments)
+            .ok_or_else(|| anyhow!("invalid vss commitments in refresh output"))?;
+
+        let key_material = self
+            .kv_manager
+            .derive_key_of(&output.seed_fingerprint, REFRESH_RECOVERY_DOMAIN)
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "the mnemonic that refreshed key {} is not in the kv store",
+                    key_uid
+                )
+            })?;
+        let shares = open_shares(&key_material, key_uid, &output.private_recover_info)?;
+
+        info!("Recovering the refreshed shares of key {}", key_uid);
+        party_info.common = GroupInfo::new(
+            party_info.common.threshold(),
+            party_info.common.encoded_pubkey(),
+            vss_commitments,
+        );
+        party_info.shares = shares;
+        Ok(())
+    }
+
+    /// get recovered secret key shares from serilized share recovery info
+    fn recover_secret_key_shares(
+        &self,
+        secret_recovery_key: &SecretRecoveryKey,
+        init: &KeygenInitSanitized,
+        output: &proto::KeygenOutput,
+    ) -> TofndResult<Vec<SecretKeyShare>> {
+        // get my share count safely
+        let my_share_count = *init.party_share_counts.get(init.my_index).ok_or_else(|| {
+            anyhow!(
+                "index {} is out of party_share_counts bounds {}",
+                init.my_index,
+                init.party_share_counts.len()
+            )
+        })?;
+        if my_share_count == 0 {
+            return Err(anyhow!("Party {} has 0 shares assigned", init.my_index));
+        }
+
+        let party_share_counts = PartyShareCounts::from_vec(init.party_share_counts.clone())
+            .map_err(|_| anyhow!("failed to create party_share_counts"))?;
+
+        // keygen sends one recovery info per share, serialized as a single vector
+        let recovery_infos = deserialize::<Vec<BytesVec>>(&output.private_recover_info)
+            .ok_or_else(|| anyhow!("Failed to deserialize private recovery infos"))?;
+        if recovery_infos.len() != my_share_count {
+            return Err(anyhow!(
+                "Party {} has {} shares but {} recovery infos were provided",
+                init.my_index,
+                my_share_count,
+                recovery_infos.len()
+            ));
+        }
+
+        let party_id = TypedUsize::<KeygenPartyId>::from_usize(init.my_index);
+
+        info!("Recovering keypair for party {} ...", party_id);
+
+        // the keypair must be derived like keygen did; it is derived once per keygen session, so all keys of a batch share it
+        let session_nonce = init.new_key_uid.as_bytes();
+        let party_keypair = match self.cfg.safe_keygen {
+            true => recover_party_keypair(party_id, secret_recovery_key, session_nonce),
+            false => recover_party_keypair_unsafe(party_id, secret_recovery_key, session_nonce),
+        }
+        .map_err(|_| anyhow!("party keypair recovery failed"))?;
+
+        info!("Finished recovering keypair for party {}", party_id);
+
+        // gather secret key shares from recovery infos
+        let secret_key_shares = recovery_infos
+            .iter()
+            .enumerate()
+            .map(|(i, recovery_info_bytes)| {
+                SecretKeyShare::recover(
+                    &party_keypair,
+                    recovery_info_bytes,
+                    party_id,
+                    i,
+                    party_share_counts.clone(),
+                    init.threshold,
+                )
+                .map_err(|_| anyhow!("Failed to recover secret key share {}", i))
+            })
+            .collect::<TofndResult<Vec<_>>>()?;
+
+        // sanity check: recovered shares must correspond to the public key that keygen returned
+        for secret_key_share in &secret_key_shares {
+            if secret_key_share.group().encoded_pubkey() != output.pub_key {
+                return Err(anyhow!(
+                    "Recovered share {} does not match the provided public key",
+                    secret_key_share.share().index()
+                ));
+            }
+        }
+
+        Ok(secret_key_shares)
+    }
+
+    /// attempt to write recovered secret key shares of key `key_uid` to the kv-store
+    async fn update_share_kv_store(&self, key_uid: String, kv_data: PartyInfo) -> TofndResult<()> {
         // try to make a reservation
         let reservation = self
             .kv_manager
             .kv()
-            .reserve_key(keygen_init_sanitized.new_key_uid)
+            .reserve_key(key_uid)
             .await
             .map_err(|err| anyhow!("failed to complete reservation: {}", err))?;
-        // acquire kv-data
-        let kv_data = PartyInfo::get_party_info(
-            secret_key_shares,
-            keygen_init_sanitized.party_uids,
-            keygen_init_sanitized.party_share_counts,
-            keygen_init_sanitized.my_index,
-        );
         // try writing the data to the kv-store
         self.kv_manager
             .kv()
@@ -51,3 +282,217 @@ impl Gg20Service {
             .map_err(|err| anyhow!("failed to update kv store: {}", err))
     }
 }
+
+/// check that the recovered shares are the shares of the party, and that they match the VSS commitments of the key
+fn verify_recovered_shares(party_info: &PartyInfo) -> TofndResult<()> {
+    let expected_indices = vss::share_indices(&party_info.tofnd.share_counts)
+        .get(party_info.tofnd.index)
+        .cloned()
+        .unwrap_or_default();
+    let indices: Vec<usize> = party_info
+        .shares
+        .iter()
+        .map(|share| share.index())
+        .collect();
+    if indices != expected_indices {
+        return Err(anyhow!(
+            "recovered shares {:?} are not the shares {:?} of party {}",
+            indices,
+            expected_indices,
+            party_info.tofnd.index
+        ));
+    }
+
+    let commitments = party_info.common.vss_commitments();
+    for share in &party_info.shares {
+        let share = Share {
+            scalar: *share.x_i(),
+            index: share.index(),
+        };
+        if !vss::verify_share(commitments, &share) {
+            return Err(anyhow!(
+                "Recovered share {} does not match the vss commitments of the key",
+                share.index
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// cipher of the recovery info of refreshed shares
+fn recovery_cipher(key_material: &[u8; 64]) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(Key::from_slice(&key_material[..32]))
+}
+
+/// encrypt the refreshed `shares` of key `key_uid`
+fn seal_shares(
+    key_material: &[u8; 64],
+    key_uid: &str,
+    shares: &[ShareSecret],
+) -> TofndResult<Vec<u8>> {
+    let mut nonce = XNonce::default();
+    rand::thread_rng().fill_bytes(nonce.as_mut_slice());
+
+    let mut ciphertext =
+        serialize(&shares.to_vec()).map_err(|_| anyhow!("could not serialize shares"))?;
+    recovery_cipher(key_material)
+        .encrypt_in_place(&nonce, key_uid.as_bytes(), &mut ciphertext)
+        .map_err(|err| anyhow!("could not encrypt recovery info: {}", err))?;
+
+    ciphertext.extend_from_slice(&nonce);
+    Ok(ciphertext)
+}
+
+/// decrypt the refreshed shares of key `key_uid`
+fn open_shares(
+    key_material: &[u8; 64],
+    key_uid: &str,
+    info: &[u8],
+) -> TofndResult<Vec<ShareSecret>> {
+    if info.len() < NONCE_SIZE {
+        return Err(anyhow!("recovery info of refresh is too short"));
+    }
+
+    let (ciphertext, nonce) = info.split_at(info.len() - NONCE_SIZE);
+    let mut plaintext = ciphertext.to_vec();
+    recovery_cipher(key_material)
+        .decrypt_in_place(
+            XNonce::from_slice(nonce),
+            key_uid.as_bytes(),
+            &mut plaintext,
+        )
+        .map_err(|_| {
+            anyhow!(
+                "could not decrypt recovery info of refresh of key {}",
+                key_uid
+            )
+        })?;
+
+    deserialize(&plaintext).ok_or_else(|| anyhow!("failed to deserialize refreshed shares"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::{
+        test_utils::{store_key, test_service},
+        types::TofndInfo,
+    };
+    use bls12_381::Scalar;
+    use testdir::testdir;
+    use tracing_test::traced_test;
+
+    /// a recovery request of key `key_uid` of parties "alice" and "bob", as alice
+    fn recover_request(key_uid: &str, seed_fingerprint: Vec<u8>) -> proto::RecoverRequest {
+        proto::RecoverRequest {
+            keygen_init: Some(proto::KeygenInit {
+                new_key_uid: key_uid.to_owned(),
+                party_uids: vec!["alice".to_owned(), "bob".to_owned()],
+                party_share_counts: vec![2, 2],
+                my_party_index: 0,
+                threshold: 1,
+                round_timeout_ms: 0,
+                party_identity_keys: vec![],
+                party_addresses: vec![],
+                echo_broadcasts: false,
+                new_key_uids: vec![],
+            }),
+            keygen_output: Some(proto::KeygenOutput {
+                pub_key: vec![],
+                private_recover_info: vec![],
+                seed_fingerprint,
+            }),
+            key_index: 0,
+            refresh_output: None,
+        }
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_recover() {
+        let service = test_service(testdir!()).await;
+        let kv = service.kv_manager.kv();
+
+        // stored keys are left as they are
+        let poly = store_key(&service, "key", Scalar::from(7)).await;
+        service
+            .handle_recover(recover_request("key", vec![]))
+            .await
+            .unwrap();
+        let party_info: PartyInfo = kv.get("key").await.unwrap().try_into().unwrap();
+        assert_eq!(party_info.common.vss_commitments(), &poly.commitments()[..]);
+
+        // keys can only be recovered with the mnemonic that generated them
+        assert!(service
+            .handle_recover(recover_request("new key", vec![1; 32]))
+            .await
+            .is_err());
+        assert!(!kv.exists("new key").await.unwrap());
+
+        // the recovered key must be in the request
+        let mut request = recover_request("new key", vec![]);
+        request.key_index = 1;
+        assert!(service.handle_recover(request).await.is_err());
+        let mut request = recover_request("new key", vec![]);
+        request.keygen_output = None;
+        assert!(service.handle_recover(request).await.is_err());
+    }
+
+    #[test]
+    fn test_refresh_recovery_info() {
+        let key_material = [7; 64];
+        let shares = vec![
+            ShareSecret::new(1, Scalar::from(3)),
+            ShareSecret::new(2, Scalar::from(5)),
+        ];
+
+        let info = seal_shares(&key_material, "key", &shares).unwrap();
+        let opened = open_shares(&key_material, "key", &info).unwrap();
+        assert_eq!(opened.len(), 2);
+        assert_eq!(opened[1].index(), 2);
+        assert_eq!(opened[1].x_i(), &Scalar::from(5));
+
+        // recovery info is bound to its key and its mnemonic
+        assert!(open_shares(&key_material, "other key", &info).is_err());
+        assert!(open_shares(&[8; 64], "key", &info).is_err());
+        assert!(open_shares(&key_material, "key", &info[..NONCE_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn test_verify_recovered_shares() {
+        // party 1 of share counts [1, 2] holds shares 1 and 2
+        let poly = vss::Polynomial::random(Scalar::from(7), 1);
+        let refreshed = vss::Polynomial::random(Scalar::zero(), 1);
+        let party_info = |shares: Vec<ShareSecret>, commitments| PartyInfo {
+            common: GroupInfo::new(1, vec![], commitments),
+            shares,
+            tofnd: TofndInfo::new(
+                vec!["alice".to_owned(), "bob".to_owned()],
+                vec![1, 2],
+                1,
+                vec![],
+            ),
+        };
+        let shares = |poly: &vss::Polynomial| {
+            vec![
+                ShareSecret::new(1, poly.eval(1)),
+                ShareSecret::new(2, poly.eval(2)),
+            ]
+        };
+
+        assert!(verify_recovered_shares(&party_info(shares(&poly), poly.commitments())).is_ok());
+
+        // shares from before a refresh don't match the refreshed commitments
+        let commitments: Vec<_> = poly
+            .commitments()
+            .iter()
+            .zip(refreshed.commitments())
+            .map(|(a, b)| a + b)
+            .collect();
+        assert!(verify_recovered_shares(&party_info(shares(&poly), commitments)).is_err());
+
+        // shares of another party
+        let other = vec![ShareSecret::new(0, poly.eval(0))];
+        assert!(verify_recovered_shares(&party_info(other, poly.commitments())).is_err());
+    }
+}
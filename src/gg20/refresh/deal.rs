@@ -0,0 +1,216 @@
+//! Zero-secret deals of a refresh.
+//! Each party deals the evaluations of a random polynomial of degree `threshold` whose constant term is zero; see [crate::gg20::vss].
+//! Adding the evaluations of all dealers to the shares re-randomizes the shares without changing the group secret,
+//! and adding the commitments of all dealers to the VSS commitments of the key keeps them consistent with the new shares.
+
+use crate::gg20::{
+    protocol::Share,
+    vss::{self, Polynomial},
+};
+use bls12_381::{G1Projective, Scalar};
+use proto::message_out::criminal_list::criminal::FaultKind;
+use serde::{Deserialize, Serialize};
+
+use crate::proto;
+
+// error handling
+use crate::TofndResult;
+
+/// The bcast of a dealer
+#[derive(Serialize, Deserialize)]
+pub(super) struct DealBcast {
+    commitments: Vec<Vec<u8>>, // compressed G1 points `a_k * g1`
+}
+
+/// The p2p of a dealer to a party; one evaluation per share of the party
+#[derive(Serialize, Deserialize)]
+pub(super) struct DealP2p {
+    sub_shares: Vec<Share>,
+}
+
+impl DealBcast {
+    pub(super) fn new(poly: &Polynomial) -> Self {
+        Self {
+            commitments: vss::encode_points(&poly.commitments()),
+        }
+    }
+
+    pub(super) fn to_bytes(&self) -> TofndResult<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+impl DealP2p {
+    pub(super) fn new(poly: &Polynomial, share_indices: &[usize]) -> Self {
+        Self {
+            sub_shares: poly.deal(share_indices),
+        }
+    }
+
+    pub(super) fn to_bytes(&self) -> TofndResult<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+/// The verified deal of a party
+pub(super) struct Deal {
+    pub(super) commitments: Vec<G1Projective>,
+    pub(super) sub_shares: Vec<Scalar>, // aligned with our shares
+}
+
+/// Parse and verify the deal of a dealer for the shares `share_indices`.
+/// Returns the kind and reason of the dealer's fault if the deal is malformed, if it does not deal a zero secret,
+/// or if a sub-share does not match the dealer's commitments.
+pub(super) fn verify_deal(
+    bcast: &[u8],
+    p2p: &[u8],
+    threshold: usize,
+    share_indices: &[usize],
+) -> Result<Deal, (FaultKind, &'static str)> {
+    let bcast: DealBcast = serde_json::from_slice(bcast)
+        .map_err(|_| (FaultKind::Corrupted, "malformed deal bcast"))?;
+    let p2p: DealP2p =
+        serde_json::from_slice(p2p).map_err(|_| (FaultKind::Corrupted, "malformed deal p2p"))?;
+
+    let commitments = vss::decode_points(&bcast.commitments)
+        .ok_or((FaultKind::Corrupted, "invalid deal commitment"))?;
+    if commitments.len() != threshold + 1 {
+        return Err((FaultKind::Protocol, "wrong number of deal commitments"));
+    }
+    if !bool::from(commitments[0].is_identity()) {
+        return Err((FaultKind::Protocol, "dealt a non-zero secret"));
+    }
+
+    let dealt_indices: Vec<usize> = p2p.sub_shares.iter().map(|share| share.index).collect();
+    if dealt_indices != share_indices {
+        return Err((FaultKind::Protocol, "sub-shares for the wrong shares"));
+    }
+    if !p2p
+        .sub_shares
+        .iter()
+        .all(|share| vss::verify_share(&commitments, share))
+    {
+        return Err((
+            FaultKind::Protocol,
+            "sub-share does not match deal commitments",
+        ));
+    }
+
+    Ok(Deal {
+        commitments,
+        sub_shares: p2p.sub_shares.iter().map(|share| share.scalar).collect(),
+    })
+}
+
+/// Add the commitments of all deals to the VSS commitments `[a_0 * g1, ..., a_t * g1]` of a key.
+/// The commitment of the secret `a_0 * g1` does not change because deals have zero secrets.
+pub(super) fn refresh_commitments(
+    commitments: &[G1Projective],
+    deals: &[Vec<G1Projective>],
+) -> Vec<G1Projective> {
+    let mut commitments = commitments.to_vec();
+    for deal in deals {
+        for (commitment, dealt) in commitments.iter_mut().zip(deal) {
+            *commitment += dealt;
+        }
+    }
+    commitments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::bls;
+
+    #[test]
+    fn test_zero_secret_deals() {
+        // a key with threshold 1 and 3 shares; f(x) = 5 + 2x
+        let threshold = 1;
+        let key = Polynomial::random(Scalar::from(5), threshold);
+        let commitments = key.commitments();
+        let shares: Vec<_> = (0..3).map(|index| key.eval(index)).collect();
+
+        // every share is dealt a sub-share by 3 dealers
+        let polys: Vec<_> = (0..3)
+            .map(|_| Polynomial::random(Scalar::zero(), threshold))
+            .collect();
+        let deals: Vec<_> = polys
+            .iter()
+            .map(|poly| {
+                let bcast = DealBcast::new(poly).to_bytes().unwrap();
+                let p2p = DealP2p::new(poly, &[0, 1, 2]).to_bytes().unwrap();
+                verify_deal(&bcast, &p2p, threshold, &[0, 1, 2]).unwrap()
+            })
+            .collect();
+
+        let new_shares: Vec<_> = shares
+            .iter()
+            .enumerate()
+            .map(|(i, share)| {
+                deals
+                    .iter()
+                    .fold(*share, |acc, deal| acc + deal.sub_shares[i])
+            })
+            .collect();
+        let new_commitments = refresh_commitments(
+            &commitments,
+            &deals
+                .iter()
+                .map(|deal| deal.commitments.clone())
+                .collect::<Vec<_>>(),
+        );
+
+        // shares change, but the secret and its commitment don't
+        assert_ne!(new_shares, shares);
+        assert_eq!(new_commitments[0], commitments[0]);
+        for (index, share) in new_shares.iter().enumerate() {
+            assert_eq!(
+                G1Projective::generator() * share,
+                bls::share_public_key(&new_commitments, index)
+            );
+        }
+        assert_ne!(
+            vss::commitments_digest(&new_commitments),
+            vss::commitments_digest(&commitments)
+        );
+
+        // bad deals are rejected
+        let poly = &polys[0];
+        let bcast = DealBcast::new(poly).to_bytes().unwrap();
+        let p2p = DealP2p::new(poly, &[0, 1, 2]).to_bytes().unwrap();
+        let mut bad_p2p = DealP2p::new(poly, &[0, 1, 2]);
+        bad_p2p.sub_shares[1].scalar += Scalar::one();
+        let bad_p2p = bad_p2p.to_bytes().unwrap();
+
+        let fault = |bcast: &[u8], p2p: &[u8], indices: &[usize]| {
+            verify_deal(bcast, p2p, threshold, indices)
+                .err()
+                .map(|(kind, _)| kind)
+        };
+        assert_eq!(
+            fault(&bcast, &bad_p2p, &[0, 1, 2]),
+            Some(FaultKind::Protocol)
+        );
+        assert_eq!(fault(&bcast, &p2p, &[0, 1]), Some(FaultKind::Protocol));
+        assert_eq!(
+            fault(b"garbage", &p2p, &[0, 1, 2]),
+            Some(FaultKind::Corrupted)
+        );
+        let other_threshold = DealBcast::new(&Polynomial::random(Scalar::zero(), 2))
+            .to_bytes()
+            .unwrap();
+        assert_eq!(
+            fault(&other_threshold, &p2p, &[0, 1, 2]),
+            Some(FaultKind::Protocol)
+        );
+        let secret = Polynomial::random(Scalar::one(), threshold);
+        assert_eq!(
+            fault(
+                &DealBcast::new(&secret).to_bytes().unwrap(),
+                &DealP2p::new(&secret, &[0, 1, 2]).to_bytes().unwrap(),
+                &[0, 1, 2]
+            ),
+            Some(FaultKind::Protocol)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! This module handles the initialization of the Refresh protocol.
+//! A [RefreshInitSanitized] struct is created out of the raw incoming [proto::RefreshInit] message,
+//! and the [PartyInfo] of the refreshed key is loaded from the KvStore.
+//! The parties of a refresh are the parties of the key, in the order in which they are stored.
+
+// tonic cruft
+use futures_util::StreamExt;
+
+// logging
+use tracing::{info, span, Level, Span};
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use super::{archive_key, proto, Gg20Service};
+use crate::gg20::{
+    delete_key::check_key_uid,
+    identity::TrafficAuth,
+    types::{align_with_uids, PartyInfo},
+};
+use std::{convert::TryInto, time::Duration};
+
+/// prefix of the session ids of refreshes, so that they don't collide with the session ids of keygens
+const REFRESH_SESSION_PREFIX: &str = "refresh:";
+
+/// RefreshInitSanitized holds all arguments needed by Refresh in the desired form; populated by proto::RefreshInit
+pub(super) struct RefreshInitSanitized {
+    pub(super) key_uid: String,                   // the refreshed key
+    pub(super) session_id: String,                // session's UID
+    pub(super) party_identity_keys: Vec<Vec<u8>>, // identity keys of parties; alligned with the stored party uids
+    pub(super) party_addresses: Vec<String>, // peer addresses of parties; alligned with the stored party uids, or empty
+    pub(super) round_timeout: Option<Duration>, // deadline of each round; `None` for tofnd's default
+}
+
+impl Gg20Service {
+    /// Receives a message from the stream and tries to handle refresh init operations.
+    /// On success, returns a sanitized struct ready to be used by the protocol, the stored [PartyInfo] of the key,
+    /// and the [TrafficAuth] that authenticates the traffic of the session.
+    pub(super) async fn handle_refresh_init(
+        &self,
+        stream: &mut tonic::Streaming<proto::MessageIn>,
+        refresh_span: Span,
+    ) -> TofndResult<(RefreshInitSanitized, PartyInfo, TrafficAuth)> {
+        // try to receive message
+        let msg = stream
+            .next()
+            .await
+            .ok_or_else(|| anyhow!("stream closed by client"))?
+            .map_err(|e| anyhow!("stream closed by server: {}", e))?;
+
+        // check if message is of expected type
+        let refresh_init = match msg.data {
+            Some(proto::message_in::Data::RefreshInit(r)) => r,
+            msg_data => {
+                return Err(anyhow!(
+                    "wrong message type; expecting RefreshInit, got {:?}",
+                    msg_data
+                ))
+            }
+        };
+
+        // get party's shares from kv store
+        let party_info: PartyInfo = self
+            .kv_manager
+            .kv()
+            .get(&refresh_init.key_uid)
+            .await
+            .map_err(|err| anyhow!("could not find key {}: {}", refresh_init.key_uid, err))?
+            .try_into()?;
+
+        let refresh_init = refresh_sanitize_args(refresh_init, &party_info.tofnd.party_uids)
+            .map_err(|err| anyhow!("failed to sanitize RefreshInit: {}", err))?;
+
+        // an earlier refresh must be finalized first
+        if self
+            .kv_manager
+            .kv()
+            .exists(&archive_key(&refresh_init.key_uid))
+            .await?
+        {
+            return Err(anyhow!(
+                "an earlier refresh of key {} is not finalized",
+                refresh_init.key_uid
+            ));
+        }
+
+        let traffic_auth = TrafficAuth::new(
+            &refresh_init.session_id,
+            &party_info.tofnd.party_uids,
+            party_info.tofnd.index,
+            self.identity_key().await?,
+            &refresh_init.party_identity_keys,
+        )
+        .map_err(|err| anyhow!("failed to authenticate parties: {}", err))?;
+
+        let init_span = span!(parent: &refresh_span, Level::INFO, "init");
+        let _enter = init_span.enter();
+        info!(
+            "[uid:{}, shares:{}] starting Refresh with [key: {}, session: {}, participants:{:?}",
+            party_info.tofnd.party_uids[party_info.tofnd.index],
+            party_info.shares.len(),
+            refresh_init.key_uid,
+            refresh_init.session_id,
+            party_info.tofnd.party_uids,
+        );
+
+        Ok((refresh_init, party_info, traffic_auth))
+    }
+}
+
+/// Sanitize arguments of incoming message.
+/// The parties of the message must be the `stored_uids` of the key, in any order;
+/// identity keys and addresses are alligned with `stored_uids`.
+fn refresh_sanitize_args(
+    args: proto::RefreshInit,
+    stored_uids: &[String],
+) -> TofndResult<RefreshInitSanitized> {
+    check_key_uid(&args.key_uid)?;
+    if args.refresh_uid.is_empty() {
+        return Err(anyhow!("missing refresh uid"));
+    }
+
+    let mut party_uids = args.party_uids.clone();
+    party_uids.sort();
+    if party_uids != stored_uids {
+        return Err(anyhow!(
+            "parties {:?} are not the parties of key {}: {:?}",
+            args.party_uids,
+            args.key_uid,
+            stored_uids
+        ));
+    }
+
+    // identity keys are needed to authenticate traffic; addresses are optional
+    let mut identity_keys =
+        align_with_uids(&args.party_uids, args.party_identity_keys, "identity keys")?;
+    if identity_keys.is_empty() {
+        return Err(anyhow!("missing identity keys"));
+    }
+    let mut addresses = align_with_uids(&args.party_uids, args.party_addresses, "addresses")?;
+
+    // a zero timeout falls back to the default of tofnd's config
+    let round_timeout = match args.round_timeout_ms {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
+
+    Ok(RefreshInitSanitized {
+        session_id: format!("{}{}", REFRESH_SESSION_PREFIX, args.refresh_uid),
+        key_uid: args.key_uid,
+        party_identity_keys: stored_uids
+            .iter()
+            .filter_map(|uid| identity_keys.remove(uid))
+            .collect(),
+        party_addresses: stored_uids
+            .iter()
+            .filter_map(|uid| addresses.remove(uid))
+            .collect(),
+        round_timeout,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_sanitize_args() {
+        let stored_uids = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let args = proto::RefreshInit {
+            key_uid: "key".to_owned(),
+            refresh_uid: "epoch 2".to_owned(),
+            party_uids: vec!["c".to_owned(), "a".to_owned(), "b".to_owned()],
+            party_identity_keys: vec![vec![3], vec![1], vec![2]],
+            party_addresses: vec![],
+            round_timeout_ms: 0,
+        };
+
+        // identity keys follow the stored order of parties
+        let res = refresh_sanitize_args(args.clone(), &stored_uids).unwrap();
+        assert_eq!(res.session_id, "refresh:epoch 2");
+        assert_eq!(res.party_identity_keys, vec![vec![1], vec![2], vec![3]]);
+        assert!(res.party_addresses.is_empty());
+        assert_eq!(res.round_timeout, None);
+
+        // other parties than the parties of the key
+        let mut other_parties = args.clone();
+        other_parties.party_uids[0] = "d".to_owned();
+        assert!(refresh_sanitize_args(other_parties, &stored_uids).is_err());
+
+        // missing or misaligned identity keys
+        let mut missing_keys = args.clone();
+        missing_keys.party_identity_keys = vec![];
+        assert!(refresh_sanitize_args(missing_keys, &stored_uids).is_err());
+        let mut misaligned_addresses = args.clone();
+        misaligned_addresses.party_addresses = vec!["addr".to_owned()];
+        assert!(refresh_sanitize_args(misaligned_addresses, &stored_uids).is_err());
+
+        // archived keys can't be refreshed
+        let mut archived = args.clone();
+        archived.key_uid = "archived:key".to_owned();
+        assert!(refresh_sanitize_args(archived, &stored_uids).is_err());
+
+        // missing refresh uid
+        let mut missing_uid = args;
+        missing_uid.refresh_uid = String::new();
+        assert!(refresh_sanitize_args(missing_uid, &stored_uids).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! Handles the refresh streaming gRPC for one party.
+//! Refresh re-randomizes the shares of an existing key among the same parties, without changing the group public key,
+//! so that shares leaked before a refresh are of no use with shares leaked after it.
+//!
+//! Protocol:
+//!   1. [self::init] First, the initialization message [proto::RefreshInit] is received from the client,
+//!      and the [PartyInfo] of the key is loaded from the KvStore.
+//!   2. Each party deals a zero-secret VSS: it broadcasts the commitments of a random polynomial with zero constant term,
+//!      and sends each other party the evaluations of the polynomial at the points of its shares; see [self::deal].
+//!      Deals that don't match their commitments are faults, and abort the refresh before anything is stored.
+//!   3. Each party adds the evaluations of all deals to its shares and the commitments of all deals to the VSS commitments of the key.
+//!      The new record replaces the old one atomically, and the old record is archived.
+//!      The new shares are returned encrypted as recovery info, since keygen's recovery info restores the old ones; see [super::recover].
+//!   4. Each party broadcasts the digest of its new VSS commitments, and the parties that did not confirm the same commitments are reported.
+//!      A party can't tell whether the others received all confirmations, so it keeps both records either way.
+//!   5. The client finalizes the refresh on all parties with [proto::FinalizeRefreshRequest]: committing purges the archive,
+//!      aborting restores the old record from it. Until then, the key uses the new record and can't be refreshed again.
+//!
+//! Traffic is transported like the traffic of keygen; see [crate::gg20::keygen] and [crate::gg20::exchange].
+
+use super::{
+    delete_key::ARCHIVED_KEY_PREFIX,
+    exchange::{Exchange, Faulters},
+    proto,
+    protocol::FaultRecord,
+    service::Gg20Service,
+    types::{GroupInfo, PartyInfo, ShareSecret},
+    vss::{self, Polynomial},
+};
+use bls12_381::Scalar;
+use proto::message_out::criminal_list::criminal::FaultKind;
+
+use tonic::Status;
+
+// tonic cruft
+use tokio::sync::mpsc;
+
+// logging
+use tracing::{info, warn, Span};
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::convert::TryInto;
+
+mod deal;
+mod init;
+use deal::{DealBcast, DealP2p};
+
+/// round in which parties deal their zero-secret VSS
+const DEAL_ROUND: usize = 1;
+/// round in which parties confirm the commitments of the refreshed key
+const CONFIRM_ROUND: usize = 2;
+
+/// get the key under which the record of `key_uid` is archived until a refresh is confirmed.
+/// Archived like deleted keys, so that the archive is hidden from listing.
+pub(super) fn archive_key(key_uid: &str) -> String {
+    format!("{}refresh:{}", ARCHIVED_KEY_PREFIX, key_uid)
+}
+
+impl Gg20Service {
+    /// handle refresh gRPC
+    pub async fn handle_refresh(
+        &self,
+        mut stream_in: tonic::Streaming<proto::MessageIn>,
+        stream_out_sender: mpsc::UnboundedSender<Result<proto::MessageOut, Status>>,
+        refresh_span: Span,
+    ) -> TofndResult<()> {
+        // 1.
+        let (refresh_init, party_info, traffic_auth) = self
+            .handle_refresh_init(&mut stream_in, refresh_span.clone())
+            .await?;
+        let party_uids = party_info.tofnd.party_uids.clone();
+        let my_index = party_info.tofnd.index;
+
+        // 2., 3. and 4.
+        let mut exchange = self.open_exchange(
+            stream_in,
+            stream_out_sender.clone(),
+            &refresh_init.session_id,
+            &party_uids,
+            my_index,
+            &refresh_init.party_addresses,
+            traffic_auth,
+            CONFIRM_ROUND,
+            refresh_init.round_timeout,
+            refresh_span,
+        )?;
+        let result = self
+            .execute_refresh(&mut exchange, &refresh_init.key_uid, party_info)
+            .await?;
+
+        stream_out_sender.send(Ok(proto::MessageOut::new_refresh_result(
+            &party_uids,
+            result,
+            &exchange.report,
+        )))?;
+        Ok(())
+    }
+
+    /// Commit or abort the pending refresh of a key.
+    /// Committing purges the old record from the archive; aborting restores it. Fails if no refresh of the key is pending.
+    pub(super) async fn handle_finalize_refresh(
+        &self,
+        request: proto::FinalizeRefreshRequest,
+    ) -> TofndResult<()> {
+        let key_uid = request.key_uid;
+        let archive_key = archive_key(&key_uid);
+
+        let kv = self.kv_manager.kv();
+        if !kv.exists(&archive_key).await.map_err(|err| anyhow!(err))? {
+            return Err(anyhow!("no refresh of key {} is pending", key_uid));
+        }
+
+        if request.commit {
+            kv.purge(&archive_key)
+                .await
+                .map_err(|err| anyhow!("could not purge archive of key {}: {}", key_uid, err))?;
+            info!("Committed the refresh of key {}", key_uid);
+        } else {
+            kv.revert(&key_uid, &archive_key)
+                .await
+                .map_err(|err| anyhow!("could not restore key {}: {}", key_uid, err))?;
+            info!(
+                "Aborted the refresh of key {}; restored the old shares",
+                key_uid
+            );
+        }
+        Ok(())
+    }
+
+    /// Run the rounds of a refresh of key `key_uid`, and replace its record once all deals are valid.
+    /// The old record stays archived until the refresh is finalized.
+    /// Returns the parties that faulted if the refresh did not complete.
+    async fn execute_refresh(
+        &self,
+        exchange: &mut Exchange,
+        key_uid: &str,
+        party_info: PartyInfo,
+    ) -> TofndResult<Result<proto::RefreshOutput, Faulters>> {
+        let my_index = exchange.my_index();
+        let all_parties: Vec<usize> = (0..exchange.party_uids().len()).collect();
+        let threshold = party_info.common.threshold();
+        let my_share_indices: Vec<usize> = party_info
+            .shares
+            .iter()
+            .map(|share| share.index())
+            .collect();
+
+        // 2. deal
+        let poly = Polynomial::random(Scalar::zero(), threshold);
+        exchange.send_bcast(DEAL_ROUND, &DealBcast::new(&poly).to_bytes()?)?;
+        for (to, share_indices) in vss::share_indices(&party_info.tofnd.share_counts)
+            .iter()
+            .enumerate()
+            .filter(|(to, _)| *to != my_index)
+        {
+            exchange.send_p2p(
+                DEAL_ROUND,
+                to,
+                &DealP2p::new(&poly, share_indices).to_bytes()?,
+            )?;
+        }
+
+        let (msgs, mut faulters) = exchange
+            .receive(DEAL_ROUND, &all_parties, true, true)
+            .await?;
+
+        // our own deal is not sent to us
+        let mut commitments = vec![poly.commitments()];
+        let mut shares: Vec<Scalar> = party_info
+            .shares
+            .iter()
+            .map(|share| share.x_i() + poly.eval(share.index()))
+            .collect();
+        for (from, (bcast, p2p)) in msgs.bcasts.iter().zip(&msgs.p2ps).enumerate() {
+            let (bcast, p2p) = match (bcast, p2p) {
+                (Some(bcast), Some(p2p)) => (bcast, p2p),
+                _ => continue,
+            };
+            match deal::verify_deal(bcast, p2p, threshold, &my_share_indices) {
+                Ok(deal) => {
+                    for (share, sub_share) in shares.iter_mut().zip(&deal.sub_shares) {
+                        *share += sub_share;
+                    }
+                    commitments.push(deal.commitments);
+                }
+                Err((kind, reason)) => {
+                    warn!(
+                        "invalid deal of party {}: {}",
+                        exchange.party_uids()[from],
+                        reason
+                    );
+                    exchange.report.set_fault(
+                        &mut faulters,
+                        from,
+                        FaultRecord::new(DEAL_ROUND, kind, reason),
+                    )?;
+                }
+            }
+        }
+        if !faulters.is_empty() {
+            return Ok(Err(faulters));
+        }
+
+        // 3. replace the record of the key and archive the old one
+        let vss_commitments =
+            deal::refresh_commitments(party_info.common.vss_commitments(), &commitments);
+        let pub_key = party_info.common.encoded_pubkey();
+        let refreshed = PartyInfo {
+            common: GroupInfo::new(threshold, pub_key.clone(), vss_commitments.clone()),
+            shares: my_share_indices
+                .iter()
+                .zip(shares)
+                .map(|(index, x_i)| ShareSecret::new(*index, x_i))
+                .collect(),
+            tofnd: party_info.tofnd,
+        };
+
+        let (private_recover_info, seed_fingerprint) = self
+            .refresh_recovery_info(key_uid, &refreshed.shares)
+            .await?;
+
+        let kv = self.kv_manager.kv();
+        let archive_key = archive_key(key_uid);
+        kv.replace(key_uid, &archive_key, refreshed.try_into()?)
+            .await
+            .map_err(|err| anyhow!("could not replace key {}: {}", key_uid, err))?;
+
+        // 4. confirm
+        let digest = vss::commitments_digest(&vss_commitments);
+        exchange.send_bcast(CONFIRM_ROUND, &digest)?;
+        let (msgs, mut faulters) = exchange
+            .receive(CONFIRM_ROUND, &all_parties, true, false)
+            .await?;
+        for (from, bcast) in msgs.bcasts.iter().enumerate() {
+            if matches!(bcast, Some(bcast) if *bcast != digest) {
+                exchange.report.set_fault(
+                    &mut faulters,
+                    from,
+                    FaultRecord::new(
+                        CONFIRM_ROUND,
+                        FaultKind::Protocol,
+                        "confirmed different commitments",
+                    ),
+                )?;
+            }
+        }
+
+        // the client commits or aborts the refresh on all parties; see [Self::handle_finalize_refresh]
+        if !faulters.is_empty() {
+            warn!(
+                "Refresh of key {} was not confirmed; keeping the old shares until the refresh is finalized",
+                key_uid
+            );
+            return Ok(Err(faulters));
+        }
+        info!(
+            "Refreshed the shares of key {}; keeping the old shares until the refresh is finalized",
+            key_uid
+        );
+
+        Ok(Ok(proto::RefreshOutput {
+            pub_key,
+            vss_commitments: vss::encode_points(&vss_commitments),
+            private_recover_info,
+            seed_fingerprint,
+        }))
+    }
+}
@@ -0,0 +1,263 @@
+//! Deals of a reshare.
+//! Each share `i` of the old key deals the evaluations of a random polynomial of degree `new_threshold` whose constant term is `x_i`;
+//! see [crate::gg20::vss]. The commitment of the constant term must be the public key of share `i`, so that a dealer can only deal its own share.
+//! Interpolating the deals of all old shares at 0 gives a polynomial whose constant term is the group secret,
+//! so the interpolated evaluations are the new shares and the interpolated commitments are the VSS commitments of the new key.
+
+use crate::gg20::{
+    bls,
+    protocol::Share,
+    vss::{self, Polynomial},
+};
+use bls12_381::{G1Projective, Scalar};
+use proto::message_out::criminal_list::criminal::FaultKind;
+use serde::{Deserialize, Serialize};
+
+use crate::proto;
+
+// error handling
+use crate::TofndResult;
+
+/// The bcast of a dealer; the commitments of one polynomial per share of the dealer
+#[derive(Serialize, Deserialize)]
+pub(super) struct ReshareBcast {
+    commitments: Vec<Vec<Vec<u8>>>, // compressed G1 points `a_k * g1`
+}
+
+/// The p2p of a dealer to a new party; per share of the dealer, one evaluation per share of the new party
+#[derive(Serialize, Deserialize)]
+pub(super) struct ReshareP2p {
+    sub_shares: Vec<Vec<Share>>,
+}
+
+impl ReshareBcast {
+    pub(super) fn new(polys: &[Polynomial]) -> Self {
+        Self {
+            commitments: polys
+                .iter()
+                .map(|poly| vss::encode_points(&poly.commitments()))
+                .collect(),
+        }
+    }
+
+    pub(super) fn to_bytes(&self) -> TofndResult<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+impl ReshareP2p {
+    pub(super) fn new(polys: &[Polynomial], share_indices: &[usize]) -> Self {
+        Self {
+            sub_shares: polys.iter().map(|poly| poly.deal(share_indices)).collect(),
+        }
+    }
+
+    pub(super) fn to_bytes(&self) -> TofndResult<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+/// The verified deal of a share of the old key
+pub(super) struct DealtShare {
+    pub(super) index: usize, // index of the dealt share in the old key
+    pub(super) commitments: Vec<G1Projective>,
+    pub(super) sub_shares: Vec<Scalar>, // aligned with our new shares; empty if we don't hold shares of the new key
+}
+
+impl DealtShare {
+    /// our own deal of share `index`, which is not sent to us
+    pub(super) fn local(index: usize, poly: &Polynomial, share_indices: &[usize]) -> Self {
+        Self {
+            index,
+            commitments: poly.commitments(),
+            sub_shares: share_indices
+                .iter()
+                .map(|index| poly.eval(*index))
+                .collect(),
+        }
+    }
+}
+
+/// Parse and verify the deal of a dealer that holds the shares `dealt_indices` of the old key with VSS commitments `old_commitments`.
+/// `p2p` and `share_indices` are `None` if we don't hold shares of the new key.
+/// Returns the kind and reason of the dealer's fault if the deal is malformed, if it does not deal the dealer's shares,
+/// or if a sub-share does not match the dealer's commitments.
+pub(super) fn verify_deal(
+    bcast: &[u8],
+    p2p: Option<&[u8]>,
+    dealt_indices: &[usize],
+    old_commitments: &[G1Projective],
+    new_threshold: usize,
+    share_indices: Option<&[usize]>,
+) -> Result<Vec<DealtShare>, (FaultKind, &'static str)> {
+    let bcast: ReshareBcast = serde_json::from_slice(bcast)
+        .map_err(|_| (FaultKind::Corrupted, "malformed deal bcast"))?;
+    if bcast.commitments.len() != dealt_indices.len() {
+        return Err((FaultKind::Protocol, "wrong number of dealt shares"));
+    }
+
+    let mut deals = Vec::with_capacity(dealt_indices.len());
+    for (index, commitments) in dealt_indices.iter().zip(&bcast.commitments) {
+        let commitments = vss::decode_points(commitments)
+            .ok_or((FaultKind::Corrupted, "invalid deal commitment"))?;
+        if commitments.len() != new_threshold + 1 {
+            return Err((FaultKind::Protocol, "wrong number of deal commitments"));
+        }
+        if commitments[0] != bls::share_public_key(old_commitments, *index) {
+            return Err((FaultKind::Protocol, "dealt a share other than its own"));
+        }
+        deals.push(DealtShare {
+            index: *index,
+            commitments,
+            sub_shares: vec![],
+        });
+    }
+
+    let (p2p, share_indices) = match (p2p, share_indices) {
+        (Some(p2p), Some(share_indices)) => (p2p, share_indices),
+        _ => return Ok(deals),
+    };
+    let p2p: ReshareP2p =
+        serde_json::from_slice(p2p).map_err(|_| (FaultKind::Corrupted, "malformed deal p2p"))?;
+    if p2p.sub_shares.len() != deals.len() {
+        return Err((FaultKind::Protocol, "wrong number of dealt shares"));
+    }
+    for (deal, sub_shares) in deals.iter_mut().zip(p2p.sub_shares) {
+        let dealt_indices: Vec<usize> = sub_shares.iter().map(|share| share.index).collect();
+        if dealt_indices != share_indices {
+            return Err((FaultKind::Protocol, "sub-shares for the wrong shares"));
+        }
+        if !sub_shares
+            .iter()
+            .all(|share| vss::verify_share(&deal.commitments, share))
+        {
+            return Err((
+                FaultKind::Protocol,
+                "sub-share does not match deal commitments",
+            ));
+        }
+        deal.sub_shares = sub_shares.iter().map(|share| share.scalar).collect();
+    }
+
+    Ok(deals)
+}
+
+/// Interpolate the deals of all shares of the old key at 0.
+/// Returns the VSS commitments of the new key and our new shares,
+/// or `None` if there are no deals or the dealt share indices are not distinct.
+pub(super) fn combine(deals: &[DealtShare]) -> Option<(Vec<G1Projective>, Vec<Scalar>)> {
+    let first = deals.first()?;
+    let dealt_indices: Vec<usize> = deals.iter().map(|deal| deal.index).collect();
+
+    let mut commitments = vec![G1Projective::identity(); first.commitments.len()];
+    let mut shares = vec![Scalar::zero(); first.sub_shares.len()];
+    for (i, deal) in deals.iter().enumerate() {
+        let lambda = bls::lagrange_coefficient(&dealt_indices, i)?;
+        for (commitment, dealt) in commitments.iter_mut().zip(&deal.commitments) {
+            *commitment += dealt * lambda;
+        }
+        for (share, sub_share) in shares.iter_mut().zip(&deal.sub_shares) {
+            *share += sub_share * lambda;
+        }
+    }
+    Some((commitments, shares))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reshare_deals() {
+        // an old key with threshold 1 and 3 shares held by 2 dealers; f(x) = 5 + ax
+        let key = Polynomial::random(Scalar::from(5), 1);
+        let old_commitments = key.commitments();
+        let dealers = vss::share_indices(&[2, 1]);
+
+        // a new key with threshold 2 and 4 shares held by 2 parties; we hold new shares 1, 2 and 3
+        let new_threshold = 2;
+        let new_shares = vss::share_indices(&[1, 3]);
+        let my_shares = new_shares[1].as_slice();
+
+        let polys: Vec<Vec<_>> = dealers
+            .iter()
+            .map(|indices| {
+                indices
+                    .iter()
+                    .map(|index| Polynomial::random(key.eval(*index), new_threshold))
+                    .collect()
+            })
+            .collect();
+        let deals: Vec<_> = dealers
+            .iter()
+            .zip(&polys)
+            .flat_map(|(indices, polys)| {
+                let bcast = ReshareBcast::new(polys).to_bytes().unwrap();
+                let p2p = ReshareP2p::new(polys, my_shares).to_bytes().unwrap();
+                verify_deal(
+                    &bcast,
+                    Some(&p2p),
+                    indices,
+                    &old_commitments,
+                    new_threshold,
+                    Some(my_shares),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        // the new key has the same secret, and our new shares match its commitments
+        let (commitments, shares) = combine(&deals).unwrap();
+        assert_eq!(commitments.len(), new_threshold + 1);
+        assert_eq!(commitments[0], old_commitments[0]);
+        for (index, share) in my_shares.iter().zip(&shares) {
+            assert_eq!(
+                G1Projective::generator() * share,
+                bls::share_public_key(&commitments, *index)
+            );
+        }
+
+        // parties without new shares only verify the commitments
+        let bcast = ReshareBcast::new(&polys[1]).to_bytes().unwrap();
+        let deal = verify_deal(
+            &bcast,
+            None,
+            &dealers[1],
+            &old_commitments,
+            new_threshold,
+            None,
+        )
+        .unwrap();
+        assert!(deal[0].sub_shares.is_empty());
+
+        // a dealer can't deal another share than its own
+        let fault = |bcast: &[u8], dealt_indices: &[usize]| {
+            verify_deal(
+                bcast,
+                None,
+                dealt_indices,
+                &old_commitments,
+                new_threshold,
+                None,
+            )
+            .err()
+            .map(|(kind, _)| kind)
+        };
+        assert_eq!(fault(&bcast, &[0]), Some(FaultKind::Protocol));
+        assert_eq!(fault(&bcast, &[2, 3]), Some(FaultKind::Protocol));
+        assert_eq!(fault(b"garbage", &[2]), Some(FaultKind::Corrupted));
+
+        // sub-shares must match the commitments
+        let mut bad_p2p = ReshareP2p::new(&polys[1], my_shares);
+        bad_p2p.sub_shares[0][1].scalar += Scalar::one();
+        assert!(verify_deal(
+            &bcast,
+            Some(&bad_p2p.to_bytes().unwrap()),
+            &dealers[1],
+            &old_commitments,
+            new_threshold,
+            Some(my_shares),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,403 @@
+//! This module handles the initialization of the Reshare protocol.
+//! A [ReshareInitSanitized] struct is created out of the raw incoming [proto::ReshareInit] message.
+//! Parties of the old key load its [PartyInfo] from the KvStore, and parties of the new key reserve the new key.
+//! The parties of a reshare are the union of the old and the new parties, sorted by uid.
+
+// tonic cruft
+use futures_util::StreamExt;
+
+// logging
+use tracing::{info, span, Level, Span};
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use super::{proto, Gg20Service};
+use crate::gg20::{
+    delete_key::check_key_uid, identity::TrafficAuth, refresh, types::PartyInfo, vss,
+};
+use crate::kv_manager::KeyReservation;
+use bls12_381::G1Projective;
+use std::{
+    collections::BTreeSet,
+    convert::{TryFrom, TryInto},
+    time::Duration,
+};
+
+/// prefix of the session ids of reshares, so that they don't collide with the session ids of keygens
+const RESHARE_SESSION_PREFIX: &str = "reshare:";
+
+/// ReshareInitSanitized holds all arguments needed by Reshare in the desired form; populated by proto::ReshareInit
+pub(super) struct ReshareInitSanitized {
+    pub(super) key_uid: String,              // the reshared key
+    pub(super) new_key_uid: String,          // the key of the new parties
+    pub(super) session_id: String,           // session's UID
+    pub(super) party_uids: Vec<String>, // all parties of the session; the sorted union of old and new parties
+    pub(super) my_index: usize,         // my index in party_uids
+    pub(super) old_parties: Vec<usize>, // indices of the old parties in party_uids; sorted by uid
+    pub(super) old_share_counts: Vec<usize>, // alligned with old_parties
+    pub(super) new_parties: Vec<usize>, // indices of the new parties in party_uids; sorted by uid
+    pub(super) new_share_counts: Vec<usize>, // alligned with new_parties
+    pub(super) new_threshold: usize,
+    pub(super) pub_key: Vec<u8>, // encoded group public key
+    pub(super) vss_commitments: Vec<G1Projective>, // VSS commitments of the old key
+    pub(super) party_identity_keys: Vec<Vec<u8>>, // identity keys of parties; alligned with party_uids
+    pub(super) party_addresses: Vec<String>, // peer addresses of parties; alligned with party_uids, or empty
+    pub(super) round_timeout: Option<Duration>, // deadline of each round; `None` for tofnd's default
+}
+
+impl ReshareInitSanitized {
+    /// my position among the old parties, if I hold shares of the old key
+    pub(super) fn my_old_position(&self) -> Option<usize> {
+        self.old_parties.iter().position(|i| *i == self.my_index)
+    }
+
+    /// my position among the new parties, if I receive shares of the new key
+    pub(super) fn my_new_position(&self) -> Option<usize> {
+        self.new_parties.iter().position(|i| *i == self.my_index)
+    }
+
+    pub(super) fn old_party_uids(&self) -> Vec<String> {
+        self.old_parties
+            .iter()
+            .map(|i| self.party_uids[*i].clone())
+            .collect()
+    }
+
+    pub(super) fn new_party_uids(&self) -> Vec<String> {
+        self.new_parties
+            .iter()
+            .map(|i| self.party_uids[*i].clone())
+            .collect()
+    }
+}
+
+impl Gg20Service {
+    /// Receives a message from the stream and tries to handle reshare init operations.
+    /// On success, returns a sanitized struct ready to be used by the protocol, the stored [PartyInfo] of the old key if we hold its shares,
+    /// the reservation of the new key if we receive its shares, and the [TrafficAuth] that authenticates the traffic of the session.
+    pub(super) async fn handle_reshare_init(
+        &self,
+        stream: &mut tonic::Streaming<proto::MessageIn>,
+        reshare_span: Span,
+    ) -> TofndResult<(
+        ReshareInitSanitized,
+        Option<PartyInfo>,
+        Option<KeyReservation>,
+        TrafficAuth,
+    )> {
+        // try to receive message
+        let msg = stream
+            .next()
+            .await
+            .ok_or_else(|| anyhow!("stream closed by client"))?
+            .map_err(|e| anyhow!("stream closed by server: {}", e))?;
+
+        // check if message is of expected type
+        let reshare_init = match msg.data {
+            Some(proto::message_in::Data::ReshareInit(r)) => r,
+            msg_data => {
+                return Err(anyhow!(
+                    "wrong message type; expecting ReshareInit, got {:?}",
+                    msg_data
+                ))
+            }
+        };
+
+        let reshare_init = reshare_sanitize_args(reshare_init)
+            .map_err(|err| anyhow!("failed to sanitize ReshareInit: {}", err))?;
+
+        // parties of the old key deal the shares they stored
+        let party_info = match reshare_init.my_old_position() {
+            Some(_) => Some(self.load_reshared_key(&reshare_init).await?),
+            None => None,
+        };
+
+        // check identity keys of parties before anything is reserved
+        let traffic_auth = TrafficAuth::new(
+            &reshare_init.session_id,
+            &reshare_init.party_uids,
+            reshare_init.my_index,
+            self.identity_key().await?,
+            &reshare_init.party_identity_keys,
+        )
+        .map_err(|err| anyhow!("failed to authenticate parties: {}", err))?;
+
+        // parties of the new key reserve it until all new parties confirmed it
+        let reservation = match reshare_init.my_new_position() {
+            Some(_) => Some(
+                self.kv_manager
+                    .kv()
+                    .reserve_key(reshare_init.new_key_uid.clone())
+                    .await
+                    .map_err(|err| anyhow!("failed to reseve key: {}", err))?,
+            ),
+            None => None,
+        };
+
+        let init_span = span!(parent: &reshare_span, Level::INFO, "init");
+        let _enter = init_span.enter();
+        info!(
+            "[uid:{}] starting Reshare with [key: {}, new key: {}, session: {}, old participants: {:?}, new participants: {:?}, new threshold: {}",
+            reshare_init.party_uids[reshare_init.my_index],
+            reshare_init.key_uid,
+            reshare_init.new_key_uid,
+            reshare_init.session_id,
+            reshare_init.old_party_uids(),
+            reshare_init.new_party_uids(),
+            reshare_init.new_threshold,
+        );
+
+        Ok((reshare_init, party_info, reservation, traffic_auth))
+    }
+
+    /// load the [PartyInfo] of the reshared key and check that the init message describes it
+    async fn load_reshared_key(
+        &self,
+        reshare_init: &ReshareInitSanitized,
+    ) -> TofndResult<PartyInfo> {
+        let key_uid = &reshare_init.key_uid;
+        let party_info: PartyInfo = self
+            .kv_manager
+            .kv()
+            .get(key_uid)
+            .await
+            .map_err(|err| anyhow!("could not find key {}: {}", key_uid, err))?
+            .try_into()?;
+
+        if party_info.tofnd.party_uids != reshare_init.old_party_uids()
+            || party_info.tofnd.share_counts != reshare_init.old_share_counts
+        {
+            return Err(anyhow!(
+                "old parties {:?} with share counts {:?} are not the parties of key {}: {:?} with share counts {:?}",
+                reshare_init.old_party_uids(),
+                reshare_init.old_share_counts,
+                key_uid,
+                party_info.tofnd.party_uids,
+                party_info.tofnd.share_counts,
+            ));
+        }
+        if party_info.common.encoded_pubkey() != reshare_init.pub_key
+            || party_info.common.vss_commitments() != reshare_init.vss_commitments.as_slice()
+        {
+            return Err(anyhow!(
+                "public key or VSS commitments do not match the stored ones of key {}",
+                key_uid
+            ));
+        }
+
+        // the archive of an unconfirmed refresh must be resolved first
+        if self
+            .kv_manager
+            .kv()
+            .exists(&refresh::archive_key(key_uid))
+            .await?
+        {
+            return Err(anyhow!(
+                "an earlier refresh of key {} is not confirmed by all parties",
+                key_uid
+            ));
+        }
+
+        Ok(party_info)
+    }
+}
+
+/// Sanitize arguments of incoming message.
+/// Old and new parties are sanitized like the parties of keygen, see [Gg20Service::keygen_sanitize_args];
+/// the threshold of the old key is the degree of its VSS commitments.
+fn reshare_sanitize_args(args: proto::ReshareInit) -> TofndResult<ReshareInitSanitized> {
+    check_key_uid(&args.key_uid)?;
+    check_key_uid(&args.new_key_uid)?;
+    if args.new_key_uid.is_empty() || args.new_key_uid == args.key_uid {
+        return Err(anyhow!(
+            "new key uid must be set and differ from the old key uid"
+        ));
+    }
+
+    let vss_commitments = vss::decode_points(&args.vss_commitments)
+        .ok_or_else(|| anyhow!("invalid VSS commitments"))?;
+    let old_threshold = vss_commitments
+        .len()
+        .checked_sub(1)
+        .ok_or_else(|| anyhow!("missing VSS commitments"))?;
+    if args.pub_key.is_empty() {
+        return Err(anyhow!("missing public key"));
+    }
+    // new parties have no record of the key, so the public key must be the one of the commitments
+    if vss::encode_pub_key(&vss_commitments).as_ref() != Some(&args.pub_key) {
+        return Err(anyhow!("public key does not match the VSS commitments"));
+    }
+
+    // my party may be missing from one of the committees, but not from both
+    let my_party_uid = &args.my_party_uid;
+    let new_key_uid = &args.new_key_uid;
+    let sanitize_committee = |uids: Vec<String>, share_counts: Vec<u32>, threshold: usize| {
+        let my_party_index = uids
+            .iter()
+            .position(|uid| uid == my_party_uid)
+            .unwrap_or_default();
+        Gg20Service::keygen_sanitize_args(proto::KeygenInit {
+            new_key_uid: new_key_uid.clone(),
+            party_uids: uids,
+            party_share_counts: share_counts,
+            my_party_index: my_party_index.try_into()?,
+            threshold: threshold.try_into()?,
+            ..Default::default()
+        })
+    };
+    let old = sanitize_committee(
+        args.old_party_uids,
+        args.old_party_share_counts,
+        old_threshold,
+    )
+    .map_err(|err| anyhow!("old parties: {}", err))?;
+    let new_threshold = usize::try_from(args.new_threshold)?;
+    let new = sanitize_committee(
+        args.new_party_uids,
+        args.new_party_share_counts,
+        new_threshold,
+    )
+    .map_err(|err| anyhow!("new parties: {}", err))?;
+
+    let party_uids: Vec<String> = old
+        .party_uids
+        .iter()
+        .chain(&new.party_uids)
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let my_index = party_uids
+        .iter()
+        .position(|uid| uid == my_party_uid)
+        .ok_or_else(|| anyhow!("party {} is neither an old nor a new party", my_party_uid))?;
+    let session_indices = |uids: &[String]| -> Vec<usize> {
+        uids.iter()
+            .filter_map(|uid| party_uids.iter().position(|u| u == uid))
+            .collect()
+    };
+
+    // identity keys are needed to authenticate traffic; addresses are optional
+    let mut identity_keys = args.party_identity_keys;
+    let party_identity_keys = party_uids
+        .iter()
+        .map(|uid| {
+            identity_keys
+                .remove(uid)
+                .ok_or_else(|| anyhow!("missing identity key of party {}", uid))
+        })
+        .collect::<TofndResult<_>>()?;
+    let mut addresses = args.party_addresses;
+    let party_addresses = match addresses.is_empty() {
+        true => vec![],
+        false => party_uids
+            .iter()
+            .map(|uid| {
+                addresses
+                    .remove(uid)
+                    .ok_or_else(|| anyhow!("missing address of party {}", uid))
+            })
+            .collect::<TofndResult<_>>()?,
+    };
+
+    // a zero timeout falls back to the default of tofnd's config
+    let round_timeout = match args.round_timeout_ms {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
+
+    Ok(ReshareInitSanitized {
+        session_id: format!("{}{}", RESHARE_SESSION_PREFIX, args.new_key_uid),
+        key_uid: args.key_uid,
+        new_key_uid: args.new_key_uid,
+        my_index,
+        old_parties: session_indices(&old.party_uids),
+        old_share_counts: old.party_share_counts,
+        new_parties: session_indices(&new.party_uids),
+        new_share_counts: new.party_share_counts,
+        new_threshold,
+        party_uids,
+        pub_key: args.pub_key,
+        vss_commitments,
+        party_identity_keys,
+        party_addresses,
+        round_timeout,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::vss::Polynomial;
+    use bls12_381::Scalar;
+
+    #[test]
+    fn test_reshare_sanitize_args() {
+        let uids = |uids: &[&str]| uids.iter().map(|uid| uid.to_string()).collect::<Vec<_>>();
+        let commitments = Polynomial::random(Scalar::from(5), 1).commitments();
+        let args = proto::ReshareInit {
+            key_uid: "key".to_owned(),
+            new_key_uid: "new key".to_owned(),
+            my_party_uid: "d".to_owned(),
+            old_party_uids: uids(&["c", "a", "b"]),
+            old_party_share_counts: vec![1, 2, 1],
+            new_party_uids: uids(&["d", "b"]),
+            new_party_share_counts: vec![],
+            new_threshold: 1,
+            pub_key: vss::encode_pub_key(&commitments).unwrap(),
+            vss_commitments: vss::encode_points(&commitments),
+            party_identity_keys: ["a", "b", "c", "d"]
+                .iter()
+                .map(|uid| (uid.to_string(), uid.as_bytes().to_vec()))
+                .collect(),
+            party_addresses: Default::default(),
+            round_timeout_ms: 0,
+        };
+
+        // parties are the sorted union of old and new parties
+        let res = reshare_sanitize_args(args.clone()).unwrap();
+        assert_eq!(res.session_id, "reshare:new key");
+        assert_eq!(res.party_uids, uids(&["a", "b", "c", "d"]));
+        assert_eq!(res.my_index, 3);
+        assert_eq!(res.old_parties, vec![0, 1, 2]);
+        assert_eq!(res.old_share_counts, vec![2, 1, 1]);
+        assert_eq!(res.new_parties, vec![1, 3]);
+        assert_eq!(res.new_share_counts, vec![1, 1]);
+        assert_eq!(res.my_old_position(), None);
+        assert_eq!(res.my_new_position(), Some(1));
+        assert_eq!(res.party_identity_keys[2], b"c".to_vec());
+        assert!(res.party_addresses.is_empty());
+
+        // my party must take part
+        let mut not_a_party = args.clone();
+        not_a_party.my_party_uid = "e".to_owned();
+        assert!(reshare_sanitize_args(not_a_party).is_err());
+
+        // the new parties must hold more shares than the new threshold
+        let mut high_threshold = args.clone();
+        high_threshold.new_threshold = 2;
+        assert!(reshare_sanitize_args(high_threshold).is_err());
+
+        // identity keys of all parties are needed
+        let mut missing_key = args.clone();
+        missing_key.party_identity_keys.remove("a");
+        assert!(reshare_sanitize_args(missing_key).is_err());
+
+        // the new key must be another key
+        let mut same_key = args.clone();
+        same_key.new_key_uid = "key".to_owned();
+        assert!(reshare_sanitize_args(same_key).is_err());
+
+        // the public key must be the one of the commitments
+        let mut wrong_pub_key = args.clone();
+        wrong_pub_key.pub_key = vss::encode_pub_key(&commitments[1..]).unwrap();
+        assert!(reshare_sanitize_args(wrong_pub_key).is_err());
+
+        // the old key must be described by valid commitments
+        let mut bad_commitments = args;
+        bad_commitments.vss_commitments = vec![vec![0; 48]];
+        assert!(reshare_sanitize_args(bad_commitments).is_err());
+    }
+}
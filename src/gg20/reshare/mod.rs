@@ -0,0 +1,268 @@
+//! Handles the reshare streaming gRPC for one party.
+//! Reshare hands the secret of an existing key to a new set of parties with a new threshold, without changing the group public key.
+//! The new shares are stored under a new key; the old key is left as it is, and can be deleted once the new key is in use.
+//!
+//! Protocol:
+//!   1. [self::init] First, the initialization message [proto::ReshareInit] is received from the client.
+//!      Old parties load the [PartyInfo] of the old key from the KvStore, and new parties reserve the new key.
+//!   2. Each share of the old key is dealt with a VSS of degree `new_threshold` whose secret is the share itself:
+//!      old parties broadcast the commitments of one polynomial per share, and send each new party the evaluations
+//!      of the polynomials at the points of its new shares; see [self::deal]. All old parties must deal.
+//!      Deals that don't match their commitments or don't deal the dealer's own shares are faults, and abort the reshare.
+//!   3. New parties interpolate the evaluations of all deals to get their new shares, and all parties interpolate
+//!      the commitments of all deals to get the VSS commitments of the new key.
+//!   4. New parties broadcast the digest of the new VSS commitments. Once all new parties confirmed the same commitments,
+//!      new parties store the new key. Otherwise, the reservation of the new key is dropped and the parties that did not confirm are reported.
+//!
+//! Traffic is transported like the traffic of keygen; see [crate::gg20::keygen] and [crate::gg20::exchange].
+
+use super::{
+    exchange::{Exchange, Faulters},
+    proto,
+    protocol::FaultRecord,
+    service::Gg20Service,
+    types::{GroupInfo, PartyInfo, ShareSecret, TofndInfo},
+    vss::{self, Polynomial},
+};
+use proto::message_out::criminal_list::criminal::FaultKind;
+
+use tonic::Status;
+
+// tonic cruft
+use tokio::sync::mpsc;
+
+// logging
+use tracing::{info, warn, Span};
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::convert::TryInto;
+
+mod deal;
+mod init;
+use deal::{DealtShare, ReshareBcast, ReshareP2p};
+use init::ReshareInitSanitized;
+
+/// round in which old parties deal their shares
+const DEAL_ROUND: usize = 1;
+/// round in which new parties confirm the commitments of the new key
+const CONFIRM_ROUND: usize = 2;
+
+impl Gg20Service {
+    /// handle reshare gRPC
+    pub async fn handle_reshare(
+        &self,
+        mut stream_in: tonic::Streaming<proto::MessageIn>,
+        stream_out_sender: mpsc::UnboundedSender<Result<proto::MessageOut, Status>>,
+        reshare_span: Span,
+    ) -> TofndResult<()> {
+        // 1.
+        let (reshare_init, party_info, reservation, traffic_auth) = self
+            .handle_reshare_init(&mut stream_in, reshare_span.clone())
+            .await?;
+
+        // 2., 3. and 4.
+        let mut exchange = self.open_exchange(
+            stream_in,
+            stream_out_sender.clone(),
+            &reshare_init.session_id,
+            &reshare_init.party_uids,
+            reshare_init.my_index,
+            &reshare_init.party_addresses,
+            traffic_auth,
+            CONFIRM_ROUND,
+            reshare_init.round_timeout,
+            reshare_span,
+        )?;
+        // stored with the new key, so that we know which mnemonic was in use when it was received
+        let seed_fingerprint = self.kv_manager.seed_fingerprint().await?;
+        let result =
+            execute_reshare(&mut exchange, &reshare_init, party_info, seed_fingerprint).await;
+
+        // store the new key if we received its shares; drop its reservation otherwise
+        let kv = self.kv_manager.kv();
+        let result = match (result, reservation) {
+            (Ok(Ok((output, Some(new_party_info)))), Some(reservation)) => {
+                kv.put(reservation, new_party_info.try_into()?)
+                    .await
+                    .map_err(|err| {
+                        anyhow!("could not store key {}: {}", reshare_init.new_key_uid, err)
+                    })?;
+                info!(
+                    "Stored the shares of key {} reshared from key {}",
+                    reshare_init.new_key_uid, reshare_init.key_uid
+                );
+                Ok(output)
+            }
+            (result, reservation) => {
+                if let Some(reservation) = reservation {
+                    kv.unreserve_key(reservation).await;
+                }
+                result?.map(|(output, _)| output)
+            }
+        };
+
+        stream_out_sender.send(Ok(proto::MessageOut::new_reshare_result(
+            &reshare_init.party_uids,
+            result,
+            &exchange.report,
+        )))?;
+        Ok(())
+    }
+}
+
+/// Run the rounds of a reshare. `party_info` is the stored [PartyInfo] of the old key if we hold its shares,
+/// and `seed_fingerprint` is the fingerprint of the current mnemonic.
+/// Returns the public data of the new key along with the [PartyInfo] of the new key if we receive its shares,
+/// or the parties that faulted if the reshare did not complete.
+async fn execute_reshare(
+    exchange: &mut Exchange,
+    init: &ReshareInitSanitized,
+    party_info: Option<PartyInfo>,
+    seed_fingerprint: Vec<u8>,
+) -> TofndResult<Result<(proto::ReshareOutput, Option<PartyInfo>), Faulters>> {
+    let my_index = exchange.my_index();
+    let old_share_indices = vss::share_indices(&init.old_share_counts);
+    let new_share_indices = vss::share_indices(&init.new_share_counts);
+    let my_new_position = init.my_new_position();
+    let my_new_shares = my_new_position.map(|pos| new_share_indices[pos].as_slice());
+
+    // 2. deal our shares of the old key
+    let mut deals = vec![];
+    if let Some(party_info) = &party_info {
+        let polys: Vec<Polynomial> = party_info
+            .shares
+            .iter()
+            .map(|share| Polynomial::random(*share.x_i(), init.new_threshold))
+            .collect();
+        exchange.send_bcast(DEAL_ROUND, &ReshareBcast::new(&polys).to_bytes()?)?;
+        for (to, share_indices) in init
+            .new_parties
+            .iter()
+            .zip(&new_share_indices)
+            .filter(|(to, _)| **to != my_index)
+        {
+            exchange.send_p2p(
+                DEAL_ROUND,
+                *to,
+                &ReshareP2p::new(&polys, share_indices).to_bytes()?,
+            )?;
+        }
+
+        // our own deal is not sent to us
+        deals.extend(party_info.shares.iter().zip(&polys).map(|(share, poly)| {
+            DealtShare::local(share.index(), poly, my_new_shares.unwrap_or_default())
+        }));
+    }
+
+    let (msgs, mut faulters) = exchange
+        .receive(DEAL_ROUND, &init.old_parties, true, my_new_shares.is_some())
+        .await?;
+    for (from, dealt_indices) in init.old_parties.iter().zip(&old_share_indices) {
+        let bcast = match &msgs.bcasts[*from] {
+            Some(bcast) => bcast,
+            None => continue,
+        };
+        let p2p = match (my_new_shares, &msgs.p2ps[*from]) {
+            (None, _) => None,
+            (Some(_), Some(p2p)) => Some(p2p.as_slice()),
+            (Some(_), None) => continue,
+        };
+        match deal::verify_deal(
+            bcast,
+            p2p,
+            dealt_indices,
+            &init.vss_commitments,
+            init.new_threshold,
+            my_new_shares,
+        ) {
+            Ok(dealt_shares) => deals.extend(dealt_shares),
+            Err((kind, reason)) => {
+                warn!(
+                    "invalid deal of party {}: {}",
+                    exchange.party_uids()[*from],
+                    reason
+                );
+                exchange.report.set_fault(
+                    &mut faulters,
+                    *from,
+                    FaultRecord::new(DEAL_ROUND, kind, reason),
+                )?;
+            }
+        }
+    }
+    if !faulters.is_empty() {
+        return Ok(Err(faulters));
+    }
+
+    // 3. interpolate the deals of all shares of the old key
+    let (vss_commitments, shares) =
+        deal::combine(&deals).ok_or_else(|| anyhow!("could not interpolate deals"))?;
+    if vss_commitments[0] != init.vss_commitments[0] {
+        return Err(anyhow!(
+            "reshared commitments do not match the secret of key {}",
+            init.key_uid
+        ));
+    }
+
+    // 4. confirm
+    let digest = vss::commitments_digest(&vss_commitments);
+    if my_new_shares.is_some() {
+        exchange.send_bcast(CONFIRM_ROUND, &digest)?;
+    }
+    let (msgs, mut faulters) = exchange
+        .receive(CONFIRM_ROUND, &init.new_parties, true, false)
+        .await?;
+    for (from, bcast) in msgs.bcasts.iter().enumerate() {
+        if matches!(bcast, Some(bcast) if *bcast != digest) {
+            exchange.report.set_fault(
+                &mut faulters,
+                from,
+                FaultRecord::new(
+                    CONFIRM_ROUND,
+                    FaultKind::Protocol,
+                    "confirmed different commitments",
+                ),
+            )?;
+        }
+    }
+    if !faulters.is_empty() {
+        warn!(
+            "Reshare of key {} to key {} was not confirmed",
+            init.key_uid, init.new_key_uid
+        );
+        return Ok(Err(faulters));
+    }
+
+    let new_party_info = match (my_new_position, my_new_shares) {
+        (Some(position), Some(share_indices)) => Some(PartyInfo {
+            common: GroupInfo::new(
+                init.new_threshold,
+                init.pub_key.clone(),
+                vss_commitments.clone(),
+            ),
+            shares: share_indices
+                .iter()
+                .zip(shares)
+                .map(|(index, x_i)| ShareSecret::new(*index, x_i))
+                .collect(),
+            tofnd: TofndInfo::new(
+                init.new_party_uids(),
+                init.new_share_counts.clone(),
+                position,
+                seed_fingerprint,
+            ),
+        }),
+        _ => None,
+    };
+
+    Ok(Ok((
+        proto::ReshareOutput {
+            pub_key: init.pub_key.clone(),
+            vss_commitments: vss::encode_points(&vss_commitments),
+        },
+        new_party_info,
+    )))
+}
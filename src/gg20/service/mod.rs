@@ -1,6 +1,6 @@
 //! This mod includes the service implementation derived from
 
-use super::proto;
+use super::{peer::PeerSessions, proto};
 use crate::config::Config;
 use crate::kv_manager::KvManager;
 
@@ -12,9 +12,18 @@ pub mod malicious;
 pub struct Gg20Service {
     pub(super) kv_manager: KvManager,
     pub(super) cfg: Config,
+    pub(super) peer_sessions: PeerSessions,
 }
 
 /// create a new Gg20 gRPC server
-pub fn new_service(cfg: Config, kv_manager: KvManager) -> impl proto::gg20_server::Gg20 {
-    Gg20Service { kv_manager, cfg }
+pub fn new_service(
+    cfg: Config,
+    kv_manager: KvManager,
+    peer_sessions: PeerSessions,
+) -> impl proto::gg20_server::Gg20 {
+    Gg20Service {
+        kv_manager,
+        cfg,
+        peer_sessions,
+    }
 }
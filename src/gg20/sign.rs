@@ -0,0 +1,93 @@
+//! This module handles the sign gRPC.
+//! Request includes a `key_uid` and the message to sign. BLS partial signatures do not require
+//! any interaction between parties, so each party signs with all of its shares stored under `key_uid`.
+//! The client is responsible to combine `threshold + 1` partial signatures into the group signature.
+
+use super::{bls, delete_key::check_key_uid, proto, service::Gg20Service, types::PartyInfo};
+
+// logging
+use tracing::info;
+
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
+use std::convert::{TryFrom, TryInto};
+
+impl Gg20Service {
+    pub(super) async fn handle_sign(
+        &self,
+        request: proto::SignRequest,
+    ) -> TofndResult<Vec<proto::PartialSignature>> {
+        // get party's shares from kv store
+        check_key_uid(&request.key_uid)?;
+        let party_info: PartyInfo = self
+            .kv_manager
+            .kv()
+            .get(&request.key_uid)
+            .await
+            .map_err(|err| anyhow!("could not find key {}: {}", request.key_uid, err))?
+            .try_into()?;
+
+        info!(
+            "Signing with {} shares of key {}",
+            party_info.shares.len(),
+            request.key_uid
+        );
+
+        // compute a partial signature for each of our shares
+        party_info
+            .shares
+            .iter()
+            .map(|share| {
+                Ok(proto::PartialSignature {
+                    share_index: u32::try_from(share.index())?,
+                    signature: bls::partial_sign(share.x_i(), &request.msg_to_sign)
+                        .to_compressed()
+                        .to_vec(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg20::test_utils::{store_key, test_service};
+    use bls12_381::{G1Affine, G2Affine, Scalar};
+    use testdir::testdir;
+    use tracing_test::traced_test;
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_sign() {
+        let service = test_service(testdir!()).await;
+        let poly = store_key(&service, "key", Scalar::from(7)).await;
+        let msg = b"message".to_vec();
+        let request = |key_uid: &str| proto::SignRequest {
+            key_uid: key_uid.to_owned(),
+            msg_to_sign: msg.clone(),
+        };
+
+        // a partial signature for each of our shares, valid under the share's public key
+        let partials = service.handle_sign(request("key")).await.unwrap();
+        let share_indices: Vec<_> = partials.iter().map(|p| p.share_index).collect();
+        assert_eq!(share_indices, vec![0, 1]);
+
+        let h = G2Affine::from(bls::hash_to_g2(&msg, bls::SIGNATURE_DST));
+        for partial in partials {
+            let pub_key = G1Affine::from(bls::share_public_key(
+                &poly.commitments(),
+                partial.share_index as usize,
+            ));
+            let signature =
+                G2Affine::from_compressed(partial.signature[..].try_into().unwrap()).unwrap();
+            assert!(bls::verify_partial(&pub_key, &h, &signature));
+        }
+
+        // unknown keys and reserved uids
+        assert!(service.handle_sign(request("unknown")).await.is_err());
+        assert!(service.handle_sign(request("archived:key")).await.is_err());
+    }
+}
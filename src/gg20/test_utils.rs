@@ -0,0 +1,102 @@
+//! Helpers for tests of the gRPC handlers.
+//! Handlers run on a kv store in memory that holds a mnemonic. Keys are dealt by the tests instead of keygen,
+//! so that tests know the secret polynomial of every key they store.
+
+use super::{
+    peer::PeerSessions,
+    service::Gg20Service,
+    types::{GroupInfo, PartyInfo, ShareSecret, TofndInfo},
+    vss,
+};
+use crate::{
+    config::Config,
+    encrypted_sled::{get_test_password, PasswordMethod},
+    kv_manager::{KvManager, StorageBackend},
+    mnemonic::Cmd,
+};
+use bls12_381::Scalar;
+
+use std::{collections::HashMap, convert::TryInto, path::PathBuf, time::Duration};
+
+/// threshold of test keys
+pub(super) const THRESHOLD: usize = 1;
+
+/// parties of test keys; we are the first party and hold shares 0 and 1
+pub(super) const PARTY_UIDS: [&str; 2] = ["alice", "bob"];
+pub(super) const SHARE_COUNTS: [usize; 2] = [2, 2];
+
+/// create a service with a new mnemonic on a kv store in memory; the mnemonic is exported to `root`
+pub(super) async fn test_service(root: PathBuf) -> Gg20Service {
+    let kv_manager = KvManager::new(root.clone(), get_test_password(), StorageBackend::Memory)
+        .unwrap()
+        .handle_mnemonic(&Cmd::Create)
+        .await
+        .unwrap();
+
+    let cfg = Config {
+        ip: "127.0.0.1".to_owned(),
+        port: 0,
+        peer_ip: "127.0.0.1".to_owned(),
+        peer_port: 0,
+        safe_keygen: false,
+        mnemonic_cmd: Cmd::Existing,
+        tofnd_path: root,
+        password_method: PasswordMethod::NoPassword,
+        storage: StorageBackend::Memory,
+        round_timeout: Duration::from_secs(1),
+        peers: HashMap::new(),
+        #[cfg(feature = "malicious")]
+        behaviours: super::service::malicious::Behaviours {
+            keygen: tofn::gg20::keygen::malicious::Behaviour::Honest,
+        },
+    };
+
+    Gg20Service {
+        kv_manager,
+        cfg,
+        peer_sessions: PeerSessions::default(),
+    }
+}
+
+/// get our party info of a key dealt by `poly`, recording the mnemonic with `seed_fingerprint`
+pub(super) fn party_info(poly: &vss::Polynomial, seed_fingerprint: Vec<u8>) -> PartyInfo {
+    let commitments = poly.commitments();
+    PartyInfo {
+        common: GroupInfo::new(
+            THRESHOLD,
+            vss::encode_pub_key(&commitments).unwrap(),
+            commitments,
+        ),
+        shares: (0..SHARE_COUNTS[0])
+            .map(|index| ShareSecret::new(index, poly.eval(index)))
+            .collect(),
+        tofnd: TofndInfo::new(
+            PARTY_UIDS.iter().map(|uid| uid.to_string()).collect(),
+            SHARE_COUNTS.to_vec(),
+            0,
+            seed_fingerprint,
+        ),
+    }
+}
+
+/// store `party_info` under `key_uid`
+pub(super) async fn put_key(service: &Gg20Service, key_uid: &str, party_info: PartyInfo) {
+    let kv = service.kv_manager.kv();
+    let reservation = kv.reserve_key(key_uid.to_owned()).await.unwrap();
+    kv.put(reservation, party_info.try_into().unwrap())
+        .await
+        .unwrap();
+}
+
+/// deal a key with group secret `secret` with the latest mnemonic and store it under `key_uid`.
+/// Returns the polynomial that dealt the shares of all parties.
+pub(super) async fn store_key(
+    service: &Gg20Service,
+    key_uid: &str,
+    secret: Scalar,
+) -> vss::Polynomial {
+    let poly = vss::Polynomial::random(secret, THRESHOLD);
+    let seed_fingerprint = service.kv_manager.seed_fingerprint().await.unwrap();
+    put_key(service, key_uid, party_info(&poly, seed_fingerprint)).await;
+    poly
+}
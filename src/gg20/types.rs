@@ -5,6 +5,10 @@ use zeroize::Zeroize;
 
 use tracing::{info, span, Level, Span};
 
+// error handling
+use crate::TofndResult;
+use anyhow::anyhow;
+
 // pub(super) type MessageDigest = tofn::gg20::sign::MessageDigest;
 
 /// Mnemonic type needs to be known globaly to create/access the mnemonic kv store
@@ -31,8 +35,14 @@ impl<InMsg, OutMsg> ProtocolCommunication<InMsg, OutMsg> {
     }
 }
 
+use bls12_381::{G1Affine, G1Projective, Scalar};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tofn::gg20::keygen::{GroupPublicInfo, SecretKeyShare, ShareSecretInfo};
+use tofn::sdk::api::{deserialize, serialize, BytesVec};
 
 /// Struct to hold `tonfd` info. This consists of information we need to
 /// store in the KV store that is not relevant to `tofn`
@@ -41,13 +51,177 @@ pub(super) struct TofndInfo {
     pub(super) party_uids: Vec<String>,
     pub(super) share_counts: Vec<usize>,
     pub(super) index: usize,
+    pub(super) created_at: u64, // unix timestamp in seconds at which the shares were stored
+    pub(super) seed_fingerprint: Vec<u8>, // fingerprint of the mnemonic that was in use when the shares were stored
+}
+
+impl TofndInfo {
+    /// tofnd data of shares that are stored now
+    pub(super) fn new(
+        party_uids: Vec<String>,
+        share_counts: Vec<usize>,
+        index: usize,
+        seed_fingerprint: Vec<u8>,
+    ) -> Self {
+        Self {
+            party_uids,
+            share_counts,
+            index,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            seed_fingerprint,
+        }
+    }
+}
+
+/// Public data of a key that is common to all parties.
+/// Populated by tofn's keygen output; owned by tofnd so that shares can be refreshed after keygen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "GroupInfoBytes", try_from = "GroupInfoBytes")]
+pub(super) struct GroupInfo {
+    threshold: usize,
+    pub_key: BytesVec, // encoded group public key; never changes
+    vss_commitments: Vec<G1Projective>,
+}
+
+impl GroupInfo {
+    pub(super) fn new(
+        threshold: usize,
+        pub_key: BytesVec,
+        vss_commitments: Vec<G1Projective>,
+    ) -> Self {
+        Self {
+            threshold,
+            pub_key,
+            vss_commitments,
+        }
+    }
+
+    pub(super) fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub(super) fn encoded_pubkey(&self) -> BytesVec {
+        self.pub_key.clone()
+    }
+
+    pub(super) fn vss_commitments(&self) -> &[G1Projective] {
+        &self.vss_commitments
+    }
+}
+
+/// serialized form of [GroupInfo]; commitments are compressed G1 points
+#[derive(Serialize, Deserialize)]
+struct GroupInfoBytes {
+    threshold: usize,
+    pub_key: BytesVec,
+    vss_commitments: Vec<BytesVec>,
+}
+
+impl From<GroupInfo> for GroupInfoBytes {
+    fn from(group: GroupInfo) -> Self {
+        Self {
+            threshold: group.threshold,
+            pub_key: group.pub_key,
+            vss_commitments: group
+                .vss_commitments
+                .iter()
+                .map(|commitment| G1Affine::from(commitment).to_compressed().to_vec())
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<GroupInfoBytes> for GroupInfo {
+    type Error = &'static str;
+    fn try_from(bytes: GroupInfoBytes) -> Result<Self, Self::Error> {
+        let vss_commitments = bytes
+            .vss_commitments
+            .iter()
+            .map(|commitment| {
+                let commitment: Option<G1Affine> = commitment
+                    .as_slice()
+                    .try_into()
+                    .ok()
+                    .and_then(|commitment| G1Affine::from_compressed(commitment).into());
+                commitment
+                    .map(G1Projective::from)
+                    .ok_or("invalid vss commitment")
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            threshold: bytes.threshold,
+            pub_key: bytes.pub_key,
+            vss_commitments,
+        })
+    }
+}
+
+/// A secret share of a key. `Debug` shows the index only, so that records can be logged.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "ShareSecretBytes", try_from = "ShareSecretBytes")]
+pub(super) struct ShareSecret {
+    index: usize,
+    x_i: Scalar,
+}
+
+impl fmt::Debug for ShareSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShareSecret")
+            .field("index", &self.index)
+            .field("x_i", &"<redacted>")
+            .finish()
+    }
+}
+
+impl ShareSecret {
+    pub(super) fn new(index: usize, x_i: Scalar) -> Self {
+        Self { index, x_i }
+    }
+
+    pub(super) fn index(&self) -> usize {
+        self.index
+    }
+
+    pub(super) fn x_i(&self) -> &Scalar {
+        &self.x_i
+    }
+}
+
+/// serialized form of [ShareSecret]
+#[derive(Serialize, Deserialize)]
+struct ShareSecretBytes {
+    index: usize,
+    x_i: [u8; 32],
+}
+
+impl From<ShareSecret> for ShareSecretBytes {
+    fn from(share: ShareSecret) -> Self {
+        Self {
+            index: share.index,
+            x_i: share.x_i.to_bytes(),
+        }
+    }
+}
+
+impl TryFrom<ShareSecretBytes> for ShareSecret {
+    type Error = &'static str;
+    fn try_from(bytes: ShareSecretBytes) -> Result<Self, Self::Error> {
+        let x_i: Option<Scalar> = Scalar::from_bytes(&bytes.x_i).into();
+        Ok(Self {
+            index: bytes.index,
+            x_i: x_i.ok_or("invalid secret share")?,
+        })
+    }
 }
 
 /// `KeyShareKv` record
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PartyInfo {
-    pub(super) common: GroupPublicInfo,
-    pub(super) shares: Vec<ShareSecretInfo>,
+    pub(super) common: GroupInfo,
+    pub(super) shares: Vec<ShareSecret>,
     pub(super) tofnd: TofndInfo,
 }
 
@@ -59,22 +233,19 @@ impl PartyInfo {
         uids: Vec<String>,
         share_counts: Vec<usize>,
         tofnd_index: usize,
+        seed_fingerprint: Vec<u8>,
     ) -> Self {
         // grap the first share to acquire common data
-        let common = secret_key_shares[0].group().clone();
+        let common = secret_key_shares[0].group().group_info();
 
         // aggregate share data into a vector
         let shares = secret_key_shares
-            .into_iter()
-            .map(|share| share.share().clone())
+            .iter()
+            .map(|share| share.share().share_secret())
             .collect();
 
         // add tofnd data
-        let tofnd = TofndInfo {
-            party_uids: uids,
-            share_counts,
-            index: tofnd_index,
-        };
+        let tofnd = TofndInfo::new(uids, share_counts, tofnd_index, seed_fingerprint);
 
         PartyInfo {
             common,
@@ -83,5 +254,211 @@ impl PartyInfo {
         }
     }
 
-  
+    /// Encode as a record of the latest version
+    pub(crate) fn encode(self) -> Option<BytesVec> {
+        let record = serialize(&PartyInfoVersion::V1(self)).ok()?;
+        Some([&RECORD_TAG[..], &record].concat())
+    }
+
+    /// Decode a record of any version. Legacy records are migrated to the latest version.
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes.strip_prefix(&RECORD_TAG[..]) {
+            Some(record) => match deserialize(record)? {
+                PartyInfoVersion::V1(party_info) => Some(party_info),
+            },
+            None => decode_legacy::<GroupPublicInfo, ShareSecretInfo>(bytes),
+        }
+    }
+
+    /// Get the uid of the party that holds the share with index `share_index`.
+    /// Shares are assigned to parties in order, according to `share_counts`.
+    pub(super) fn share_owner(&self, share_index: usize) -> Option<&str> {
+        super::vss::share_owner(&self.tofnd.share_counts, share_index)
+            .map(|index| self.tofnd.party_uids[index].as_str())
+    }
+}
+
+/// Tag of versioned `KeyShareKv` records. Legacy records start with the threshold of the key,
+/// which would have to be absurdly large to match it.
+const RECORD_TAG: [u8; 8] = *b"tofndkey";
+
+/// Versions of `KeyShareKv` records; new layouts of [PartyInfo] are added as new versions, and decoding migrates old ones
+#[derive(Serialize, Deserialize)]
+enum PartyInfoVersion {
+    V1(PartyInfo),
+}
+
+/// tofn's group info, as stored by legacy records and output by keygen
+trait IntoGroupInfo {
+    fn group_info(&self) -> GroupInfo;
+}
+
+impl IntoGroupInfo for GroupPublicInfo {
+    fn group_info(&self) -> GroupInfo {
+        GroupInfo::new(
+            self.threshold(),
+            self.encoded_pubkey(),
+            self.vss_commitments().to_vec(),
+        )
+    }
+}
+
+/// tofn's share info, as stored by legacy records and output by keygen
+trait IntoShareSecret {
+    fn share_secret(&self) -> ShareSecret;
+}
+
+impl IntoShareSecret for ShareSecretInfo {
+    fn share_secret(&self) -> ShareSecret {
+        ShareSecret::new(self.index().as_usize(), *self.x_i())
+    }
+}
+
+/// `KeyShareKv` record of releases before versioned records; tofn's group and share info were stored as is
+#[derive(Serialize, Deserialize)]
+struct LegacyPartyInfo<G, S> {
+    common: G,
+    shares: Vec<S>,
+    tofnd: LegacyTofndInfo,
+}
+
+/// [TofndInfo] of legacy records
+#[derive(Serialize, Deserialize)]
+struct LegacyTofndInfo {
+    party_uids: Vec<String>,
+    share_counts: Vec<usize>,
+    index: usize,
+}
+
+/// Decode a legacy record and migrate it. Legacy records don't record when they were stored,
+/// nor which mnemonic was in use, so `created_at` is zero and `seed_fingerprint` is empty.
+fn decode_legacy<G, S>(bytes: &[u8]) -> Option<PartyInfo>
+where
+    G: IntoGroupInfo + serde::de::DeserializeOwned,
+    S: IntoShareSecret + serde::de::DeserializeOwned,
+{
+    let legacy: LegacyPartyInfo<G, S> = deserialize(bytes)?;
+    Some(PartyInfo {
+        common: legacy.common.group_info(),
+        shares: legacy.shares.iter().map(S::share_secret).collect(),
+        tofnd: TofndInfo {
+            party_uids: legacy.tofnd.party_uids,
+            share_counts: legacy.tofnd.share_counts,
+            index: legacy.tofnd.index,
+            created_at: 0,
+            seed_fingerprint: vec![],
+        },
+    })
+}
+
+/// Map optional per-party values to the uids of parties.
+/// `values` must either be empty or alligned with `uids`; `name` describes the values in errors.
+pub(super) fn align_with_uids<T>(
+    uids: &[String],
+    values: Vec<T>,
+    name: &str,
+) -> TofndResult<HashMap<String, T>> {
+    if !values.is_empty() && values.len() != uids.len() {
+        return Err(anyhow!(
+            "uid vector and {} vector not alligned: {} uids, {} {}",
+            name,
+            uids.len(),
+            values.len(),
+            name,
+        ));
+    }
+    Ok(uids.iter().cloned().zip(values).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// stand-in for tofn's group info in legacy records
+    #[derive(Serialize, Deserialize)]
+    struct TestGroup {
+        threshold: usize,
+        pub_key: BytesVec,
+    }
+
+    impl IntoGroupInfo for TestGroup {
+        fn group_info(&self) -> GroupInfo {
+            GroupInfo::new(
+                self.threshold,
+                self.pub_key.clone(),
+                vec![G1Projective::generator()],
+            )
+        }
+    }
+
+    /// stand-in for tofn's share info in legacy records
+    #[derive(Serialize, Deserialize)]
+    struct TestShare {
+        index: usize,
+        x_i: [u8; 32],
+    }
+
+    impl IntoShareSecret for TestShare {
+        fn share_secret(&self) -> ShareSecret {
+            ShareSecret::new(self.index, Scalar::from_bytes(&self.x_i).unwrap())
+        }
+    }
+
+    #[test]
+    fn test_party_info_records() {
+        let party_info = PartyInfo {
+            common: GroupInfo::new(1, vec![1, 2, 3], vec![G1Projective::generator()]),
+            shares: vec![ShareSecret::new(2, Scalar::from(5))],
+            tofnd: TofndInfo::new(vec!["a".to_owned(), "b".to_owned()], vec![1, 2], 1, vec![9]),
+        };
+
+        // versioned records round-trip
+        let bytes = party_info.clone().encode().unwrap();
+        assert!(bytes.starts_with(&RECORD_TAG));
+        let decoded = PartyInfo::decode(&bytes).unwrap();
+        assert_eq!(decoded.common.encoded_pubkey(), vec![1, 2, 3]);
+        assert_eq!(decoded.shares[0].x_i(), &Scalar::from(5));
+        assert_eq!(decoded.tofnd.seed_fingerprint, vec![9]);
+        assert_eq!(decoded.tofnd.created_at, party_info.tofnd.created_at);
+
+        // records of the baseline layout are migrated
+        let legacy = serialize(&LegacyPartyInfo {
+            common: TestGroup {
+                threshold: 1,
+                pub_key: vec![1, 2, 3],
+            },
+            shares: vec![TestShare {
+                index: 2,
+                x_i: Scalar::from(5).to_bytes(),
+            }],
+            tofnd: LegacyTofndInfo {
+                party_uids: vec!["a".to_owned(), "b".to_owned()],
+                share_counts: vec![1, 2],
+                index: 1,
+            },
+        })
+        .unwrap();
+        let migrated = decode_legacy::<TestGroup, TestShare>(&legacy).unwrap();
+        assert_eq!(migrated.common.threshold(), 1);
+        assert_eq!(migrated.common.encoded_pubkey(), vec![1, 2, 3]);
+        assert_eq!(migrated.shares[0].index(), 2);
+        assert_eq!(migrated.shares[0].x_i(), &Scalar::from(5));
+        assert_eq!(migrated.tofnd.party_uids, vec!["a", "b"]);
+        assert_eq!(migrated.tofnd.share_counts, vec![1, 2]);
+        assert_eq!(migrated.tofnd.index, 1);
+        assert_eq!(migrated.tofnd.created_at, 0);
+        assert!(migrated.tofnd.seed_fingerprint.is_empty());
+
+        // the versioned layout can't be read as a legacy record, and corrupted records are rejected
+        assert!(decode_legacy::<TestGroup, TestShare>(&bytes).is_none());
+        assert!(PartyInfo::decode(&bytes[..bytes.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_share_secret_debug() {
+        let share = ShareSecret::new(3, Scalar::from(42));
+        let debug = format!("{:?}", share);
+        assert!(debug.contains("index: 3"));
+        assert!(!debug.contains(&format!("{:?}", Scalar::from(42))));
+    }
 }
@@ -0,0 +1,158 @@
+//! Feldman VSS helpers for the protocols that tofnd runs by itself, i.e. [crate::gg20::refresh] and [crate::gg20::reshare].
+//! A dealer commits to the coefficients `a_0, ..., a_t` of a random polynomial with `[a_0 * g1, ..., a_t * g1]`,
+//! and each share `i` is dealt the evaluation of the polynomial at [bls::share_point], which can be checked against the commitments.
+
+use super::{bls, protocol::Share};
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use group::ff::Field;
+use sha2::{Digest, Sha256};
+
+use std::convert::TryInto;
+
+/// a random polynomial `a_0 + a_1 * x + ... + a_t * x^t` of degree `t` with a chosen constant term `a_0`
+pub(super) struct Polynomial {
+    coeffs: Vec<Scalar>, // a_0, ..., a_t
+}
+
+impl Polynomial {
+    pub(super) fn random(constant: Scalar, degree: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        Self {
+            coeffs: std::iter::once(constant)
+                .chain((0..degree).map(|_| Scalar::random(&mut rng)))
+                .collect(),
+        }
+    }
+
+    /// commitments `a_k * g1` of the coefficients `a_0, ..., a_t`
+    pub(super) fn commitments(&self) -> Vec<G1Projective> {
+        self.coeffs
+            .iter()
+            .map(|coeff| G1Projective::generator() * coeff)
+            .collect()
+    }
+
+    /// evaluate the polynomial at the point of share `share_index`
+    pub(super) fn eval(&self, share_index: usize) -> Scalar {
+        let x = bls::share_point(share_index);
+        // Horner's rule
+        self.coeffs
+            .iter()
+            .rev()
+            .fold(Scalar::zero(), |acc, coeff| acc * x + coeff)
+    }
+
+    /// evaluate the polynomial at the points of the shares `share_indices`
+    pub(super) fn deal(&self, share_indices: &[usize]) -> Vec<Share> {
+        share_indices
+            .iter()
+            .map(|index| Share {
+                scalar: self.eval(*index),
+                index: *index,
+            })
+            .collect()
+    }
+}
+
+/// get the indices of the shares of each party; shares are assigned to parties in order
+pub(super) fn share_indices(share_counts: &[usize]) -> Vec<Vec<usize>> {
+    let mut first_share = 0;
+    share_counts
+        .iter()
+        .map(|count| {
+            let indices = (first_share..first_share + count).collect();
+            first_share += count;
+            indices
+        })
+        .collect()
+}
+
+/// get the index of the party that holds the share `share_index`; shares are assigned to parties in order
+pub(super) fn share_owner(share_counts: &[usize], share_index: usize) -> Option<usize> {
+    let mut first_share = 0;
+    for (party, count) in share_counts.iter().enumerate() {
+        first_share += count;
+        if share_index < first_share {
+            return Some(party);
+        }
+    }
+    None
+}
+
+/// check that a dealt share matches the commitments of its dealer
+pub(super) fn verify_share(commitments: &[G1Projective], share: &Share) -> bool {
+    G1Projective::generator() * share.scalar == bls::share_public_key(commitments, share.index)
+}
+
+/// encode points as compressed G1 points
+pub(super) fn encode_points(points: &[G1Projective]) -> Vec<Vec<u8>> {
+    points
+        .iter()
+        .map(|point| G1Affine::from(point).to_compressed().to_vec())
+        .collect()
+}
+
+/// encode the group public key of a key with VSS commitments `commitments`, i.e. its constant commitment, like tofn does
+pub(super) fn encode_pub_key(commitments: &[G1Projective]) -> Option<Vec<u8>> {
+    commitments
+        .first()
+        .map(|commitment| G1Affine::from(commitment).to_compressed().to_vec())
+}
+
+/// decode compressed G1 points; returns `None` if any point is malformed
+pub(super) fn decode_points(points: &[Vec<u8>]) -> Option<Vec<G1Projective>> {
+    points
+        .iter()
+        .map(|point| {
+            let point: Option<G1Affine> =
+                G1Affine::from_compressed(point.as_slice().try_into().ok()?).into();
+            point.map(G1Projective::from)
+        })
+        .collect()
+}
+
+/// digest of VSS commitments, so that parties can confirm that they agree on them
+pub(super) fn commitments_digest(commitments: &[G1Projective]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for commitment in commitments {
+        hasher.update(G1Affine::from(commitment).to_compressed());
+    }
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_polynomial() {
+        let poly = Polynomial::random(Scalar::from(7), 2);
+        let commitments = poly.commitments();
+        assert_eq!(commitments.len(), 3);
+        assert_eq!(commitments[0], G1Projective::generator() * Scalar::from(7));
+
+        let shares = poly.deal(&[0, 3]);
+        assert!(shares.iter().all(|share| verify_share(&commitments, share)));
+        let mut bad_share = shares[1].clone();
+        bad_share.scalar += Scalar::one();
+        assert!(!verify_share(&commitments, &bad_share));
+
+        // any 3 shares interpolate the constant term
+        let indices = [0, 2, 5];
+        let secret: Scalar = indices
+            .iter()
+            .enumerate()
+            .map(|(i, index)| poly.eval(*index) * bls::lagrange_coefficient(&indices, i).unwrap())
+            .fold(Scalar::zero(), |acc, term| acc + term);
+        assert_eq!(secret, Scalar::from(7));
+
+        assert_eq!(share_indices(&[2, 1]), vec![vec![0, 1], vec![2]]);
+        let owners: Vec<_> = (0..4).map(|i| share_owner(&[2, 1], i)).collect();
+        assert_eq!(owners, vec![Some(0), Some(0), Some(1), None]);
+        assert_eq!(
+            decode_points(&encode_points(&commitments)),
+            Some(commitments)
+        );
+        assert_eq!(decode_points(&[vec![0; 48]]), None);
+    }
+}
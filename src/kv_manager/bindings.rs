@@ -0,0 +1,300 @@
+//! Bindings of kvstore operations to [Storage] operations. Errors are mapped to [super::error::InnerKvError].
+//! Reservations are implemented here, so that they behave the same for every [Storage] backend.
+
+use serde::{de::DeserializeOwned, Serialize};
+use tofn::sdk::api::{deserialize, serialize};
+
+use super::error::{InnerKvError::*, InnerKvResult};
+use super::storage::Storage;
+use super::types::{KeyReservation, DEFAULT_RESERVE};
+
+use crate::encrypted_sled::Password;
+
+/// Reserves a key. New key's value is [DEFAULT_RESERVE].
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_reserve(kv: &dyn Storage, key: String) -> InnerKvResult<KeyReservation> {
+    // search key in kv store.
+    // If reserve key already exists inside our database, return an error
+    if kv.contains_key(&key)? {
+        return Err(LogicalErr(format!(
+            "kv_manager key <{}> already reserved.",
+            key
+        )));
+    }
+
+    // try to insert the new key with default value
+    kv.insert(&key, DEFAULT_RESERVE.as_bytes().to_vec())?;
+
+    // return key reservation
+    Ok(KeyReservation { key })
+}
+
+/// Deletes an unreserved key if it exists.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_delete(kv: &dyn Storage, key: String) -> InnerKvResult<()> {
+    if !kv.contains_key(&key)? {
+        return Ok(());
+    }
+
+    // check if key holds the default reserve value. If yes, can't delete it.
+    if kv.get(&key)?.as_deref().map_or(false, is_reserved) {
+        return Err(LogicalErr(format!(
+            "can't delete reserved key <{}> in kv store.",
+            key
+        )));
+    }
+
+    kv.remove(&key)?;
+
+    Ok(())
+}
+
+/// Inserts a value to an existing key.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_put<V>(
+    kv: &dyn Storage,
+    reservation: KeyReservation,
+    value: V,
+) -> InnerKvResult<()>
+where
+    V: Serialize,
+{
+    // check if key holds the default reserve value. If not, send an error.
+    if !kv
+        .get(&reservation.key)?
+        .as_deref()
+        .map_or(false, is_reserved)
+    {
+        return Err(LogicalErr(format!(
+            "did not find reservation for key <{}> in kv store.",
+            reservation.key
+        )));
+    }
+
+    // convert value into bytes
+    let bytes = serialize(&value).map_err(|_| SerializationErr)?;
+
+    // insert new value
+    kv.insert(&reservation.key, bytes)?;
+
+    Ok(())
+}
+
+/// Inserts values to existing keys, atomically; either all values are inserted or none of them is.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_put_all<V>(
+    kv: &dyn Storage,
+    values: Vec<(KeyReservation, V)>,
+) -> InnerKvResult<()>
+where
+    V: Serialize,
+{
+    let mut inserts = Vec::with_capacity(values.len());
+    for (reservation, value) in values {
+        // check if key holds the default reserve value. If not, send an error.
+        if !kv
+            .get(&reservation.key)?
+            .as_deref()
+            .map_or(false, is_reserved)
+        {
+            return Err(LogicalErr(format!(
+                "did not find reservation for key <{}> in kv store.",
+                reservation.key
+            )));
+        }
+
+        // convert value into bytes
+        let bytes = serialize(&value).map_err(|_| SerializationErr)?;
+        inserts.push((reservation.key, bytes));
+    }
+
+    kv.apply_batch(inserts, vec![])?;
+
+    Ok(())
+}
+
+/// Releases the reservations of `keys` that are still held, e.g. after [handle_put_all] failed.
+/// Keys that hold other values are left as is.
+pub(super) fn handle_release(kv: &dyn Storage, keys: Vec<String>) {
+    for key in keys {
+        if kv
+            .get(&key)
+            .ok()
+            .flatten()
+            .as_deref()
+            .map_or(false, is_reserved)
+        {
+            let _ = kv.remove(&key);
+        }
+    }
+}
+
+/// Get the value of an existing key.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_get<V>(kv: &dyn Storage, key: String) -> InnerKvResult<V>
+where
+    V: DeserializeOwned,
+{
+    // try to get value of 'key'
+    let value = match kv.get(&key)? {
+        Some(bytes) => deserialize(&bytes).ok_or(DeserializationErr)?,
+        None => {
+            return Err(LogicalErr(format!("key <{}> does not have a value.", key)));
+        }
+    };
+
+    // return value
+    Ok(value)
+}
+
+/// Checks if a key exists in the kvstore.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_exists(kv: &dyn Storage, key: &str) -> InnerKvResult<bool> {
+    kv.contains_key(key).map_err(|err| {
+        LogicalErr(format!(
+            "Could not perform 'contains_key' for key <{}> due to error: {}",
+            key, err
+        ))
+    })
+}
+
+/// Get all keys that start with `prefix`, in lexicographic order. Reserved keys are skipped.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_keys(kv: &dyn Storage, prefix: &str) -> InnerKvResult<Vec<String>> {
+    let mut keys = Vec::new();
+    for (key, value) in kv.scan_prefix(prefix)? {
+        // skip keys that hold the default reserve value
+        if is_reserved(&value) {
+            continue;
+        }
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// Moves the value of an unreserved key to a new key that does not exist.
+/// Both changes are applied atomically and flushed to disk.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_rename(kv: &dyn Storage, from: String, to: String) -> InnerKvResult<()> {
+    let value = match kv.get(&from)? {
+        Some(value) => value,
+        None => return Err(LogicalErr(format!("key <{}> does not have a value.", from))),
+    };
+
+    // check if key holds the default reserve value. If yes, can't move it.
+    if is_reserved(&value) {
+        return Err(LogicalErr(format!(
+            "can't move reserved key <{}> in kv store.",
+            from
+        )));
+    }
+
+    // don't overwrite existing values or reservations
+    if kv.contains_key(&to)? {
+        return Err(LogicalErr(format!(
+            "can't move key <{}> to existing key <{}> in kv store.",
+            from, to
+        )));
+    }
+
+    kv.apply_batch(vec![(to, value)], vec![from])?;
+    kv.flush()?;
+
+    Ok(())
+}
+
+/// Replaces the value of an unreserved key and moves its previous value to `archive_key`, which must not exist.
+/// Both changes are applied atomically and flushed to disk.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_replace<V>(
+    kv: &dyn Storage,
+    key: String,
+    archive_key: String,
+    value: V,
+) -> InnerKvResult<()>
+where
+    V: Serialize,
+{
+    let previous = get_unreserved(kv, &key)?;
+
+    // don't overwrite existing values or reservations
+    if kv.contains_key(&archive_key)? {
+        return Err(LogicalErr(format!(
+            "can't archive key <{}> to existing key <{}> in kv store.",
+            key, archive_key
+        )));
+    }
+
+    // convert value into bytes
+    let bytes = serialize(&value).map_err(|_| SerializationErr)?;
+
+    kv.apply_batch(vec![(archive_key, previous), (key, bytes)], vec![])?;
+    kv.flush()?;
+
+    Ok(())
+}
+
+/// Moves the value of `archive_key` back to `key`, overwriting the value of `key`.
+/// Undoes [handle_replace]. Both changes are applied atomically and flushed to disk.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_revert(
+    kv: &dyn Storage,
+    key: String,
+    archive_key: String,
+) -> InnerKvResult<()> {
+    // only unreserved values can be overwritten
+    get_unreserved(kv, &key)?;
+    let archived = get_unreserved(kv, &archive_key)?;
+
+    kv.apply_batch(vec![(key, archived)], vec![archive_key])?;
+    kv.flush()?;
+
+    Ok(())
+}
+
+/// Get the value of a key that exists and is not reserved.
+fn get_unreserved(kv: &dyn Storage, key: &str) -> InnerKvResult<Vec<u8>> {
+    let value = match kv.get(key)? {
+        Some(value) => value,
+        None => return Err(LogicalErr(format!("key <{}> does not have a value.", key))),
+    };
+    if is_reserved(&value) {
+        return Err(LogicalErr(format!(
+            "key <{}> is reserved in kv store.",
+            key
+        )));
+    }
+    Ok(value)
+}
+
+/// Whether `value` is the default reserve value of reserved keys.
+fn is_reserved(value: &[u8]) -> bool {
+    value == DEFAULT_RESERVE.as_bytes()
+}
+
+/// Deletes an unreserved key if it exists, and rewrites the kv store so that its value is erased from disk.
+/// Returns the [Storage] error or [LogicalErr] on failure.
+pub(super) fn handle_purge(kv: &mut dyn Storage, key: String) -> InnerKvResult<()> {
+    if !kv.contains_key(&key)? {
+        return Ok(());
+    }
+
+    // reserved keys can't be deleted
+    if kv.get(&key)?.as_deref().map_or(false, is_reserved) {
+        return Err(LogicalErr(format!(
+            "can't purge reserved key <{}> in kv store.",
+            key
+        )));
+    }
+
+    kv.purge(&key)
+}
+
+/// Re-encrypts the kv store under `password`.
+/// Returns the [Storage] error on failure.
+pub(super) fn handle_change_password(
+    kv: &mut dyn Storage,
+    password: Password,
+) -> InnerKvResult<()> {
+    kv.change_password(password)
+}
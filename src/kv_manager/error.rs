@@ -15,7 +15,7 @@ use crate::encrypted_sled;
 #[derive(thiserror::Error, Debug)]
 pub enum KvError {
     #[error("Kv initialization Error: {0}")]
-    InitErr(#[from] encrypted_sled::Error),
+    InitErr(InnerKvError),
     #[error("Recv Error: {0}")] // errors receiving from "actor pattern"'s channels
     RecvErr(#[from] tokio::sync::oneshot::error::RecvError),
     #[error("Send Error: {0}")] // errors sending to "actor pattern"'s channels
@@ -30,6 +30,18 @@ pub enum KvError {
     DeleteErr(InnerKvError),
     #[error("Exits Error: {0}")]
     ExistsErr(InnerKvError),
+    #[error("Keys Error: {0}")]
+    KeysErr(InnerKvError),
+    #[error("Rename Error: {0}")]
+    RenameErr(InnerKvError),
+    #[error("Purge Error: {0}")]
+    PurgeErr(InnerKvError),
+    #[error("Replace Error: {0}")]
+    ReplaceErr(InnerKvError),
+    #[error("Revert Error: {0}")]
+    RevertErr(InnerKvError),
+    #[error("Change Password Error: {0}")]
+    ChangePasswordErr(InnerKvError),
 }
 pub type KvResult<Success> = Result<Success, KvError>;
 
@@ -38,6 +50,8 @@ pub type KvResult<Success> = Result<Success, KvError>;
 pub enum InnerKvError {
     #[error("Sled Error: {0}")] // Delegate Sled's errors
     SledErr(#[from] encrypted_sled::Error),
+    #[error("Log Error: {0}")] // Delegate errors of the append-only log storage
+    LogErr(#[from] LogError),
     #[error("Logical Error: {0}")] // Logical errors (eg double deletion)
     LogicalErr(String),
     #[error("Serialization Error: failed to serialize value")]
@@ -46,3 +60,16 @@ pub enum InnerKvError {
     DeserializationErr,
 }
 pub(super) type InnerKvResult<Success> = Result<Success, InnerKvError>;
+
+/// Errors of the append-only log storage; see [super::storage]
+#[derive(thiserror::Error, Debug)]
+pub enum LogError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Encryption error: {0}")]
+    Encryption(#[from] encrypted_sled::Error),
+    #[error("file is not a tofnd kv log")]
+    MalformedHeader,
+    #[error("corrupted entry at offset {0}")]
+    CorruptedEntry(u64),
+}
@@ -1,18 +1,26 @@
 //! Public API for kvstore operations
 //! Errors are mapped to [super::error::KvError]
 
-use crate::encrypted_sled::{self, Password};
+use crate::encrypted_sled::Password;
 
 use super::{
+    bindings::{
+        handle_change_password, handle_delete, handle_exists, handle_get, handle_keys,
+        handle_purge, handle_put, handle_put_all, handle_release, handle_rename, handle_replace,
+        handle_reserve, handle_revert,
+    },
     error::{KvError::*, KvResult},
-    sled_bindings::{handle_delete, handle_exists, handle_get, handle_put, handle_reserve},
+    storage::{Storage, StorageBackend},
     types::{
         Command::{self, *},
         KeyReservation, DEFAULT_KV_NAME, DEFAULT_KV_PATH,
     },
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::{fmt::Debug, path::PathBuf};
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
 use tokio::sync::{mpsc, oneshot};
 
 // logging
@@ -29,24 +37,30 @@ impl<V: 'static> Kv<V>
 where
     V: Debug + Send + Sync + Serialize + DeserializeOwned,
 {
-    /// Creates a new kv service. Returns [InitErr] on failure.
+    /// Creates a new kv service on `storage`. Returns [InitErr] on failure.
     /// the path of the kvstore is `root_path` + "/kvstore/" + `kv_name`
-    pub fn new(root_path: PathBuf, password: Password) -> KvResult<Self> {
+    pub fn new(root_path: PathBuf, password: Password, storage: StorageBackend) -> KvResult<Self> {
         let kv_path = root_path.join(DEFAULT_KV_PATH).join(DEFAULT_KV_NAME);
         // use to_string_lossy() instead of to_str() to avoid handling Option<&str>
         let kv_path = kv_path.to_string_lossy().to_string();
-        Self::with_db_name(kv_path, password)
+        Self::with_db_name(kv_path, password, storage)
     }
 
-    /// Creates a kvstore at `full_db_name` and spawns a new kv_manager. Returns [InitErr] on failure.
+    /// Creates a kvstore at `full_db_name` on `storage` and spawns a new kv_manager. Returns [InitErr] on failure.
     /// `full_db_name` is the name of the path of the kvstrore + its name
     /// Example: ~/tofnd/kvstore/database_1
-    pub fn with_db_name(full_db_name: String, password: Password) -> KvResult<Self> {
+    pub fn with_db_name(
+        full_db_name: String,
+        password: Password,
+        storage: StorageBackend,
+    ) -> KvResult<Self> {
         let (sender, rx) = mpsc::unbounded_channel();
 
-        // get kv store from db name before entering the kv_cmd_handler because
+        // open storage from db name before entering the kv_cmd_handler because
         // it's more convenient to return an error from outside of a tokio::span
-        let kv = get_kv_store(&full_db_name, password)?;
+        let kv = storage
+            .open(Path::new(&full_db_name), password)
+            .map_err(InitErr)?;
 
         tokio::spawn(kv_cmd_handler(rx, kv));
         Ok(Self { sender })
@@ -81,6 +95,19 @@ where
         resp_rx.await?.map_err(PutErr)
     }
 
+    /// Puts new values given their [super::types::KeyReservation]s, atomically
+    /// Returns [PutErr] or [SendErr] on failure. The reservations are consumed, so they are released on [PutErr].
+    pub async fn put_all(&self, values: Vec<(KeyReservation, V)>) -> KvResult<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sender
+            .send(PutAll {
+                values,
+                resp: resp_tx,
+            })
+            .map_err(|e| SendErr(e.to_string()))?;
+        resp_rx.await?.map_err(PutErr)
+    }
+
     /// Gets a value given a key
     /// Returns [GetErr] or [SendErr] on failure.
     pub async fn get(&self, key: &str) -> KvResult<V> {
@@ -119,40 +146,94 @@ where
             .map_err(|e| SendErr(e.to_string()))?;
         resp_rx.await?.map_err(ExistsErr)
     }
-}
 
-/// Returns the db with name `db_name`, or creates a new if such DB does not exist
-/// Returns [sled::Error] on failure.
-/// Default path DB path is the executable's directory; The caller can specify a
-/// full path followed by the name of the DB
-/// Usage:
-///  let my_db = get_kv_store(&"my_current_dir_db")?;
-///  let my_db = get_kv_store(&"/tmp/my_tmp_bd")?;
-pub fn get_kv_store(
-    db_name: &str,
-    password: Password,
-) -> encrypted_sled::Result<encrypted_sled::Db> {
-    // create/open DB
-    info!("START: decrypt kvstore");
-    let kv = encrypted_sled::Db::open(db_name, password)?;
-    info!("DONE: decrypt kvstore");
-
-    // log whether the DB was newly created or not
-    if kv.was_recovered() {
-        info!("kv_manager found existing db [{}]", db_name);
-    } else {
-        info!(
-            "kv_manager cannot open existing db [{}]. creating new db",
-            db_name
-        );
-    }
-    Ok(kv)
+    /// Gets all unreserved keys that start with `prefix`, in lexicographic order
+    /// Returns [KeysErr] or [SendErr] on failure.
+    pub async fn keys(&self, prefix: &str) -> KvResult<Vec<String>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sender
+            .send(Keys {
+                prefix: prefix.to_string(),
+                resp: resp_tx,
+            })
+            .map_err(|e| SendErr(e.to_string()))?;
+        resp_rx.await?.map_err(KeysErr)
+    }
+
+    /// Moves the value of an unreserved key to a new key
+    /// Returns [RenameErr] or [SendErr] on failure.
+    pub async fn rename(&self, from: &str, to: &str) -> KvResult<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sender
+            .send(Rename {
+                from: from.to_string(),
+                to: to.to_string(),
+                resp: resp_tx,
+            })
+            .map_err(|e| SendErr(e.to_string()))?;
+        resp_rx.await?.map_err(RenameErr)
+    }
+
+    /// Deletes an unreserved key and rewrites the kvstore, so that its value is erased from disk
+    /// Returns [PurgeErr] or [SendErr] on failure.
+    pub async fn purge(&self, key: &str) -> KvResult<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sender
+            .send(Purge {
+                key: key.to_string(),
+                resp: resp_tx,
+            })
+            .map_err(|e| SendErr(e.to_string()))?;
+        resp_rx.await?.map_err(PurgeErr)
+    }
+
+    /// Replaces the value of an unreserved key and moves its previous value to `archive_key`, atomically
+    /// Returns [ReplaceErr] or [SendErr] on failure.
+    pub async fn replace(&self, key: &str, archive_key: &str, value: V) -> KvResult<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sender
+            .send(Replace {
+                key: key.to_string(),
+                archive_key: archive_key.to_string(),
+                value,
+                resp: resp_tx,
+            })
+            .map_err(|e| SendErr(e.to_string()))?;
+        resp_rx.await?.map_err(ReplaceErr)
+    }
+
+    /// Moves the value of `archive_key` back to `key`, atomically; undoes [Self::replace]
+    /// Returns [RevertErr] or [SendErr] on failure.
+    pub async fn revert(&self, key: &str, archive_key: &str) -> KvResult<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sender
+            .send(Revert {
+                key: key.to_string(),
+                archive_key: archive_key.to_string(),
+                resp: resp_tx,
+            })
+            .map_err(|e| SendErr(e.to_string()))?;
+        resp_rx.await?.map_err(RevertErr)
+    }
+
+    /// Re-encrypts the kvstore under `password`, atomically
+    /// Returns [ChangePasswordErr] or [SendErr] on failure.
+    pub async fn change_password(&self, password: Password) -> KvResult<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sender
+            .send(ChangePassword {
+                password,
+                resp: resp_tx,
+            })
+            .map_err(|e| SendErr(e.to_string()))?;
+        resp_rx.await?.map_err(ChangePasswordErr)
+    }
 }
 
 // private handler function to process commands as per the "actor" pattern (see above)
 async fn kv_cmd_handler<V: 'static>(
     mut rx: mpsc::UnboundedReceiver<Command<V>>,
-    kv: encrypted_sled::Db,
+    mut kv: Box<dyn Storage>,
 ) where
     V: Serialize + DeserializeOwned,
 {
@@ -163,7 +244,7 @@ async fn kv_cmd_handler<V: 'static>(
         // TODO refactor repeated code
         match cmd {
             ReserveKey { key, resp } => {
-                if resp.send(handle_reserve(&kv, key)).is_err() {
+                if resp.send(handle_reserve(kv.as_ref(), key)).is_err() {
                     warn!("receiver dropped");
                 }
             }
@@ -175,22 +256,86 @@ async fn kv_cmd_handler<V: 'static>(
                 value,
                 resp,
             } => {
-                if resp.send(handle_put(&kv, reservation, value)).is_err() {
+                if resp
+                    .send(handle_put(kv.as_ref(), reservation, value))
+                    .is_err()
+                {
+                    warn!("receiver dropped");
+                }
+            }
+            PutAll { values, resp } => {
+                let keys = values
+                    .iter()
+                    .map(|(reservation, _)| reservation.key.clone())
+                    .collect();
+                let res = handle_put_all(kv.as_ref(), values);
+                if res.is_err() {
+                    handle_release(kv.as_ref(), keys);
+                }
+                if resp.send(res).is_err() {
                     warn!("receiver dropped");
                 }
             }
             Get { key, resp } => {
-                if resp.send(handle_get(&kv, key)).is_err() {
+                if resp.send(handle_get(kv.as_ref(), key)).is_err() {
                     warn!("receiver dropped");
                 }
             }
             Exists { key, resp } => {
-                if resp.send(handle_exists(&kv, &key)).is_err() {
+                if resp.send(handle_exists(kv.as_ref(), &key)).is_err() {
                     warn!("receiver dropped");
                 }
             }
             Delete { key, resp } => {
-                if resp.send(handle_delete(&kv, key)).is_err() {
+                if resp.send(handle_delete(kv.as_ref(), key)).is_err() {
+                    warn!("receiver dropped");
+                }
+            }
+            Keys { prefix, resp } => {
+                if resp.send(handle_keys(kv.as_ref(), &prefix)).is_err() {
+                    warn!("receiver dropped");
+                }
+            }
+            Rename { from, to, resp } => {
+                if resp.send(handle_rename(kv.as_ref(), from, to)).is_err() {
+                    warn!("receiver dropped");
+                }
+            }
+            Purge { key, resp } => {
+                if resp.send(handle_purge(kv.as_mut(), key)).is_err() {
+                    warn!("receiver dropped");
+                }
+            }
+            Replace {
+                key,
+                archive_key,
+                value,
+                resp,
+            } => {
+                if resp
+                    .send(handle_replace(kv.as_ref(), key, archive_key, value))
+                    .is_err()
+                {
+                    warn!("receiver dropped");
+                }
+            }
+            Revert {
+                key,
+                archive_key,
+                resp,
+            } => {
+                if resp
+                    .send(handle_revert(kv.as_ref(), key, archive_key))
+                    .is_err()
+                {
+                    warn!("receiver dropped");
+                }
+            }
+            ChangePassword { password, resp } => {
+                if resp
+                    .send(handle_change_password(kv.as_mut(), password))
+                    .is_err()
+                {
                     warn!("receiver dropped");
                 }
             }
@@ -0,0 +1,456 @@
+//! [Storage] of [StorageBackend::Log](super::StorageBackend::Log): a single file with an append-only log of encrypted changes.
+//!
+//! The file starts with a header <[MAGIC], password salt>, followed by length-prefixed entries.
+//! Every entry is an [EncryptedRecord](crate::encrypted_sled::RecordCipher) under a key that is derived from the password.
+//! The first entry holds [VERIFICATION_VALUE], which is used to verify the password. Every other entry holds the changes
+//! of a single call, so that the changes of a call are applied atomically.
+//!
+//! On open, all entries are decrypted and replayed into a map in memory, which serves all reads.
+//! Entries are synced to disk before they are applied to the map. If tofnd crashed while appending an entry,
+//! the incomplete entry is dropped on the next open.
+//!
+//! Changes that remove or overwrite values are appended like any other change, so that every write is a single append.
+//! The log is compacted, i.e. the map is written to a new log that atomically replaces the old one, so that removed values
+//! can't be recovered from the file: on open if the log holds obsolete changes, and on append once more than
+//! [COMPACTION_THRESHOLD] changes, and more than half of the changes of the log, are obsolete.
+//!
+//! New logs are written the same way, so that a crash never leaves a partial header behind.
+//! On a password change, the log is compacted under a new password salt. A crash before the replacement leaves
+//! the old log, and the old password, in effect.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use rand::RngCore;
+use tofn::sdk::api::{deserialize, serialize};
+
+use super::Storage;
+use crate::encrypted_sled::{self, Password, RecordCipher};
+use crate::kv_manager::error::{
+    InnerKvError::SerializationErr,
+    InnerKvResult, LogError,
+    LogError::{CorruptedEntry, MalformedHeader},
+};
+
+// logging
+use tracing::{info, warn};
+
+/// identifies tofnd kv logs
+const MAGIC: &[u8] = b"TOFNDLOG";
+
+/// extension of the new log that is written before it replaces the log
+const NEW_LOG_EXTENSION: &str = "new";
+
+/// number of obsolete changes above which the log is compacted on append
+const COMPACTION_THRESHOLD: usize = 1024;
+
+/// size of the password salt
+const SALT_SIZE: usize = 32;
+
+/// size of the length prefix of entries
+const LENGTH_SIZE: usize = 4;
+
+/// value of the first entry; used to verify the password
+const VERIFICATION_VALUE: &[u8] = b"verification_value";
+
+/// changes of an entry; values are inserted if they are `Some` and deleted otherwise
+type Changes = Vec<(String, Option<Vec<u8>>)>;
+
+/// A map of keys to values that is persisted in an append-only log.
+/// The kvstore owns its storage, so [RefCell]s are enough to append to the log from `&self`.
+pub(super) struct LogStore {
+    path: PathBuf,
+    file: RefCell<File>,
+    map: RefCell<BTreeMap<String, Vec<u8>>>,
+    cipher: RecordCipher,
+    salt: [u8; SALT_SIZE],
+    changes: Cell<usize>, // number of changes in the log; the ones that don't hold a value of the map are obsolete
+}
+
+impl LogStore {
+    /// Open the log at `path`, or create a new one if it does not exist.
+    pub(super) fn open(path: &Path, password: Password) -> Result<Self, LogError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // a log is only ever created with its header, so an empty file is not a log yet
+        let mut bytes = Vec::new();
+        let file = match OpenOptions::new().read(true).append(true).open(path) {
+            Ok(mut file) => {
+                file.read_to_end(&mut bytes)?;
+                Some(file)
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let mut file = match file {
+            Some(file) if !bytes.is_empty() => file,
+            _ => {
+                info!(
+                    "kv_manager cannot open existing log [{}]. creating new log",
+                    path.display()
+                );
+                return Self::create(path, password);
+            }
+        };
+        info!("kv_manager found existing log [{}]", path.display());
+
+        // read header
+        if bytes.len() < MAGIC.len() + SALT_SIZE || !bytes.starts_with(MAGIC) {
+            return Err(MalformedHeader);
+        }
+        let salt: [u8; SALT_SIZE] = bytes[MAGIC.len()..MAGIC.len() + SALT_SIZE]
+            .try_into()
+            .map_err(|_| MalformedHeader)?;
+
+        info!("START: decrypt kvstore");
+        let cipher = RecordCipher::new(password, salt.into())?;
+        let mut offset = MAGIC.len() + SALT_SIZE;
+
+        // verify that the password is correct
+        let (verification, next) = next_entry(&bytes, offset).ok_or(MalformedHeader)?;
+        if cipher.decrypt(verification).ok().as_deref() != Some(VERIFICATION_VALUE) {
+            return Err(encrypted_sled::Error::WrongPassword.into());
+        }
+        offset = next;
+
+        // replay all changes
+        let mut map = BTreeMap::new();
+        let mut change_count = 0;
+        while let Some((entry, next)) = next_entry(&bytes, offset) {
+            let changes: Changes = cipher
+                .decrypt(entry)
+                .ok()
+                .and_then(|entry| deserialize(&entry))
+                .ok_or(CorruptedEntry(offset as u64))?;
+            change_count += changes.len();
+            apply(&mut map, changes);
+            offset = next;
+        }
+        info!("DONE: decrypt kvstore");
+
+        // drop an entry whose append was interrupted
+        if offset < bytes.len() {
+            warn!(
+                "dropping incomplete entry at offset {} of log [{}]",
+                offset,
+                path.display()
+            );
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+        }
+
+        let store = Self {
+            path: path.to_owned(),
+            file: RefCell::new(file),
+            map: RefCell::new(map),
+            cipher,
+            salt,
+            changes: Cell::new(change_count),
+        };
+
+        // drop obsolete changes; the log remains valid if this fails
+        if store.obsolete_changes() > 0 {
+            info!("compacting log [{}]", path.display());
+            if let Err(err) = store.compact() {
+                warn!("unable to compact log [{}]: {}", path.display(), err);
+            }
+        }
+        Ok(store)
+    }
+
+    /// Create a new log at `path` that only holds the header and the verification value
+    fn create(path: &Path, password: Password) -> Result<Self, LogError> {
+        let salt = new_salt();
+        let cipher = RecordCipher::new(password, salt.into())?;
+        let file = replace(path, &header(&cipher, &salt)?)?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            file: RefCell::new(file),
+            map: RefCell::new(BTreeMap::new()),
+            cipher,
+            salt,
+            changes: Cell::new(0),
+        })
+    }
+
+    /// number of changes in the log that don't hold a value of the map
+    fn obsolete_changes(&self) -> usize {
+        self.changes.get() - self.map.borrow().len()
+    }
+
+    /// Replace the log with a new log that only holds the values of the map.
+    fn compact(&self) -> InnerKvResult<()> {
+        let file = self.write_log(&self.map.borrow(), &self.cipher, &self.salt)?;
+        *self.file.borrow_mut() = file;
+        self.changes.set(self.map.borrow().len());
+        Ok(())
+    }
+
+    /// Write a new log with the values of `map`, encrypted by `cipher` under `salt`, and atomically replace the log with it.
+    /// Returns the file of the new log; it is positioned at its end, so that it can be appended.
+    fn write_log(
+        &self,
+        map: &BTreeMap<String, Vec<u8>>,
+        cipher: &RecordCipher,
+        salt: &[u8; SALT_SIZE],
+    ) -> InnerKvResult<File> {
+        let mut bytes = header(cipher, salt)?;
+        if !map.is_empty() {
+            let values: Changes = map
+                .iter()
+                .map(|(key, value)| (key.clone(), Some(value.clone())))
+                .collect();
+            let values = serialize(&values).map_err(|_| SerializationErr)?;
+            bytes.extend_from_slice(&frame(&cipher.encrypt(&values).map_err(LogError::from)?));
+        }
+        Ok(replace(&self.path, &bytes)?)
+    }
+
+    /// Append `changes` to the log and apply them to the map.
+    /// The log is compacted if too many of its changes are obsolete.
+    fn append(&self, changes: Changes) -> InnerKvResult<()> {
+        let bytes = serialize(&changes).map_err(|_| SerializationErr)?;
+        let entry = frame(&self.cipher.encrypt(&bytes).map_err(LogError::from)?);
+
+        {
+            let mut file = self.file.borrow_mut();
+            file.write_all(&entry).map_err(LogError::from)?;
+            file.sync_data().map_err(LogError::from)?;
+        }
+
+        self.changes.set(self.changes.get() + changes.len());
+        apply(&mut self.map.borrow_mut(), changes);
+
+        // the changes are persisted; the log remains valid if the compaction fails
+        let obsolete = self.obsolete_changes();
+        if obsolete > COMPACTION_THRESHOLD && obsolete > self.map.borrow().len() {
+            if let Err(err) = self.compact() {
+                warn!("unable to compact log [{}]: {}", self.path.display(), err);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write `bytes` to a new file and atomically replace the file at `path` with it.
+/// Returns the new file; it is positioned at its end, so that it can be appended.
+fn replace(path: &Path, bytes: &[u8]) -> Result<File, LogError> {
+    // discard any leftover of an interrupted replacement
+    let new_path = path.with_extension(NEW_LOG_EXTENSION);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&new_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+
+    // atomically replace the old file and persist the rename
+    fs::rename(&new_path, path)?;
+    if let Some(parent) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(parent).and_then(|dir| dir.sync_all())?;
+    }
+    Ok(file)
+}
+
+fn new_salt() -> [u8; SALT_SIZE] {
+    let mut salt = [0u8; SALT_SIZE];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt
+}
+
+/// header of a log: <[MAGIC], salt, verification value>
+fn header(cipher: &RecordCipher, salt: &[u8; SALT_SIZE]) -> Result<Vec<u8>, LogError> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(salt);
+    header.extend_from_slice(&frame(&cipher.encrypt(VERIFICATION_VALUE)?));
+    Ok(header)
+}
+
+/// prefix `entry` with its length
+fn frame(entry: &[u8]) -> Vec<u8> {
+    let mut bytes = (entry.len() as u32).to_be_bytes().to_vec();
+    bytes.extend_from_slice(entry);
+    bytes
+}
+
+/// Get the entry at `offset` of the log `bytes` and the offset of the next entry.
+/// Returns `None` if there is no complete entry at `offset`.
+fn next_entry(bytes: &[u8], offset: usize) -> Option<(&[u8], usize)> {
+    let start = offset.checked_add(LENGTH_SIZE)?;
+    let length = u32::from_be_bytes(bytes.get(offset..start)?.try_into().ok()?) as usize;
+    let end = start.checked_add(length)?;
+    Some((bytes.get(start..end)?, end))
+}
+
+fn apply(map: &mut BTreeMap<String, Vec<u8>>, changes: Changes) {
+    for (key, value) in changes {
+        match value {
+            Some(value) => map.insert(key, value),
+            None => map.remove(&key),
+        };
+    }
+}
+
+impl Storage for LogStore {
+    fn get(&self, key: &str) -> InnerKvResult<Option<Vec<u8>>> {
+        Ok(self.map.borrow().get(key).cloned())
+    }
+
+    fn contains_key(&self, key: &str) -> InnerKvResult<bool> {
+        Ok(self.map.borrow().contains_key(key))
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> InnerKvResult<()> {
+        self.append(vec![(key.to_owned(), Some(value))])
+    }
+
+    fn remove(&self, key: &str) -> InnerKvResult<()> {
+        if !self.map.borrow().contains_key(key) {
+            return Ok(());
+        }
+        self.append(vec![(key.to_owned(), None)])
+    }
+
+    fn apply_batch(
+        &self,
+        inserts: Vec<(String, Vec<u8>)>,
+        removes: Vec<String>,
+    ) -> InnerKvResult<()> {
+        let changes = inserts
+            .into_iter()
+            .map(|(key, value)| (key, Some(value)))
+            .chain(removes.into_iter().map(|key| (key, None)))
+            .collect();
+        self.append(changes)
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> InnerKvResult<Vec<(String, Vec<u8>)>> {
+        Ok(self
+            .map
+            .borrow()
+            .range(prefix.to_owned()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn flush(&self) -> InnerKvResult<()> {
+        // entries are synced when they are appended
+        Ok(())
+    }
+
+    fn purge(&mut self, key: &str) -> InnerKvResult<()> {
+        self.remove(key)?;
+        self.compact()
+    }
+
+    fn change_password(&mut self, password: Password) -> InnerKvResult<()> {
+        let salt = new_salt();
+        let cipher = RecordCipher::new(password, salt.into()).map_err(LogError::from)?;
+        let file = self.write_log(&self.map.borrow(), &cipher, &salt)?;
+        self.changes.set(self.map.borrow().len());
+
+        info!("re-encrypted log [{}]", self.path.display());
+        *self.file.get_mut() = file;
+        self.cipher = cipher;
+        self.salt = salt;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypted_sled::get_test_password;
+    use testdir::testdir;
+
+    /// decrypted entries of the log of `store`, including the verification value
+    fn entries(store: &LogStore) -> Vec<Vec<u8>> {
+        let bytes = fs::read(&store.path).unwrap();
+        let mut offset = MAGIC.len() + SALT_SIZE;
+        let mut entries = Vec::new();
+        while let Some((entry, next)) = next_entry(&bytes, offset) {
+            entries.push(store.cipher.decrypt(entry).unwrap());
+            offset = next;
+        }
+        entries
+    }
+
+    fn in_log(store: &LogStore, value: &[u8]) -> bool {
+        entries(store)
+            .iter()
+            .any(|entry| entry.windows(value.len()).any(|window| window == value))
+    }
+
+    #[test]
+    fn test_compaction() {
+        let path = testdir!().join("kv.log");
+        let store = LogStore::open(&path, get_test_password()).unwrap();
+        store.insert("a", b"secret a".to_vec()).unwrap();
+        store.insert("b", b"secret b".to_vec()).unwrap();
+        assert_eq!(entries(&store).len(), 3);
+
+        // removes and overwrites are appended
+        store.remove("a").unwrap();
+        store.insert("b", b"new b".to_vec()).unwrap();
+        store
+            .apply_batch(
+                vec![("c".to_owned(), b"secret c".to_vec())],
+                vec!["b".to_owned()],
+            )
+            .unwrap();
+        assert_eq!(entries(&store).len(), 6);
+        assert!(in_log(&store, b"secret a"));
+
+        // obsolete changes are dropped on open
+        drop(store);
+        let store = LogStore::open(&path, get_test_password()).unwrap();
+        assert!(!in_log(&store, b"secret a"));
+        assert!(!in_log(&store, b"secret b"));
+        assert!(!in_log(&store, b"new b"));
+        assert_eq!(entries(&store).len(), 2);
+        assert_eq!(store.get("a").unwrap(), None);
+        assert_eq!(store.get("b").unwrap(), None);
+        assert_eq!(store.get("c").unwrap(), Some(b"secret c".to_vec()));
+
+        // the compacted log is still appended, and compacted once too many of its changes are obsolete
+        for i in 0..COMPACTION_THRESHOLD + 2 {
+            store.insert("d", i.to_be_bytes().to_vec()).unwrap();
+        }
+        assert_eq!(entries(&store).len(), 2);
+        drop(store);
+        let store = LogStore::open(&path, get_test_password()).unwrap();
+        assert_eq!(store.get("c").unwrap(), Some(b"secret c".to_vec()));
+        assert_eq!(
+            store.get("d").unwrap(),
+            Some((COMPACTION_THRESHOLD + 1).to_be_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn test_create() {
+        // an empty file is not a log yet, e.g. if it was created by an earlier release that crashed
+        let path = testdir!().join("kv.log");
+        File::create(&path).unwrap();
+        let store = LogStore::open(&path, get_test_password()).unwrap();
+        store.insert("a", b"a".to_vec()).unwrap();
+        drop(store);
+
+        // leftovers of an interrupted replacement are ignored
+        fs::write(path.with_extension(NEW_LOG_EXTENSION), MAGIC).unwrap();
+        let store = LogStore::open(&path, get_test_password()).unwrap();
+        assert_eq!(store.get("a").unwrap(), Some(b"a".to_vec()));
+        assert_eq!(entries(&store).len(), 2);
+    }
+}
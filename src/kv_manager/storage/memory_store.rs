@@ -0,0 +1,72 @@
+//! [Storage] of [StorageBackend::Memory](super::StorageBackend::Memory): a map in memory.
+//! Values are not encrypted and are lost when the store is dropped.
+
+use std::{cell::RefCell, collections::BTreeMap};
+
+use super::Storage;
+use crate::encrypted_sled::Password;
+use crate::kv_manager::error::{InnerKvError::LogicalErr, InnerKvResult};
+
+/// A map of keys to values in memory.
+/// The kvstore owns its storage, so a [RefCell] is enough to mutate the map from `&self`.
+#[derive(Default)]
+pub(super) struct MemoryStore {
+    map: RefCell<BTreeMap<String, Vec<u8>>>,
+}
+
+impl Storage for MemoryStore {
+    fn get(&self, key: &str) -> InnerKvResult<Option<Vec<u8>>> {
+        Ok(self.map.borrow().get(key).cloned())
+    }
+
+    fn contains_key(&self, key: &str) -> InnerKvResult<bool> {
+        Ok(self.map.borrow().contains_key(key))
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> InnerKvResult<()> {
+        self.map.borrow_mut().insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> InnerKvResult<()> {
+        self.map.borrow_mut().remove(key);
+        Ok(())
+    }
+
+    fn apply_batch(
+        &self,
+        inserts: Vec<(String, Vec<u8>)>,
+        removes: Vec<String>,
+    ) -> InnerKvResult<()> {
+        let mut map = self.map.borrow_mut();
+        map.extend(inserts);
+        for key in removes {
+            map.remove(&key);
+        }
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> InnerKvResult<Vec<(String, Vec<u8>)>> {
+        Ok(self
+            .map
+            .borrow()
+            .range(prefix.to_owned()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn flush(&self) -> InnerKvResult<()> {
+        Ok(())
+    }
+
+    fn purge(&mut self, key: &str) -> InnerKvResult<()> {
+        self.remove(key)
+    }
+
+    fn change_password(&mut self, _password: Password) -> InnerKvResult<()> {
+        Err(LogicalErr(
+            "memory storage is not encrypted with a password".to_string(),
+        ))
+    }
+}
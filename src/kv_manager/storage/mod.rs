@@ -0,0 +1,78 @@
+//! Storage backends of the kvstore. A [Storage] is a map of string keys to byte values;
+//! the operations of the kvstore, e.g. reservations, are implemented on top of it in [super::bindings].
+//!
+//! The following [StorageBackend]s are available:
+//!     [StorageBackend::Sled]: an [crate::encrypted_sled::Db] on disk. This is the default.
+//!     [StorageBackend::Memory]: a map in memory that is lost when tofnd exits. Intended for tests and embedders.
+//!     [StorageBackend::Log]: a single file on disk with an append-only log of encrypted changes.
+
+use std::path::Path;
+
+use crate::encrypted_sled::Password;
+
+use super::error::InnerKvResult;
+
+mod log_store;
+mod memory_store;
+mod sled_store;
+
+/// extension of the file of [StorageBackend::Log]
+const LOG_EXTENSION: &str = "log";
+
+/// Storage of the values of a kvstore.
+/// All changes of a single call are applied atomically.
+pub(super) trait Storage: Send {
+    /// Get the value of `key`, if it exists
+    fn get(&self, key: &str) -> InnerKvResult<Option<Vec<u8>>>;
+
+    /// Returns `true` if the storage contains a value for `key`
+    fn contains_key(&self, key: &str) -> InnerKvResult<bool>;
+
+    /// Insert `value` to `key`, overwriting any previous value
+    fn insert(&self, key: &str, value: Vec<u8>) -> InnerKvResult<()>;
+
+    /// Delete the value of `key`, if it exists
+    fn remove(&self, key: &str) -> InnerKvResult<()>;
+
+    /// Insert the `inserts` and delete the `removes`; either all changes are applied or none of them is
+    fn apply_batch(
+        &self,
+        inserts: Vec<(String, Vec<u8>)>,
+        removes: Vec<String>,
+    ) -> InnerKvResult<()>;
+
+    /// Get all keys that start with `prefix` along with their values, in lexicographic order of keys
+    fn scan_prefix(&self, prefix: &str) -> InnerKvResult<Vec<(String, Vec<u8>)>>;
+
+    /// Persist all changes
+    fn flush(&self) -> InnerKvResult<()>;
+
+    /// Delete the value of `key`, if it exists, and rewrite the storage, so that the value can't be recovered from disk
+    fn purge(&mut self, key: &str) -> InnerKvResult<()>;
+
+    /// Re-encrypt the storage under `password`, atomically; a crash leaves either the old or the new password in effect
+    fn change_password(&mut self, password: Password) -> InnerKvResult<()>;
+}
+
+/// Specifies which [Storage] is used by the kvstore
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageBackend {
+    Sled,
+    Memory,
+    Log,
+}
+
+impl StorageBackend {
+    /// Open the storage at `path`, or create a new one if it does not exist.
+    /// [StorageBackend::Log] appends [LOG_EXTENSION] to `path`; [StorageBackend::Memory] ignores it.
+    pub(super) fn open(&self, path: &Path, password: Password) -> InnerKvResult<Box<dyn Storage>> {
+        Ok(match self {
+            Self::Sled => Box::new(sled_store::open(path, password)?),
+            Self::Memory => Box::new(memory_store::MemoryStore::default()),
+            Self::Log => Box::new(log_store::LogStore::open(
+                &path.with_extension(LOG_EXTENSION),
+                password,
+            )?),
+        })
+    }
+}
@@ -0,0 +1,83 @@
+//! [Storage] of [StorageBackend::Sled](super::StorageBackend::Sled): an [encrypted_sled::Db] on disk.
+
+use std::path::Path;
+
+use crate::encrypted_sled::{self, Password};
+
+use super::Storage;
+use crate::kv_manager::error::{InnerKvError::LogicalErr, InnerKvResult};
+
+// logging
+use tracing::info;
+
+/// Returns the db at `path`, or creates a new if such DB does not exist
+pub(super) fn open(path: &Path, password: Password) -> encrypted_sled::Result<encrypted_sled::Db> {
+    // create/open DB
+    info!("START: decrypt kvstore");
+    let kv = encrypted_sled::Db::open(path, password)?;
+    info!("DONE: decrypt kvstore");
+
+    // log whether the DB was newly created or not
+    if kv.was_recovered() {
+        info!("kv_manager found existing db [{}]", path.display());
+    } else {
+        info!(
+            "kv_manager cannot open existing db [{}]. creating new db",
+            path.display()
+        );
+    }
+    Ok(kv)
+}
+
+impl Storage for encrypted_sled::Db {
+    fn get(&self, key: &str) -> InnerKvResult<Option<Vec<u8>>> {
+        Ok(encrypted_sled::Db::get(self, key)?.map(|value| value.to_vec()))
+    }
+
+    fn contains_key(&self, key: &str) -> InnerKvResult<bool> {
+        Ok(encrypted_sled::Db::contains_key(self, key)?)
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> InnerKvResult<()> {
+        encrypted_sled::Db::insert(self, key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> InnerKvResult<()> {
+        encrypted_sled::Db::remove(self, key)?;
+        Ok(())
+    }
+
+    fn apply_batch(
+        &self,
+        inserts: Vec<(String, Vec<u8>)>,
+        removes: Vec<String>,
+    ) -> InnerKvResult<()> {
+        Ok(encrypted_sled::Db::apply_batch(self, inserts, removes)?)
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> InnerKvResult<Vec<(String, Vec<u8>)>> {
+        encrypted_sled::Db::scan_prefix(self, prefix)
+            .map(|entry| -> InnerKvResult<(String, Vec<u8>)> {
+                let (key, value) = entry?;
+                let key = String::from_utf8(key.to_vec()).map_err(|err| {
+                    LogicalErr(format!("found non utf-8 key in kv store: {}", err))
+                })?;
+                Ok((key, value.to_vec()))
+            })
+            .collect()
+    }
+
+    fn flush(&self) -> InnerKvResult<()> {
+        encrypted_sled::Db::flush(self)?;
+        Ok(())
+    }
+
+    fn purge(&mut self, key: &str) -> InnerKvResult<()> {
+        Ok(encrypted_sled::Db::purge(self, key)?)
+    }
+
+    fn change_password(&mut self, password: Password) -> InnerKvResult<()> {
+        Ok(encrypted_sled::Db::change_password(self, password)?)
+    }
+}
@@ -1,8 +1,13 @@
-//! [sled_bindings] tests
+//! [bindings] tests
 
 use super::{
-    error::InnerKvError::LogicalErr,
-    sled_bindings::{handle_exists, handle_get, handle_put, handle_reserve},
+    bindings::{
+        handle_change_password, handle_delete, handle_exists, handle_get, handle_keys,
+        handle_purge, handle_put, handle_put_all, handle_release, handle_rename, handle_replace,
+        handle_reserve, handle_revert,
+    },
+    error::{InnerKvError::LogicalErr, LogError},
+    storage::StorageBackend,
     types::{KeyReservation, DEFAULT_RESERVE},
 };
 use crate::encrypted_sled;
@@ -121,6 +126,80 @@ fn put_failure_put_twice() {
     clean_up(kv_name.to_str().unwrap(), kv);
 }
 
+#[test]
+fn test_put_all() {
+    let kv_name = testdir!();
+    let kv = open_with_test_password(&kv_name).unwrap();
+
+    let reservation_1 = handle_reserve(&kv, "key_1".to_string()).unwrap();
+    let reservation_2 = handle_reserve(&kv, "key_2".to_string()).unwrap();
+
+    // nothing is inserted if one of the keys is not reserved
+    let err = handle_put_all(
+        &kv,
+        vec![
+            (reservation_1, "value_1".to_string()),
+            (
+                KeyReservation {
+                    key: "other".to_string(),
+                },
+                "other".to_string(),
+            ),
+        ],
+    )
+    .unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+    assert_eq!(
+        kv.get("key_1").unwrap(),
+        Some(sled::IVec::from(DEFAULT_RESERVE))
+    );
+    assert!(!kv.contains_key("other").unwrap());
+
+    // all values are inserted otherwise
+    let reservation_1 = KeyReservation {
+        key: "key_1".to_string(),
+    };
+    handle_put_all(
+        &kv,
+        vec![
+            (reservation_1, "value_1".to_string()),
+            (reservation_2, "value_2".to_string()),
+        ],
+    )
+    .unwrap();
+    let value: String = handle_get(&kv, "key_1".to_string()).unwrap();
+    assert_eq!(value, "value_1");
+    let value: String = handle_get(&kv, "key_2".to_string()).unwrap();
+    assert_eq!(value, "value_2");
+
+    clean_up(kv_name.to_str().unwrap(), kv);
+}
+
+#[test]
+fn test_release() {
+    let kv_name = testdir!();
+    let kv = open_with_test_password(&kv_name).unwrap();
+
+    handle_reserve(&kv, "reserved".to_string()).unwrap();
+    let reservation = handle_reserve(&kv, "taken".to_string()).unwrap();
+    handle_put(&kv, reservation, "value".to_string()).unwrap();
+
+    // only keys that are still reserved are released
+    handle_release(
+        &kv,
+        vec![
+            "reserved".to_string(),
+            "taken".to_string(),
+            "unknown".to_string(),
+        ],
+    );
+    assert!(!kv.contains_key("reserved").unwrap());
+    let value: String = handle_get(&kv, "taken".to_string()).unwrap();
+    assert_eq!(value, "value");
+
+    clean_up(kv_name.to_str().unwrap(), kv);
+}
+
 #[test]
 fn get_success() {
     let kv_name = testdir!();
@@ -187,3 +266,263 @@ fn test_exists() {
     assert!(exists.is_ok());
     assert!(!exists.unwrap()); // check that the result is false
 }
+
+#[test]
+fn test_keys() {
+    let kv_name = testdir!();
+    let kv = open_with_test_password(&kv_name).unwrap();
+
+    // no keys yet
+    assert!(handle_keys(&kv, "").unwrap().is_empty());
+
+    // put two keys and reserve a third one
+    for key in ["key_b", "key_a"].iter() {
+        let reservation = handle_reserve(&kv, key.to_string()).unwrap();
+        handle_put(&kv, reservation, "value".to_string()).unwrap();
+    }
+    handle_reserve(&kv, "key_c".to_string()).unwrap();
+    let reservation = handle_reserve(&kv, "other".to_string()).unwrap();
+    handle_put(&kv, reservation, "value".to_string()).unwrap();
+
+    // keys are sorted and reserved keys are skipped
+    assert_eq!(handle_keys(&kv, "key").unwrap(), vec!["key_a", "key_b"]);
+    assert_eq!(
+        handle_keys(&kv, "").unwrap(),
+        vec!["key_a", "key_b", "other"]
+    );
+
+    clean_up(kv_name.to_str().unwrap(), kv);
+}
+
+#[test]
+fn test_rename() {
+    let kv_name = testdir!();
+    let kv = open_with_test_password(&kv_name).unwrap();
+
+    let reservation = handle_reserve(&kv, "key".to_string()).unwrap();
+    handle_put(&kv, reservation, "value".to_string()).unwrap();
+
+    // rename moves the value
+    handle_rename(&kv, "key".to_string(), "new_key".to_string()).unwrap();
+    assert!(!handle_exists(&kv, "key").unwrap());
+    let value: String = handle_get(&kv, "new_key".to_string()).unwrap();
+    assert_eq!(value, "value");
+
+    // can't rename a missing key
+    let err = handle_rename(&kv, "key".to_string(), "other".to_string()).unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+
+    // can't rename to an existing or reserved key
+    handle_reserve(&kv, "key".to_string()).unwrap();
+    let err = handle_rename(&kv, "new_key".to_string(), "key".to_string()).unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+
+    // can't rename a reserved key
+    let err = handle_rename(&kv, "key".to_string(), "other".to_string()).unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+
+    clean_up(kv_name.to_str().unwrap(), kv);
+}
+
+#[test]
+fn test_purge() {
+    let kv_name = testdir!();
+    let mut kv = open_with_test_password(&kv_name).unwrap();
+
+    let reservation = handle_reserve(&kv, "key".to_string()).unwrap();
+    handle_put(&kv, reservation, "value".to_string()).unwrap();
+    handle_purge(&mut kv, "key".to_string()).unwrap();
+    assert!(!handle_exists(&kv, "key").unwrap());
+
+    // purging a missing key is a no-op
+    handle_purge(&mut kv, "key".to_string()).unwrap();
+
+    // can't purge a reserved key
+    handle_reserve(&kv, "key".to_string()).unwrap();
+    let err = handle_purge(&mut kv, "key".to_string()).unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+
+    clean_up(kv_name.to_str().unwrap(), kv);
+}
+
+#[test]
+fn test_replace() {
+    let kv_name = testdir!();
+    let kv = open_with_test_password(&kv_name).unwrap();
+
+    let reservation = handle_reserve(&kv, "key".to_string()).unwrap();
+    handle_put(&kv, reservation, "value".to_string()).unwrap();
+
+    // replace archives the previous value
+    handle_replace(
+        &kv,
+        "key".to_string(),
+        "archive".to_string(),
+        "new value".to_string(),
+    )
+    .unwrap();
+    let value: String = handle_get(&kv, "key".to_string()).unwrap();
+    assert_eq!(value, "new value");
+    let value: String = handle_get(&kv, "archive".to_string()).unwrap();
+    assert_eq!(value, "value");
+
+    // can't overwrite an existing archive
+    let err = handle_replace(
+        &kv,
+        "key".to_string(),
+        "archive".to_string(),
+        "newer value".to_string(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+
+    // revert brings the archived value back
+    handle_revert(&kv, "key".to_string(), "archive".to_string()).unwrap();
+    let value: String = handle_get(&kv, "key".to_string()).unwrap();
+    assert_eq!(value, "value");
+    assert!(!handle_exists(&kv, "archive").unwrap());
+
+    // can't revert without an archive, or replace a missing or reserved key
+    let err = handle_revert(&kv, "key".to_string(), "archive".to_string()).unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+    let err = handle_replace(
+        &kv,
+        "other".to_string(),
+        "archive".to_string(),
+        "value".to_string(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+    handle_reserve(&kv, "reserved".to_string()).unwrap();
+    let err = handle_replace(
+        &kv,
+        "reserved".to_string(),
+        "archive".to_string(),
+        "value".to_string(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, LogicalErr(_)));
+
+    clean_up(kv_name.to_str().unwrap(), kv);
+}
+
+#[test]
+fn test_storage_backends() {
+    for backend in [
+        StorageBackend::Sled,
+        StorageBackend::Memory,
+        StorageBackend::Log,
+    ] {
+        let kv_name = testdir!().join("kv");
+        let mut storage = backend
+            .open(&kv_name, encrypted_sled::get_test_password())
+            .unwrap();
+        let kv = storage.as_ref();
+
+        // reservations behave the same on all backends
+        let reservation = handle_reserve(kv, "key_a".to_string()).unwrap();
+        assert!(matches!(
+            handle_reserve(kv, "key_a".to_string()),
+            Err(LogicalErr(_))
+        ));
+        handle_put(kv, reservation, "value_a".to_string()).unwrap();
+        let reservation = handle_reserve(kv, "key_b".to_string()).unwrap();
+        handle_put_all(kv, vec![(reservation, "value_b".to_string())]).unwrap();
+        handle_reserve(kv, "key_c".to_string()).unwrap();
+
+        let value: String = handle_get(kv, "key_a".to_string()).unwrap();
+        assert_eq!(value, "value_a");
+        assert!(handle_exists(kv, "key_c").unwrap());
+        assert_eq!(handle_keys(kv, "key").unwrap(), vec!["key_a", "key_b"]);
+
+        handle_replace(
+            kv,
+            "key_a".to_string(),
+            "archive".to_string(),
+            "new_value".to_string(),
+        )
+        .unwrap();
+        handle_revert(kv, "key_a".to_string(), "archive".to_string()).unwrap();
+        let value: String = handle_get(kv, "key_a".to_string()).unwrap();
+        assert_eq!(value, "value_a");
+
+        handle_rename(kv, "key_b".to_string(), "other".to_string()).unwrap();
+        handle_purge(storage.as_mut(), "other".to_string()).unwrap();
+        assert_eq!(handle_keys(storage.as_ref(), "").unwrap(), vec!["key_a"]);
+    }
+}
+
+#[test]
+fn test_log_storage() {
+    let kv_name = testdir!().join("kv");
+    let log_name = kv_name.with_extension("log");
+    let open = |password| StorageBackend::Log.open(&kv_name, password);
+
+    let kv = open(encrypted_sled::get_test_password()).unwrap();
+    let reservation = handle_reserve(kv.as_ref(), "key".to_string()).unwrap();
+    handle_put(kv.as_ref(), reservation, "value".to_string()).unwrap();
+    handle_reserve(kv.as_ref(), "reserved".to_string()).unwrap();
+    drop(kv);
+
+    // the log can't be opened with another password
+    assert!(matches!(
+        open(encrypted_sled::Password::from("wrong password")),
+        Err(super::error::InnerKvError::LogErr(LogError::Encryption(
+            encrypted_sled::Error::WrongPassword
+        )))
+    ));
+
+    // an entry whose append was interrupted is dropped
+    let log_len = std::fs::metadata(&log_name).unwrap().len();
+    let mut log = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&log_name)
+        .unwrap();
+    std::io::Write::write_all(&mut log, &[0, 0, 1, 0, 42]).unwrap();
+    drop(log);
+
+    // all changes are replayed on open
+    let kv = open(encrypted_sled::get_test_password()).unwrap();
+    assert_eq!(std::fs::metadata(&log_name).unwrap().len(), log_len);
+    let value: String = handle_get(kv.as_ref(), "key".to_string()).unwrap();
+    assert_eq!(value, "value");
+    assert!(handle_exists(kv.as_ref(), "reserved").unwrap());
+    assert_eq!(handle_keys(kv.as_ref(), "").unwrap(), vec!["key"]);
+
+    // the log is still appended after the dropped entry
+    handle_delete(kv.as_ref(), "key".to_string()).unwrap();
+    drop(kv);
+    let kv = open(encrypted_sled::get_test_password()).unwrap();
+    assert!(!handle_exists(kv.as_ref(), "key").unwrap());
+}
+
+#[test]
+fn test_log_storage_change_password() {
+    let kv_name = testdir!().join("kv");
+    let open = |password: &str| {
+        StorageBackend::Log.open(&kv_name, encrypted_sled::Password::from(password))
+    };
+
+    let mut kv = open("old password").unwrap();
+    let reservation = handle_reserve(kv.as_ref(), "key".to_string()).unwrap();
+    handle_put(kv.as_ref(), reservation, "value".to_string()).unwrap();
+    handle_change_password(kv.as_mut(), encrypted_sled::Password::from("new password")).unwrap();
+
+    // the store is still appended after the password change
+    let reservation = handle_reserve(kv.as_ref(), "other".to_string()).unwrap();
+    handle_put(kv.as_ref(), reservation, "other_value".to_string()).unwrap();
+    drop(kv);
+
+    assert!(matches!(
+        open("old password"),
+        Err(super::error::InnerKvError::LogErr(LogError::Encryption(
+            encrypted_sled::Error::WrongPassword
+        )))
+    ));
+
+    let kv = open("new password").unwrap();
+    let value: String = handle_get(kv.as_ref(), "key".to_string()).unwrap();
+    assert_eq!(value, "value");
+    let value: String = handle_get(kv.as_ref(), "other".to_string()).unwrap();
+    assert_eq!(value, "other_value");
+}
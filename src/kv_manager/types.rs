@@ -2,6 +2,8 @@
 
 use std::fmt::Debug;
 
+use crate::encrypted_sled::Password;
+
 // default KV store names
 pub const DEFAULT_KV_NAME: &str = "kv";
 
@@ -41,6 +43,10 @@ pub(super) enum Command<V> {
         value: V,
         resp: Responder<()>,
     },
+    PutAll {
+        values: Vec<(KeyReservation, V)>,
+        resp: Responder<()>,
+    },
     Get {
         key: String, // TODO should be &str except lifetimes...
         resp: Responder<V>,
@@ -53,4 +59,32 @@ pub(super) enum Command<V> {
         key: String,
         resp: Responder<()>,
     },
+    Keys {
+        prefix: String,
+        resp: Responder<Vec<String>>,
+    },
+    Rename {
+        from: String,
+        to: String,
+        resp: Responder<()>,
+    },
+    Purge {
+        key: String,
+        resp: Responder<()>,
+    },
+    Replace {
+        key: String,
+        archive_key: String,
+        value: V,
+        resp: Responder<()>,
+    },
+    Revert {
+        key: String,
+        archive_key: String,
+        resp: Responder<()>,
+    },
+    ChangePassword {
+        password: Password,
+        resp: Responder<()>,
+    },
 }
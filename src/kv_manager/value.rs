@@ -1,4 +1,9 @@
-use std::{convert::TryFrom, path::PathBuf};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 use tofn::sdk::api::{deserialize, serialize};
 
 use crate::{
@@ -10,6 +15,7 @@ use crate::{
 use super::{
     error::{InnerKvError, KvResult},
     kv::Kv,
+    storage::StorageBackend,
 };
 
 /// Kv manager for grpc services
@@ -17,13 +23,19 @@ use super::{
 pub struct KvManager {
     kv: Kv<KvValue>,
     io: FileIo,
+    seed_fingerprints: SeedFingerprints,
 }
 
+/// Fingerprints of mnemonic seeds, indexed by a digest of the entropy of the mnemonic.
+/// Deriving a seed is expensive, so the fingerprint of each mnemonic is only computed once.
+pub type SeedFingerprints = Arc<Mutex<HashMap<[u8; 32], Vec<u8>>>>;
+
 impl KvManager {
-    pub fn new(root: PathBuf, password: Password) -> KvResult<Self> {
+    pub fn new(root: PathBuf, password: Password, storage: StorageBackend) -> KvResult<Self> {
         Ok(KvManager {
-            kv: Kv::<KvValue>::new(root.clone(), password)?,
+            kv: Kv::<KvValue>::new(root.clone(), password, storage)?,
             io: FileIo::new(root),
+            seed_fingerprints: SeedFingerprints::default(),
         })
     }
     pub fn kv(&self) -> &Kv<KvValue> {
@@ -32,6 +44,9 @@ impl KvManager {
     pub fn io(&self) -> &FileIo {
         &self.io
     }
+    pub fn seed_fingerprints(&self) -> &SeedFingerprints {
+        &self.seed_fingerprints
+    }
 }
 
 /// Value type stored in the kv-store
@@ -41,7 +56,7 @@ type KvValue = Vec<u8>;
 impl TryFrom<KvValue> for PartyInfo {
     type Error = InnerKvError;
     fn try_from(v: KvValue) -> Result<Self, Self::Error> {
-        deserialize(&v).ok_or(InnerKvError::DeserializationErr)
+        PartyInfo::decode(&v).ok_or(InnerKvError::DeserializationErr)
     }
 }
 
@@ -49,7 +64,7 @@ impl TryFrom<KvValue> for PartyInfo {
 impl TryFrom<PartyInfo> for KvValue {
     type Error = InnerKvError;
     fn try_from(v: PartyInfo) -> Result<Self, Self::Error> {
-        serialize(&v).map_err(|_| InnerKvError::SerializationErr)
+        v.encode().ok_or(InnerKvError::SerializationErr)
     }
 }
 
@@ -1,5 +1,5 @@
 use std::net::SocketAddr;
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, sync::watch};
 use tokio_stream::wrappers::TcpListenerStream;
 
 mod encrypted_sled;
@@ -7,7 +7,6 @@ mod gg20;
 mod kv_manager;
 mod mnemonic;
 
-
 // gather logs; need to set RUST_LOG=info
 use tracing::{info, span, Level};
 
@@ -55,6 +54,7 @@ async fn main() -> TofndResult<()> {
     set_up_logs(); // can't print any logs until they're set up
     let cfg = parse_args()?;
     let socket_address = addr(&cfg.ip, cfg.port)?;
+    let peer_socket_address = addr(&cfg.peer_ip, cfg.peer_port)?;
 
     // immediately read an encryption password from stdin
     let password = cfg.password_method.execute()?;
@@ -72,11 +72,14 @@ async fn main() -> TofndResult<()> {
     let cmd = cfg.mnemonic_cmd.clone();
 
     // this step takes a long time due to password-based decryption
-    let kv_manager = KvManager::new(cfg.tofnd_path.clone(), password)?
+    let kv_manager = KvManager::new(cfg.tofnd_path.clone(), password, cfg.storage)?
         .handle_mnemonic(&cfg.mnemonic_cmd)
         .await?;
 
-    let gg20_service = gg20::service::new_service(cfg, kv_manager.clone());
+    // sessions of the direct transport are shared by the gg20 and peer services
+    let peer_sessions = gg20::peer::PeerSessions::default();
+    let gg20_service = gg20::service::new_service(cfg, kv_manager.clone(), peer_sessions.clone());
+    let peer_service = gg20::peer::new_service(peer_sessions);
     // let multisig_service = multisig::service::new_service(kv_manager);
 
     if cmd.exit_after_cmd() {
@@ -85,7 +88,8 @@ async fn main() -> TofndResult<()> {
     }
 
     let gg20_service = proto::gg20_server::Gg20Server::new(gg20_service);
-   // let multisig_service = proto::multisig_server::MultisigServer::new(multisig_service);
+    let peer_service = proto::peer_server::PeerServer::new(peer_service);
+    // let multisig_service = proto::multisig_server::MultisigServer::new(multisig_service);
 
     let incoming = TcpListener::bind(socket_address).await?;
     info!(
@@ -93,11 +97,31 @@ async fn main() -> TofndResult<()> {
         incoming.local_addr()?
     );
 
-    tonic::transport::Server::builder()
+    // peers get their own listener, so that they can't reach the gg20 service
+    let peer_incoming = TcpListener::bind(peer_socket_address).await?;
+    info!("tofnd peer listen addr {:?}", peer_incoming.local_addr()?);
+
+    // both servers shut down on the same signal
+    let (shutdown_sender, shutdown_receiver) = watch::channel(());
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = shutdown_sender.send(());
+    });
+
+    let server = tonic::transport::Server::builder()
         .add_service(gg20_service)
         // .add_service(multisig_service)
-        .serve_with_incoming_shutdown(TcpListenerStream::new(incoming), shutdown_signal())
-        .await?;
+        .serve_with_incoming_shutdown(
+            TcpListenerStream::new(incoming),
+            shutdown(shutdown_receiver.clone()),
+        );
+    let peer_server = tonic::transport::Server::builder()
+        .add_service(peer_service)
+        .serve_with_incoming_shutdown(
+            TcpListenerStream::new(peer_incoming),
+            shutdown(shutdown_receiver),
+        );
+    tokio::try_join!(server, peer_server)?;
 
     Ok(())
 }
@@ -119,4 +143,7 @@ async fn shutdown_signal() {
     info!("tofnd shutdown signal received");
 }
 
-
+// resolves once the shutdown signal is received, or if it can no longer be received
+async fn shutdown(mut receiver: watch::Receiver<()>) {
+    let _ = receiver.changed().await;
+}
@@ -7,6 +7,7 @@ use super::{
     },
 };
 use crate::{
+    encrypted_sled::read_new_password,
     gg20::types::{Entropy, Password}, // TODO: move from gg20::types
     kv_manager::{
         error::{InnerKvError, KvError},
@@ -19,6 +20,7 @@ use tofn::{
 };
 
 use rpassword::read_password;
+use sha2::{Digest, Sha256, Sha512};
 use std::convert::TryInto;
 use tracing::{error, info};
 
@@ -28,6 +30,24 @@ const MNEMONIC_KEY: &str = "mnemonic";
 // key to store mnemonic count
 const MNEMONIC_COUNT_KEY: &str = "mnemonic_count";
 
+// domain of mnemonic fingerprints
+const FINGERPRINT_DOMAIN: &[u8] = b"tofnd mnemonic fingerprint";
+
+/// Returns `true` if `key` is used by the kv-store to hold mnemonic data, i.e.
+/// `mnemonic`, `mnemonic_<n>` for rotated mnemonics, or `mnemonic_count`.
+pub fn is_mnemonic_key(key: &str) -> bool {
+    if key == MNEMONIC_KEY || key == MNEMONIC_COUNT_KEY {
+        return true;
+    }
+    match key
+        .strip_prefix(MNEMONIC_KEY)
+        .and_then(|s| s.strip_prefix('_'))
+    {
+        Some(index) => !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
 // A user may decide to protect their mnemonic with a passphrase.
 // We pass an empty password since the mnemonic has sufficient entropy and will be backed up.
 // https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki#from-mnemonic-to-seed
@@ -40,6 +60,7 @@ pub enum Cmd {
     Import,
     Export,
     Rotate,
+    ChangePassword,
 }
 
 impl Cmd {
@@ -50,12 +71,13 @@ impl Cmd {
             "import" => Self::Import,
             "export" => Self::Export,
             "rotate" => Self::Rotate,
+            "change-password" => Self::ChangePassword,
             _ => return Err(WrongCommand(cmd_str.to_string())),
         };
         Ok(cmd)
     }
     /// On [Cmd::Existing], continue tofnd.
-    /// On [Cmd::Create], [Cmd::Import] or [Cmd::Export], exit tofnd.
+    /// On [Cmd::Create], [Cmd::Import], [Cmd::Export], [Cmd::Rotate] or [Cmd::ChangePassword], exit tofnd.
     pub fn exit_after_cmd(&self) -> bool {
         match &self {
             Cmd::Existing => false,
@@ -63,6 +85,7 @@ impl Cmd {
             Cmd::Import => true,
             Cmd::Export => true,
             Cmd::Rotate => true,
+            Cmd::ChangePassword => true,
         }
     }
 }
@@ -90,6 +113,95 @@ impl KvManager {
         )
     }
 
+    /// Derive 64 bytes of key material for `domain` from the latest mnemonic seed.
+    /// Used for tofnd's own keys, which must not collide with the keys that tofn derives from the seed.
+    pub async fn derive_key(&self, domain: &[u8]) -> SeedResult<[u8; 64]> {
+        self.derive_key_under(MNEMONIC_KEY, domain).await
+    }
+
+    /// Derive 64 bytes of key material for `domain` from the mnemonic seed with `fingerprint`, or from the latest one if `fingerprint` is empty.
+    /// Returns `None` if the mnemonic is no longer in the kv-store.
+    pub async fn derive_key_of(
+        &self,
+        fingerprint: &[u8],
+        domain: &[u8],
+    ) -> SeedResult<Option<[u8; 64]>> {
+        let key = match fingerprint.is_empty() {
+            true => MNEMONIC_KEY.to_owned(),
+            false => match self.find_seed_key(fingerprint).await? {
+                Some(key) => key,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(self.derive_key_under(&key, domain).await?))
+    }
+
+    /// Derive 64 bytes of key material for `domain` from the mnemonic seed under key
+    async fn derive_key_under(&self, key: &str, domain: &[u8]) -> SeedResult<[u8; 64]> {
+        let mnemonic = self
+            .kv()
+            .get(key)
+            .await?
+            .try_into()
+            .map_err(KvError::GetErr)?;
+        let seed = bip39_seed(mnemonic, Password(MNEMONIC_PASSWORD.to_owned()))?;
+
+        let mut hasher = Sha512::new();
+        hasher.update(domain);
+        hasher.update(seed.as_bytes());
+        Ok(hasher.finalize().into())
+    }
+
+    /// Get the fingerprint of the latest mnemonic seed.
+    /// The fingerprint identifies a mnemonic without revealing it, so it can be stored along with the keys that the mnemonic generated.
+    pub async fn seed_fingerprint(&self) -> SeedResult<Vec<u8>> {
+        self.get_seed_fingerprint(MNEMONIC_KEY).await
+    }
+
+    /// Get the fingerprint of the mnemonic seed under key.
+    /// Fingerprints are cached by the digest of the mnemonic's entropy, since seed derivation is expensive.
+    async fn get_seed_fingerprint(&self, key: &str) -> SeedResult<Vec<u8>> {
+        let mnemonic: Entropy = self
+            .kv()
+            .get(key)
+            .await?
+            .try_into()
+            .map_err(KvError::GetErr)?;
+        let digest: [u8; 32] = Sha256::digest(&mnemonic.0).into();
+        if let Some(fingerprint) = self.seed_fingerprints().lock().unwrap().get(&digest) {
+            return Ok(fingerprint.clone());
+        }
+
+        let seed = bip39_seed(mnemonic, Password(MNEMONIC_PASSWORD.to_owned()))?;
+        let mut hasher = Sha256::new();
+        hasher.update(FINGERPRINT_DOMAIN);
+        hasher.update(seed.as_bytes());
+        let fingerprint = hasher.finalize().to_vec();
+
+        self.seed_fingerprints()
+            .lock()
+            .unwrap()
+            .insert(digest, fingerprint.clone());
+        Ok(fingerprint)
+    }
+
+    /// Find the key of the mnemonic with `fingerprint`, among the latest and the rotated mnemonics.
+    /// Returns `None` if the mnemonic is no longer in the kv-store, including if there is no mnemonic at all.
+    pub async fn find_seed_key(&self, fingerprint: &[u8]) -> SeedResult<Option<String>> {
+        if self.seed_count().await? == 0 {
+            return Ok(None);
+        }
+        for key in self.seed_key_iter().await? {
+            if !self.kv().exists(&key).await? {
+                continue;
+            }
+            if self.get_seed_fingerprint(&key).await? == fingerprint {
+                return Ok(Some(key));
+            }
+        }
+        Ok(None)
+    }
+
     pub async fn seed_key_iter(&self) -> InnerMnemonicResult<Vec<String>> {
         let count = self.seed_count().await?;
         if count == 0 {
@@ -119,6 +231,10 @@ impl KvManager {
             Cmd::Import => self.handle_import().await.map_err(ImportErr)?,
             Cmd::Export => self.handle_export().await.map_err(ExportErr)?,
             Cmd::Rotate => self.handle_rotate().await.map_err(RotateErr)?,
+            Cmd::ChangePassword => self
+                .handle_change_password()
+                .await
+                .map_err(ChangePasswordErr)?,
         };
         Ok(self)
     }
@@ -331,6 +447,21 @@ impl KvManager {
 
         Ok(())
     }
+
+    /// Prompts for a new password and re-encrypts the kv-store under it.
+    /// The old password is verified when the kv-store is opened.
+    async fn handle_change_password(&self) -> InnerMnemonicResult<()> {
+        info!("Changing password");
+        let password = read_new_password().map_err(|e| PasswordErr(e.to_string()))?;
+
+        self.kv().change_password(password).await.map_err(|err| {
+            error!("Cannot re-encrypt kv store: {:?}", err);
+            KvErr(err)
+        })?;
+
+        info!("Password changed. Use the new password to run tofnd.");
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -342,7 +473,7 @@ mod tests {
         encrypted_sled::get_test_password,
         kv_manager::{
             error::{InnerKvError, KvError},
-            KvManager,
+            KvManager, StorageBackend,
         },
         mnemonic::results::{file_io::FileIoError, mnemonic::InnerMnemonicError},
     };
@@ -353,7 +484,75 @@ mod tests {
     // create a service
     fn get_kv_manager(testdir: PathBuf) -> KvManager {
         // create test dirs
-        KvManager::new(testdir, get_test_password()).unwrap()
+        KvManager::new(testdir, get_test_password(), StorageBackend::Sled).unwrap()
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_derive_key() {
+        let testdir = testdir!();
+        let kv = get_kv_manager(testdir);
+        // no mnemonic yet
+        assert!(kv.derive_key(b"domain").await.is_err());
+
+        kv.handle_create().await.unwrap();
+        let key = kv.derive_key(b"domain").await.unwrap();
+        // derivation is deterministic and domain separated
+        assert_eq!(key, kv.derive_key(b"domain").await.unwrap());
+        assert_ne!(key, kv.derive_key(b"other domain").await.unwrap());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_find_seed_key() {
+        let testdir = testdir!();
+        let kv = get_kv_manager(testdir);
+        let path = std::path::Path::new(kv.io().export_path()).to_owned();
+
+        // no mnemonic yet
+        assert_eq!(kv.find_seed_key(b"unknown").await.unwrap(), None);
+
+        kv.handle_create().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let fingerprint = kv.seed_fingerprint().await.unwrap();
+        assert_eq!(kv.seed_fingerprints().lock().unwrap().len(), 1);
+        assert_eq!(
+            kv.find_seed_key(&fingerprint).await.unwrap(),
+            Some(MNEMONIC_KEY.to_owned())
+        );
+
+        // the mnemonic is still found after it is rotated out
+        kv.handle_rotate().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_ne!(fingerprint, kv.seed_fingerprint().await.unwrap());
+        assert_eq!(
+            kv.find_seed_key(&fingerprint).await.unwrap(),
+            Some(format!("{}_1", MNEMONIC_KEY))
+        );
+
+        // unknown mnemonics are not found
+        assert_eq!(kv.find_seed_key(b"unknown").await.unwrap(), None);
+
+        // fingerprints are derived once per mnemonic
+        assert_eq!(kv.seed_fingerprints().lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_is_mnemonic_key() {
+        for key in ["mnemonic", "mnemonic_count", "mnemonic_1", "mnemonic_12"].iter() {
+            assert!(is_mnemonic_key(key));
+        }
+        for key in [
+            "mnemonic_",
+            "mnemonic_1a",
+            "mnemonics",
+            "my_mnemonic",
+            "key",
+        ]
+        .iter()
+        {
+            assert!(!is_mnemonic_key(key));
+        }
     }
 
     #[traced_test]
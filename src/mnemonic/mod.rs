@@ -5,11 +5,12 @@
 //!     [Cmd::Create]: Creates a new mnemonic, inserts it in the kv-store, exports it to a file and exits; Fails if a mnemonic exists.
 //!     [Cmd::Import]: Prompts user to give a new mnemonic, inserts it in the kv-store and exits; Fails if a mnemonic exists or if the provided string is not a valid bip39 mnemonic.
 //!     [Cmd::Export]: Writes the existing mnemonic to a file and exits; Succeeds when there is an existing mnemonic, fails otherwise.
+//!     [Cmd::ChangePassword]: Prompts user to give a new password, re-encrypts the kv-store under it and exits.
 
 mod bip39_bindings;
 mod cmd_handler;
 mod file_io;
 mod results;
 
-pub use cmd_handler::Cmd;
+pub use cmd_handler::{is_mnemonic_key, Cmd};
 pub use file_io::FileIo;
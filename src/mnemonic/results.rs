@@ -64,6 +64,8 @@ pub(super) mod mnemonic {
         ExportErr(InnerMnemonicError),
         #[error("Cannot rotate mnemonic: {0}")]
         RotateErr(InnerMnemonicError),
+        #[error("Cannot change password: {0}")]
+        ChangePasswordErr(InnerMnemonicError),
     }
     pub type MnemonicResult<Success> = Result<Success, MnemonicError>;
     pub type SeedResult<Success> = Result<Success, InnerMnemonicError>;